@@ -0,0 +1,292 @@
+use super::ex::{self, Command};
+use super::keys;
+use crossterm::event::KeyEvent;
+use text_editor::{CaseOp, MovementKey, ScreenPos};
+
+// the lines dd, dj and dk (or yy, yj and yk) take
+#[derive(Debug, Clone, Copy)]
+pub enum Lines {
+    Current,
+    Below,
+    Above,
+}
+
+/* what a built-in key of normal mode does. A key that ends in an argument
+ * like m{a-z} gets it as the key that was typed last */
+#[derive(Debug, Clone)]
+pub enum Normal {
+    Move(MovementKey),
+    // w, b, e, 0 and $
+    Motion,
+    ScreenLine(ScreenPos),
+    // i, a, I, A, o and O
+    Insert(char),
+    Visual,
+    CommandLine,
+    Search(bool),
+    // in the direction of the last search, or against it
+    NextMatch(bool),
+    // forward, whole words only
+    SearchWord(bool, bool),
+    // with spaces and without comment leaders, gJ doesn't
+    Join(bool),
+    ToggleCase,
+    Paste,
+    Delete(Lines),
+    Yank(Lines),
+    AlignLine(ScreenPos),
+    AlignColumn(ScreenPos),
+    ScrollColumns(i32),
+    Case(CaseOp),
+    CaseLine(CaseOp),
+    Tab(bool),
+    Mark,
+    // to the exact position with true
+    Jump(bool),
+    HistoryWindow,
+    // an ex command line
+    Command(&'static str),
+    ScrollPage(i32),
+    ScrollHalfPage(i32),
+    ScrollLines(i32),
+    Increment(i64),
+    Window(char),
+}
+
+/* a built-in key of normal mode, in the notation of keys::parse. `{...}`
+ * stands for a key to choose */
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub keys: &'static str,
+    pub action: Normal,
+    pub description: &'static str,
+}
+
+const fn bind(keys: &'static str, action: Normal, description: &'static str) -> Binding {
+    Binding {
+        keys,
+        action,
+        description,
+    }
+}
+
+/* every built-in key of normal mode but the ones of ex commands, which are
+ * in ex::COMMANDS. handle_normal_mode runs them from here and the which-key
+ * popup and the palette list them */
+pub const NORMAL: [Binding; 84] = [
+    bind("h", Normal::Move(MovementKey::Left), "cursor left"),
+    bind("j", Normal::Move(MovementKey::Down), "cursor down"),
+    bind("k", Normal::Move(MovementKey::Up), "cursor up"),
+    bind("l", Normal::Move(MovementKey::Right), "cursor right"),
+    bind("w", Normal::Motion, "next word"),
+    bind("b", Normal::Motion, "previous word"),
+    bind("e", Normal::Motion, "end of word"),
+    bind("0", Normal::Motion, "start of line"),
+    bind("$", Normal::Motion, "end of line"),
+    bind(
+        "H",
+        Normal::ScreenLine(ScreenPos::Start),
+        "top of the screen",
+    ),
+    bind(
+        "M",
+        Normal::ScreenLine(ScreenPos::Middle),
+        "middle of the screen",
+    ),
+    bind(
+        "L",
+        Normal::ScreenLine(ScreenPos::End),
+        "bottom of the screen",
+    ),
+    bind("i", Normal::Insert('i'), "insert before the cursor"),
+    bind("a", Normal::Insert('a'), "append after the cursor"),
+    bind("I", Normal::Insert('I'), "insert at the first non-blank"),
+    bind("A", Normal::Insert('A'), "append at the end of the line"),
+    bind("o", Normal::Insert('o'), "open a line below"),
+    bind("O", Normal::Insert('O'), "open a line above"),
+    bind("v", Normal::Visual, "visual mode"),
+    bind(":", Normal::CommandLine, "command line"),
+    bind("/", Normal::Search(true), "search forward"),
+    bind("?", Normal::Search(false), "search backward"),
+    bind("n", Normal::NextMatch(true), "next match"),
+    bind("N", Normal::NextMatch(false), "previous match"),
+    bind(
+        "*",
+        Normal::SearchWord(true, true),
+        "search the word under the cursor",
+    ),
+    bind(
+        "#",
+        Normal::SearchWord(false, true),
+        "search the word under the cursor backward",
+    ),
+    bind("J", Normal::Join(true), "join lines"),
+    bind("~", Normal::ToggleCase, "toggle case of a character"),
+    bind("p", Normal::Paste, "paste"),
+    bind("dd", Normal::Delete(Lines::Current), "delete the line"),
+    bind(
+        "dj",
+        Normal::Delete(Lines::Below),
+        "delete the line and the one below",
+    ),
+    bind(
+        "dk",
+        Normal::Delete(Lines::Above),
+        "delete the line and the one above",
+    ),
+    bind("yy", Normal::Yank(Lines::Current), "yank the line"),
+    bind(
+        "yj",
+        Normal::Yank(Lines::Below),
+        "yank the line and the one below",
+    ),
+    bind(
+        "yk",
+        Normal::Yank(Lines::Above),
+        "yank the line and the one above",
+    ),
+    bind(
+        "zz",
+        Normal::AlignLine(ScreenPos::Middle),
+        "cursor line to the middle",
+    ),
+    bind(
+        "zt",
+        Normal::AlignLine(ScreenPos::Start),
+        "cursor line to the top",
+    ),
+    bind(
+        "zb",
+        Normal::AlignLine(ScreenPos::End),
+        "cursor line to the bottom",
+    ),
+    bind("zh", Normal::ScrollColumns(-1), "scroll left"),
+    bind("zl", Normal::ScrollColumns(1), "scroll right"),
+    bind(
+        "zs",
+        Normal::AlignColumn(ScreenPos::Start),
+        "cursor column to the left edge",
+    ),
+    bind(
+        "ze",
+        Normal::AlignColumn(ScreenPos::End),
+        "cursor column to the right edge",
+    ),
+    bind("gJ", Normal::Join(false), "join lines without spaces"),
+    bind(
+        "g*",
+        Normal::SearchWord(true, false),
+        "search the word, also inside others",
+    ),
+    bind(
+        "g#",
+        Normal::SearchWord(false, false),
+        "search the word backward, also inside others",
+    ),
+    bind("g~{motion}", Normal::Case(CaseOp::Toggle), "toggle case"),
+    bind("gu{motion}", Normal::Case(CaseOp::Lower), "lowercase"),
+    bind("gU{motion}", Normal::Case(CaseOp::Upper), "uppercase"),
+    bind(
+        "g~~",
+        Normal::CaseLine(CaseOp::Toggle),
+        "toggle case of the line",
+    ),
+    bind("guu", Normal::CaseLine(CaseOp::Lower), "lowercase the line"),
+    bind("gUU", Normal::CaseLine(CaseOp::Upper), "uppercase the line"),
+    bind("gt", Normal::Tab(true), "next tab page"),
+    bind("gT", Normal::Tab(false), "previous tab page"),
+    bind("m{a-z}", Normal::Mark, "set a mark"),
+    bind("'{mark}", Normal::Jump(false), "jump to the line of a mark"),
+    bind("`{mark}", Normal::Jump(true), "jump to a mark"),
+    bind("q:", Normal::HistoryWindow, "command-line window"),
+    bind("ZQ", Normal::Command("q!"), "quit without writing"),
+    bind("<C-f>", Normal::ScrollPage(1), "page down"),
+    bind("<C-b>", Normal::ScrollPage(-1), "page up"),
+    bind("<C-d>", Normal::ScrollHalfPage(1), "half a page down"),
+    bind("<C-u>", Normal::ScrollHalfPage(-1), "half a page up"),
+    bind("<C-e>", Normal::ScrollLines(1), "scroll a line down"),
+    bind("<C-y>", Normal::ScrollLines(-1), "scroll a line up"),
+    bind("<C-a>", Normal::Increment(1), "increment the number"),
+    bind("<C-x>", Normal::Increment(-1), "decrement the number"),
+    bind("<C-w>S", Normal::Window('S'), "split the window"),
+    bind("<C-w>w", Normal::Window('w'), "next window"),
+    bind("<C-w>W", Normal::Window('W'), "previous window"),
+    bind("<C-w>p", Normal::Window('p'), "last used window"),
+    bind("<C-w>h", Normal::Window('h'), "window to the left"),
+    bind("<C-w>j", Normal::Window('j'), "window below"),
+    bind("<C-w>k", Normal::Window('k'), "window above"),
+    bind("<C-w>l", Normal::Window('l'), "window to the right"),
+    bind("<C-w>q", Normal::Window('q'), "quit the window"),
+    bind("<C-w>+", Normal::Window('+'), "taller window"),
+    bind("<C-w>-", Normal::Window('-'), "shorter window"),
+    bind("<C-w>>", Normal::Window('>'), "wider window"),
+    bind("<C-w><lt>", Normal::Window('<'), "narrower window"),
+    bind(
+        "<C-w>=",
+        Normal::Window('='),
+        "make the windows the same size",
+    ),
+    bind("<Left>", Normal::Move(MovementKey::Left), "cursor left"),
+    bind("<Down>", Normal::Move(MovementKey::Down), "cursor down"),
+    bind("<Up>", Normal::Move(MovementKey::Up), "cursor up"),
+    bind("<Right>", Normal::Move(MovementKey::Right), "cursor right"),
+];
+
+pub enum Found {
+    Binding(&'static Binding),
+    // the key of an ex command, it runs the command
+    Command(&'static Command),
+    // the keys so far start a longer one
+    Prefix,
+    None,
+}
+
+// the built-in key the `typed` keys are, or start
+pub fn lookup(typed: &[KeyEvent]) -> Found {
+    let Some((_, before)) = typed.split_last() else {
+        return Found::None;
+    };
+    let names: String = typed.iter().map(keys::name).collect();
+    let head: String = before.iter().map(keys::name).collect();
+
+    // g~~ is a key of its own, not g~ with ~ for a motion
+    let exact = NORMAL.iter().find(|binding| binding.keys == names);
+    let argument = || {
+        NORMAL.iter().find(|binding| {
+            binding
+                .keys
+                .split_once('{')
+                .is_some_and(|(keys, _)| !keys.is_empty() && keys == head)
+        })
+    };
+    if let Some(binding) = exact.or_else(argument) {
+        return Found::Binding(binding);
+    }
+    if let Some(command) = ex::COMMANDS.iter().find(|command| command.keys == names) {
+        return Found::Command(command);
+    }
+    if all_keys().any(|(keys, _)| keys.len() > names.len() && keys.starts_with(&names)) {
+        return Found::Prefix;
+    }
+    Found::None
+}
+
+// the keys of NORMAL and of the ex commands, with what they do
+fn all_keys() -> impl Iterator<Item = (&'static str, &'static str)> {
+    let normal = NORMAL
+        .iter()
+        .map(|binding| (binding.keys, binding.description));
+    let commands = ex::COMMANDS
+        .iter()
+        .filter(|command| !command.keys.is_empty())
+        .map(|command| (command.keys, command.description));
+    normal.chain(commands)
+}
+
+// the built-in keys after `prefix` and what they do
+pub fn continuations(prefix: &str) -> Vec<(String, &'static str)> {
+    all_keys()
+        .filter(|(keys, _)| keys.len() > prefix.len() && keys.starts_with(prefix))
+        .map(|(keys, description)| (keys[prefix.len()..].to_string(), description))
+        .collect()
+}
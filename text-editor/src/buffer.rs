@@ -0,0 +1,61 @@
+use super::options::Local;
+use super::row::Row;
+use super::undo::UndoHistory;
+use std::collections::HashMap;
+use text_editor::Position;
+
+/* a buffer that isn't the current one. The current buffer lives in the
+ * fields of Kass, switching swaps them with one of these */
+#[derive(Debug, Clone)]
+pub struct Buffer {
+    pub number: usize,
+    // empty when the buffer has no name
+    pub filepath: String,
+    // a buffer of the argument list isn't read until it is first shown
+    pub loaded: bool,
+    pub rows: Vec<Row>,
+    pub undo_history: UndoHistory,
+    // how the file was stored and the buffer local options
+    pub file_format: Vec<String>,
+    pub options: Local,
+
+    // where the cursor and the view were when it was left
+    pub cursor: Position,
+    pub rowoff: u16,
+    pub coloff: u16,
+    pub marks: HashMap<char, Position>,
+    pub last_visual: Option<(Position, Position)>,
+}
+
+impl Buffer {
+    // a buffer for `filepath` that is read once it is shown
+    pub fn unloaded(number: usize, filepath: &str) -> Self {
+        Buffer {
+            number,
+            filepath: filepath.to_string(),
+            loaded: false,
+            rows: Vec::new(),
+            undo_history: UndoHistory::new(),
+            file_format: Vec::new(),
+            options: Local::default(),
+            cursor: Position::default(),
+            rowoff: 0,
+            coloff: 0,
+            marks: HashMap::new(),
+            last_visual: None,
+        }
+    }
+
+    pub fn modified(&self) -> bool {
+        self.undo_history.modified()
+    }
+
+    // the name :ls shows
+    pub fn name(&self) -> &str {
+        if self.filepath.is_empty() {
+            "[No Name]"
+        } else {
+            &self.filepath
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::{env, fs, path::PathBuf};
+
+// what a key did to the command line
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    // the text or the cursor changed
+    Edited,
+    Execute,
+    // backspace on an empty line leaves the command line
+    Cancel,
+    // Ctrl-r {reg}, the editor knows what is in the registers
+    Register(char),
+    // Ctrl-r Ctrl-w, the word under the cursor in the buffer
+    Word,
+    Ignored,
+}
+
+#[derive(Debug, Clone)]
+pub struct CommandLine {
+    // the typed text without the leading `:`
+    pub text: String,
+    // byte index into text
+    pub cursor: usize,
+
+    pub history: Vec<String>,
+    pub history_size: usize,
+    // the entry shown by Up / Down, history.len() is the line being typed
+    history_idx: usize,
+    // the text typed before browsing, only entries starting with it are shown
+    prefix: Option<String>,
+    // Ctrl-r was pressed, the next key names the register
+    pending_register: bool,
+}
+
+impl CommandLine {
+    pub fn new() -> Self {
+        let history = history_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|content| content.lines().map(String::from).collect::<Vec<_>>())
+            .unwrap_or_default();
+
+        CommandLine {
+            text: String::new(),
+            cursor: 0,
+            history_idx: history.len(),
+            history,
+            history_size: 200,
+            prefix: None,
+            pending_register: false,
+        }
+    }
+
+    // opens the command line with `text` already typed
+    pub fn start(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.text.len();
+        self.history_idx = self.history.len();
+        self.prefix = None;
+        self.pending_register = false;
+    }
+
+    // inserts at the cursor, line breaks can't be part of a command
+    pub fn insert(&mut self, text: &str) {
+        let text = text.replace('\n', " ");
+        self.text.insert_str(self.cursor, &text);
+        self.cursor += text.len();
+    }
+
+    fn prev_boundary(&self) -> usize {
+        self.text[..self.cursor]
+            .chars()
+            .next_back()
+            .map_or(0, |c| self.cursor - c.len_utf8())
+    }
+
+    fn next_boundary(&self) -> usize {
+        self.text[self.cursor..]
+            .chars()
+            .next()
+            .map_or(self.cursor, |c| self.cursor + c.len_utf8())
+    }
+
+    // Ctrl-w: the spaces before the cursor and then the word or the run of symbols
+    fn delete_word_before(&mut self) {
+        let before = self.text[..self.cursor].trim_end();
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let word = before.chars().next_back().is_some_and(is_word);
+
+        let start = before
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| !c.is_whitespace() && is_word(*c) == word)
+            .last()
+            .map_or(before.len(), |(i, _)| i);
+        self.text.replace_range(start..self.cursor, "");
+        self.cursor = start;
+    }
+
+    // Up / Down, skipping entries that don't start with what was typed
+    fn browse(&mut self, back: bool) {
+        let prefix = self.prefix.get_or_insert_with(|| self.text.clone()).clone();
+        let matches = |entry: &String| entry.starts_with(&prefix);
+
+        let found = if back {
+            self.history[..self.history_idx].iter().rposition(matches)
+        } else {
+            self.history
+                .get(self.history_idx + 1..)
+                .and_then(|newer| newer.iter().position(matches))
+                .map(|i| self.history_idx + 1 + i)
+        };
+
+        match found {
+            Some(idx) => {
+                self.history_idx = idx;
+                self.text = self.history[idx].clone();
+            }
+            // going past the newest entry brings the typed text back
+            None if !back => {
+                self.history_idx = self.history.len();
+                self.text = prefix;
+            }
+            None => {}
+        }
+        self.cursor = self.text.len();
+    }
+
+    pub fn handle_key(&mut self, key: &KeyEvent) -> Input {
+        if self.pending_register {
+            self.pending_register = false;
+            return match (key.code, key.modifiers) {
+                (KeyCode::Char('w'), KeyModifiers::CONTROL) => Input::Word,
+                (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => Input::Register(c),
+                _ => Input::Ignored,
+            };
+        }
+
+        // anything but Up / Down starts browsing over with the new text
+        if !matches!(key.code, KeyCode::Up | KeyCode::Down) {
+            self.prefix = None;
+            self.history_idx = self.history.len();
+        }
+
+        match (key.code, key.modifiers) {
+            (KeyCode::Enter, _) => return Input::Execute,
+            (KeyCode::Backspace, _) if self.text.is_empty() => return Input::Cancel,
+            (KeyCode::Backspace, _) | (KeyCode::Char('h'), KeyModifiers::CONTROL) => {
+                let start = self.prev_boundary();
+                self.text.replace_range(start..self.cursor, "");
+                self.cursor = start;
+            }
+            (KeyCode::Delete, _) => {
+                let end = self.next_boundary();
+                self.text.replace_range(self.cursor..end, "");
+            }
+            (KeyCode::Left, _) => self.cursor = self.prev_boundary(),
+            (KeyCode::Right, _) => self.cursor = self.next_boundary(),
+            (KeyCode::Home, _) | (KeyCode::Char('b'), KeyModifiers::CONTROL) => self.cursor = 0,
+            (KeyCode::End, _) | (KeyCode::Char('e'), KeyModifiers::CONTROL) => {
+                self.cursor = self.text.len()
+            }
+            (KeyCode::Char('w'), KeyModifiers::CONTROL) => self.delete_word_before(),
+            (KeyCode::Char('u'), KeyModifiers::CONTROL) => {
+                self.text.replace_range(..self.cursor, "");
+                self.cursor = 0;
+            }
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => self.pending_register = true,
+            (KeyCode::Up, _) => self.browse(true),
+            (KeyCode::Down, _) => self.browse(false),
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                self.text.insert(self.cursor, c);
+                self.cursor += c.len_utf8();
+            }
+            _ => return Input::Ignored,
+        }
+        Input::Edited
+    }
+
+    // adds an executed line to the history and writes it out for the next session
+    pub fn remember(&mut self, line: &str) {
+        if line.trim().is_empty() {
+            return;
+        }
+        self.history.retain(|entry| entry != line);
+        self.history.push(line.to_string());
+        let excess = self.history.len().saturating_sub(self.history_size);
+        self.history.drain(..excess);
+        self.history_idx = self.history.len();
+
+        // the history is a convenience, a read only home shouldn't get in the way
+        if let Some(path) = history_path() {
+            if let Some(dir) = path.parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            let _ = fs::write(path, self.history.join("\n") + "\n");
+        }
+    }
+}
+
+// $XDG_DATA_HOME/kass/history, ~/.local/share/kass/history without it
+fn history_path() -> Option<PathBuf> {
+    let data = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))?;
+    Some(data.join("kass").join("history"))
+}
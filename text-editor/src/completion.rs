@@ -0,0 +1,140 @@
+use std::{env, fs, path::PathBuf};
+
+// what the argument of a command is completed with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Complete {
+    Nothing,
+    Option,
+    File,
+    Buffer,
+}
+
+// the candidates of a Tab completion being cycled through
+#[derive(Debug, Clone)]
+pub struct Completion {
+    // where the completed word starts in the command line
+    pub start: usize,
+    pub candidates: Vec<String>,
+    // None shows the text that was typed
+    pub selected: Option<usize>,
+    typed: String,
+}
+
+impl Completion {
+    pub fn new(start: usize, typed: &str, candidates: Vec<String>) -> Self {
+        Completion {
+            start,
+            candidates,
+            selected: None,
+            typed: typed.to_string(),
+        }
+    }
+
+    /* moves to the next (or previous) candidate and returns the text for the
+     * word, going past either end shows the typed text again */
+    pub fn step(&mut self, forward: bool) -> &str {
+        let len = self.candidates.len();
+        self.selected = match (self.selected, forward) {
+            (None, true) => Some(0),
+            (None, false) => Some(len - 1),
+            (Some(i), true) if i + 1 < len => Some(i + 1),
+            (Some(i), false) if i > 0 => Some(i - 1),
+            _ => None,
+        };
+        match self.selected {
+            Some(i) => &self.candidates[i],
+            None => &self.typed,
+        }
+    }
+}
+
+/* scores `candidate` when every character of `pattern` appears in it in
+ * order, ignoring case. Runs of characters and matches at the start of a
+ * word count more, gaps count against it */
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i64> {
+    let mut score = 0;
+    let mut chars = candidate.char_indices();
+    let mut last_match: Option<usize> = None;
+    let mut prev: Option<char> = None;
+
+    for p in pattern.chars().flat_map(|c| c.to_lowercase()) {
+        loop {
+            let (i, c) = chars.next()?;
+            let word_start = prev.is_none_or(|prev| !prev.is_alphanumeric())
+                || (c.is_uppercase() && prev.is_some_and(|prev| prev.is_lowercase()));
+            prev = Some(c);
+
+            if c.to_lowercase().eq(std::iter::once(p)) {
+                score += match last_match {
+                    Some(last) if last + 1 == i => 8,
+                    Some(last) => 1 - (i - last) as i64 / 4,
+                    None => 2 - i as i64 / 4,
+                };
+                if word_start {
+                    score += 6;
+                }
+                last_match = Some(i + c.len_utf8() - 1);
+                break;
+            }
+        }
+    }
+    Some(score)
+}
+
+/* the candidates starting with `word`, or the fuzzy matches best first when
+ * none does */
+pub fn filter(word: &str, candidates: &[String]) -> Vec<String> {
+    let mut matches: Vec<String> = candidates
+        .iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .cloned()
+        .collect();
+    if matches.is_empty() {
+        let mut scored: Vec<(i64, &String)> = candidates
+            .iter()
+            .filter_map(|candidate| fuzzy_score(word, candidate).map(|score| (score, candidate)))
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+        matches = scored
+            .into_iter()
+            .map(|(_, candidate)| candidate.clone())
+            .collect();
+    } else {
+        matches.sort();
+    }
+    matches.dedup();
+    matches
+}
+
+/* files and directories for a partly typed path, relative to the cwd.
+ * Directories end in `/`, hidden files show up once a `.` is typed */
+pub fn paths(word: &str) -> Vec<String> {
+    let (dir, name) = match word.rfind('/') {
+        Some(i) => (&word[..=i], &word[i + 1..]),
+        None => ("", word),
+    };
+
+    let search_dir = match dir.strip_prefix("~/") {
+        Some(rest) => env::var_os("HOME").map(|home| PathBuf::from(home).join(rest)),
+        None if dir.is_empty() => Some(PathBuf::from(".")),
+        None => Some(PathBuf::from(dir)),
+    };
+    let Some(entries) = search_dir.and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+
+    let names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let mut file_name = entry.file_name().into_string().ok()?;
+            if file_name.starts_with('.') && !name.starts_with('.') {
+                return None;
+            }
+            if entry.path().is_dir() {
+                file_name.push('/');
+            }
+            Some(format!("{dir}{file_name}"))
+        })
+        .collect();
+    filter(word, &names)
+}
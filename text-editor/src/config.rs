@@ -0,0 +1,236 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+use toml::{Table, Value};
+
+/* a config file turned into the ex commands it stands for, so that a config
+ * can do nothing a typed command can't. Filetype settings wait for a file */
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub commands: Vec<String>,
+    // `setlocal` arguments by file extension
+    pub filetypes: HashMap<String, Vec<String>>,
+}
+
+// $XDG_CONFIG_HOME/kass, ~/.config/kass without it
+fn config_dir() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config.join("kass"))
+}
+
+// config.toml, or a kassrc of ex commands when there is no toml
+pub fn user_config_path() -> Option<PathBuf> {
+    let dir = config_dir()?;
+    ["config.toml", "kassrc"]
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/* the nearest .kass.toml from the directory of `file` up, settings for
+ * everything in that tree. Without a file the search starts in the cwd */
+pub fn project_config_path(file: &Path) -> Option<PathBuf> {
+    let cwd = env::current_dir().ok()?;
+    let dir = match cwd.join(file).parent() {
+        Some(dir) if !file.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => cwd,
+    };
+    dir.ancestors()
+        .map(|dir| dir.join(".kass.toml"))
+        .find(|path| path.is_file())
+}
+
+pub fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "toml")
+}
+
+pub fn load(path: &Path) -> Result<Config, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("E484: Can't open file {}: {e}", path.display()))?;
+    if is_toml(path) {
+        parse(&content)
+            .map_err(|e| format!("Error detected while processing {}:\n{e}", path.display()))
+    } else {
+        Ok(Config {
+            commands: script(&content),
+            ..Config::default()
+        })
+    }
+}
+
+/* a .kass.toml comes with whatever tree a file is opened in, it only gets
+ * options and filetype sections. `commands` and `keys` could run any ex
+ * command. The :setlocal arguments it has for a file with `extension` */
+pub fn load_project(path: &Path, extension: &str) -> Result<Vec<String>, String> {
+    let error = |e: String| format!("Error detected while processing {}:\n{e}", path.display());
+    if !is_toml(path) {
+        return Err(error(String::from(
+            "E12: Not allowed in a project config: a script",
+        )));
+    }
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("E484: Can't open file {}: {e}", path.display()))?;
+    let table = table(&content).map_err(error)?;
+    if let Some(key) = table
+        .keys()
+        .find(|key| !matches!(key.as_str(), "options" | "filetype"))
+    {
+        return Err(error(format!(
+            "E12: Not allowed in a project config: {key}"
+        )));
+    }
+    let mut config = from_table(&table).map_err(error)?;
+    let mut args = match table.get("options") {
+        Some(Value::Table(options)) => set_args(options).map_err(error)?,
+        _ => Vec::new(),
+    };
+    args.extend(config.filetypes.remove(extension).unwrap_or_default());
+    Ok(args)
+}
+
+// the lines of an ex script, blank lines and `"` comments left out
+pub fn script(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(|line| line.trim_start().trim_start_matches(':'))
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('"'))
+        .map(String::from)
+        .collect()
+}
+
+/* commands = ["..."]           ex commands, run first
+ * [options]                    name = true / false / number / "string" / [list]
+ * [keys.normal] .. [keys.command]  "lhs" = "rhs", leader = "," under [keys]
+ * [theme.Group]                fg, bg, style
+ * [filetype.<extension>]       options for files with that extension */
+pub fn parse(content: &str) -> Result<Config, String> {
+    from_table(&table(content)?)
+}
+
+fn from_table(table: &Table) -> Result<Config, String> {
+    let mut config = Config::default();
+    for (key, value) in table {
+        match (key.as_str(), value) {
+            ("commands", Value::Array(commands)) => {
+                for command in commands {
+                    config.commands.push(string(command, "commands")?);
+                }
+            }
+            ("options", Value::Table(options)) => {
+                config.commands.extend(
+                    set_args(options)?
+                        .into_iter()
+                        .map(|arg| format!("set {arg}")),
+                );
+            }
+            ("keys", Value::Table(keys)) => config.commands.extend(mappings(keys)?),
+            ("theme", Value::Table(groups)) => {
+                for (group, style) in groups {
+                    let Value::Table(style) = style else {
+                        return Err(format!("theme.{group}: expected a table"));
+                    };
+                    let mut command = format!("highlight {group}");
+                    for (key, value) in style {
+                        let value = string(value, &format!("theme.{group}.{key}"))?;
+                        command.push_str(&format!(" {key}={}", value.replace(' ', "")));
+                    }
+                    config.commands.push(command);
+                }
+            }
+            ("filetype", Value::Table(filetypes)) => {
+                for (extension, options) in filetypes {
+                    let Value::Table(options) = options else {
+                        return Err(format!("filetype.{extension}: expected a table"));
+                    };
+                    config
+                        .filetypes
+                        .insert(extension.clone(), set_args(options)?);
+                }
+            }
+            _ => return Err(format!("unknown setting: {key}")),
+        }
+    }
+    Ok(config)
+}
+
+fn table(content: &str) -> Result<Table, String> {
+    content.parse().map_err(|e: toml::de::Error| {
+        let message = e.message().to_string();
+        match e.span().map(|span| line_of(content, span.start)) {
+            Some(line) => format!("line {line}: {message}"),
+            None => message,
+        }
+    })
+}
+
+// the one based line of a byte offset
+fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset.min(content.len())].matches('\n').count() + 1
+}
+
+fn string(value: &Value, key: &str) -> Result<String, String> {
+    match value {
+        Value::String(text) => Ok(text.clone()),
+        _ => Err(format!("{key}: expected a string")),
+    }
+}
+
+// `:set` arguments for a table of options, spaces and commas escaped
+fn set_args(options: &Table) -> Result<Vec<String>, String> {
+    let escape = |text: &str| text.replace('\\', "\\\\").replace(' ', "\\ ");
+    options
+        .iter()
+        .map(|(name, value)| match value {
+            Value::Boolean(true) => Ok(name.clone()),
+            Value::Boolean(false) => Ok(format!("no{name}")),
+            Value::Integer(n) => Ok(format!("{name}={n}")),
+            Value::String(text) => Ok(format!("{name}={}", escape(text))),
+            Value::Array(items) => {
+                let items = items
+                    .iter()
+                    .map(|item| string(item, name).map(|item| escape(&item)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(format!("{name}={}", items.join(",")))
+            }
+            _ => Err(format!(
+                "{name}: expected a boolean, number, string or list"
+            )),
+        })
+        .collect()
+}
+
+// [keys] tables become noremap commands, the leader is set before any of them
+fn mappings(keys: &Table) -> Result<Vec<String>, String> {
+    let mut commands = Vec::new();
+    if let Some(leader) = keys.get("leader") {
+        let leader = string(leader, "keys.leader")?;
+        commands.push(format!("set mapleader={}", leader.replace(' ', "<Space>")));
+    }
+
+    for (mode, table) in keys {
+        let prefix = match mode.as_str() {
+            "leader" => continue,
+            "normal" => "n",
+            "insert" => "i",
+            "visual" => "v",
+            "command" => "c",
+            _ => return Err(format!("keys.{mode}: unknown mode")),
+        };
+        let Value::Table(table) = table else {
+            return Err(format!("keys.{mode}: expected a table"));
+        };
+        for (lhs, rhs) in table {
+            let rhs = string(rhs, &format!("keys.{mode}.{lhs}"))?;
+            commands.push(format!(
+                "{prefix}noremap {} {rhs}",
+                lhs.replace(' ', "<Space>")
+            ));
+        }
+    }
+    Ok(commands)
+}
@@ -0,0 +1,330 @@
+use super::paths;
+use regex::Regex;
+use std::{collections::HashMap, fs, path::Path};
+
+// a [glob] section of an .editorconfig and the properties under it
+struct Section {
+    pattern: Regex,
+    properties: Vec<(String, String)>,
+}
+
+// the sections of one .editorconfig and whether the search stops there
+struct File {
+    root: bool,
+    sections: Vec<Section>,
+}
+
+/* the properties of every .editorconfig from `path`'s directory up to the
+ * one with `root = true`. Nearer files win over farther ones and later
+ * sections over earlier ones, `unset` takes a property away again */
+pub fn properties(path: &Path) -> HashMap<String, String> {
+    let path = paths::absolute(path);
+    let mut files = Vec::new();
+    for dir in path.ancestors().skip(1) {
+        let Ok(content) = fs::read_to_string(dir.join(".editorconfig")) else {
+            continue;
+        };
+        let file = parse(&content);
+        let root = file.root;
+        files.push((dir.to_path_buf(), file));
+        if root {
+            break;
+        }
+    }
+
+    let mut properties = HashMap::new();
+    for (dir, file) in files.iter().rev() {
+        let Ok(relative) = path.strip_prefix(dir) else {
+            continue;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        for section in file
+            .sections
+            .iter()
+            .filter(|s| s.pattern.is_match(&relative))
+        {
+            for (key, value) in &section.properties {
+                if value == "unset" {
+                    properties.remove(key);
+                } else {
+                    properties.insert(key.clone(), value.clone());
+                }
+            }
+        }
+    }
+    properties
+}
+
+// sections with a glob that doesn't compile are left out, like unknown keys
+fn parse(content: &str) -> File {
+    let mut file = File {
+        root: false,
+        sections: Vec::new(),
+    };
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(glob) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            if let Ok(pattern) = Regex::new(&glob_regex(glob)) {
+                file.sections.push(Section {
+                    pattern,
+                    properties: Vec::new(),
+                });
+            }
+            continue;
+        }
+        let Some((key, value)) = line.split_once(['=', ':']) else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let value = value.trim().to_ascii_lowercase();
+        match file.sections.last_mut() {
+            Some(section) => section.properties.push((key, value)),
+            // the preamble before the first section
+            None if key == "root" => file.root = value == "true",
+            None => {}
+        }
+    }
+    file
+}
+
+/* a section glob as an anchored regex for paths relative to the directory of
+ * the .editorconfig. Globs without a `/` match the file name at any depth */
+fn glob_regex(glob: &str) -> String {
+    let body = translate(glob.strip_prefix('/').unwrap_or(glob));
+    if glob.contains('/') {
+        format!("^{body}$")
+    } else {
+        format!("^(?:.*/)?{body}$")
+    }
+}
+
+fn translate(glob: &str) -> String {
+    let chars: Vec<char> = glob.chars().collect();
+    let mut regex = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                regex.push_str(&regex::escape(&chars[i].to_string()));
+            }
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                i += 1;
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => match chars[i..].iter().position(|&c| c == ']') {
+                Some(len) if len > 1 => {
+                    let class: String = chars[i + 1..i + len].iter().collect();
+                    let class = match class.strip_prefix('!') {
+                        Some(rest) => format!("^{}", rest.replace('\\', "\\\\")),
+                        None => class.replace('\\', "\\\\"),
+                    };
+                    regex.push_str(&format!("[{class}]"));
+                    i += len;
+                }
+                _ => regex.push_str("\\["),
+            },
+            '{' => match closing_brace(&chars[i..]) {
+                Some(len) => {
+                    let inner: String = chars[i + 1..i + len].iter().collect();
+                    regex.push_str(&braces(&inner));
+                    i += len;
+                }
+                None => regex.push_str("\\{"),
+            },
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    regex
+}
+
+// the offset of the `}` closing the `{` that `chars` starts with
+fn closing_brace(chars: &[char]) -> Option<usize> {
+    let mut depth = 0;
+    let mut escaped = false;
+    for (i, &c) in chars.iter().enumerate() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// {a,b,c} picks one, {1..10} a number in the range, anything else is literal
+fn braces(inner: &str) -> String {
+    if let Some((from, to)) = inner.split_once("..") {
+        if let (Ok(from), Ok(to)) = (from.parse::<i64>(), to.parse::<i64>()) {
+            let (from, to) = (from.min(to), from.max(to));
+            // a huge range can't be spelled out, any number does then
+            if to - from > 1000 {
+                return String::from("[+-]?[0-9]+");
+            }
+            let numbers: Vec<String> = (from..=to).map(|n| n.to_string()).collect();
+            return format!("(?:{})", numbers.join("|"));
+        }
+    }
+
+    // split on the commas outside of nested braces
+    let mut parts = Vec::new();
+    let (mut depth, mut start, mut escaped) = (0, 0, false);
+    for (i, c) in inner.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&inner[start..]);
+
+    if parts.len() == 1 {
+        return format!("\\{{{}\\}}", translate(inner));
+    }
+    let parts: Vec<String> = parts.into_iter().map(translate).collect();
+    format!("(?:{})", parts.join("|"))
+}
+
+/* the :setlocal arguments for the properties kass knows, values it doesn't
+ * understand are ignored as the spec asks */
+pub fn set_args(properties: &HashMap<String, String>) -> Vec<String> {
+    let number = |key: &str| {
+        properties
+            .get(key)
+            .filter(|value| value.parse::<usize>().is_ok_and(|n| n > 0))
+    };
+    let mut args = Vec::new();
+
+    match properties.get("indent_style").map(String::as_str) {
+        Some("tab") => args.push(String::from("noexpandtab")),
+        Some("space") => args.push(String::from("expandtab")),
+        _ => {}
+    }
+    // without tab_width a tab is as wide as an indent
+    if let Some(width) = number("tab_width").or(number("indent_size")) {
+        args.push(format!("tabstop={width}"));
+    }
+    match properties.get("indent_size").map(String::as_str) {
+        Some("tab") => {
+            if let Some(width) = number("tab_width") {
+                args.push(format!("shiftwidth={width}"));
+            }
+        }
+        Some(_) => {
+            if let Some(size) = number("indent_size") {
+                args.push(format!("shiftwidth={size}"));
+            }
+        }
+        None => {}
+    }
+    match properties.get("end_of_line").map(String::as_str) {
+        Some("lf") => args.push(String::from("fileformat=unix")),
+        Some("crlf") => args.push(String::from("fileformat=dos")),
+        Some("cr") => args.push(String::from("fileformat=mac")),
+        _ => {}
+    }
+    if let Some(charset) = properties.get("charset") {
+        if super::options::ENCODINGS.contains(&charset.as_str()) {
+            args.push(format!("fileencoding={charset}"));
+        }
+    }
+    for (key, option) in [
+        ("trim_trailing_whitespace", "trimtrailingwhitespace"),
+        ("insert_final_newline", "fixendofline"),
+    ] {
+        match properties.get(key).map(String::as_str) {
+            Some("true") => args.push(option.to_string()),
+            Some("false") => args.push(format!("no{option}")),
+            _ => {}
+        }
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(glob: &str, path: &str) -> bool {
+        Regex::new(&glob_regex(glob)).unwrap().is_match(path)
+    }
+
+    #[test]
+    fn any_depth() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", "src/bin/main.rs"));
+        assert!(!matches("*.rs", "main.rsx"));
+        assert!(matches("*", "src/main.rs"));
+    }
+
+    #[test]
+    fn anchored() {
+        assert!(matches("/src/*.rs", "src/main.rs"));
+        assert!(!matches("/src/*.rs", "lib/src/main.rs"));
+        assert!(!matches("/src/*.rs", "src/bin/main.rs"));
+        assert!(matches("src/*.rs", "src/main.rs"));
+        assert!(!matches("src/*.rs", "lib/src/main.rs"));
+        assert!(matches("src/**.rs", "src/bin/main.rs"));
+    }
+
+    #[test]
+    fn alternatives() {
+        assert!(matches("*.{js,ts}", "index.ts"));
+        assert!(!matches("*.{js,ts}", "index.rs"));
+        // nested braces are alternatives within one alternative
+        assert!(matches("{a,{b,c}d}.txt", "a.txt"));
+        assert!(matches("{a,{b,c}d}.txt", "cd.txt"));
+        assert!(!matches("{a,{b,c}d}.txt", "c.txt"));
+        // a single word in braces is taken literally
+        assert!(matches("{single}.txt", "{single}.txt"));
+        assert!(!matches("{single}.txt", "single.txt"));
+        // so is a brace that is never closed
+        assert!(matches("{a,b.txt", "{a,b.txt"));
+    }
+
+    #[test]
+    fn number_ranges() {
+        assert!(matches("file{1..10}.txt", "file1.txt"));
+        assert!(matches("file{1..10}.txt", "file10.txt"));
+        assert!(!matches("file{1..10}.txt", "file11.txt"));
+        assert!(!matches("file{1..10}.txt", "file0.txt"));
+        assert!(matches("file{10..1}.txt", "file5.txt"));
+        assert!(matches("file{-2..2}.txt", "file-1.txt"));
+        assert!(matches("file{1..100000}.txt", "file4242.txt"));
+    }
+
+    #[test]
+    fn classes() {
+        assert!(matches("[ab].c", "a.c"));
+        assert!(!matches("[ab].c", "c.c"));
+        assert!(matches("[!x].c", "y.c"));
+        assert!(!matches("[!x].c", "x.c"));
+        assert!(matches("[a.c", "[a.c"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(matches("\\*.txt", "*.txt"));
+        assert!(!matches("\\*.txt", "a.txt"));
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "a/c"));
+    }
+}
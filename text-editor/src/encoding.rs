@@ -0,0 +1,114 @@
+// the encodings of 'fileencoding', text is kept as utf-8 in between
+
+const UTF8_BOM: &[u8] = &[0xef, 0xbb, 0xbf];
+
+/* the text of a file and the encoding it was read with. A byte order mark
+ * wins over `charset`, invalid utf-8 is replaced rather than refused */
+pub fn decode(bytes: &[u8], charset: Option<&str>) -> (String, &'static str) {
+    let charset = match bytes {
+        [0xef, 0xbb, 0xbf, ..] => "utf-8-bom",
+        [0xff, 0xfe, ..] => "utf-16le",
+        [0xfe, 0xff, ..] => "utf-16be",
+        _ => match charset {
+            Some("latin1") => "latin1",
+            Some("utf-16le") => "utf-16le",
+            Some("utf-16be") => "utf-16be",
+            Some("utf-8-bom") => "utf-8-bom",
+            _ => "utf-8",
+        },
+    };
+
+    let text = match charset {
+        "latin1" => bytes.iter().map(|&b| b as char).collect(),
+        "utf-16le" | "utf-16be" => {
+            let bytes = bytes
+                .strip_prefix(&[0xff, 0xfe][..])
+                .or_else(|| bytes.strip_prefix(&[0xfe, 0xff][..]))
+                .unwrap_or(bytes);
+            let units: Vec<u16> = bytes
+                .chunks(2)
+                .map(|pair| {
+                    let pair = [pair[0], pair.get(1).copied().unwrap_or(0)];
+                    match charset {
+                        "utf-16le" => u16::from_le_bytes(pair),
+                        _ => u16::from_be_bytes(pair),
+                    }
+                })
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => String::from_utf8_lossy(bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes)).into_owned(),
+    };
+    (text, charset)
+}
+
+// the bytes written for `text`, None when a character has no latin1 byte
+pub fn encode(text: &str, charset: &str) -> Option<Vec<u8>> {
+    match charset {
+        "utf-8-bom" => Some([UTF8_BOM, text.as_bytes()].concat()),
+        "latin1" => text.chars().map(|c| u8::try_from(c).ok()).collect(),
+        "utf-16le" => Some(
+            [0xfeff]
+                .into_iter()
+                .chain(text.encode_utf16())
+                .flat_map(u16::to_le_bytes)
+                .collect(),
+        ),
+        "utf-16be" => Some(
+            [0xfeff]
+                .into_iter()
+                .chain(text.encode_utf16())
+                .flat_map(u16::to_be_bytes)
+                .collect(),
+        ),
+        _ => Some(text.as_bytes().to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "grüße, 𝄞\n";
+
+    #[test]
+    fn round_trips() {
+        for charset in ["utf-8", "utf-8-bom", "utf-16le", "utf-16be"] {
+            let bytes = encode(TEXT, charset).unwrap();
+            assert_eq!(decode(&bytes, Some(charset)), (TEXT.to_string(), charset));
+        }
+        let bytes = encode("grüße\n", "latin1").unwrap();
+        assert_eq!(bytes, b"gr\xfc\xdfe\n");
+        assert_eq!(
+            decode(&bytes, Some("latin1")),
+            (String::from("grüße\n"), "latin1")
+        );
+    }
+
+    #[test]
+    fn byte_order_marks() {
+        // the mark wins over the charset a file was expected in
+        for charset in ["utf-8-bom", "utf-16le", "utf-16be"] {
+            let bytes = encode(TEXT, charset).unwrap();
+            assert_eq!(decode(&bytes, Some("latin1")).1, charset);
+            assert_eq!(decode(&bytes, None), (TEXT.to_string(), charset));
+        }
+    }
+
+    #[test]
+    fn unencodable() {
+        assert_eq!(encode("𝄞", "latin1"), None);
+        assert_eq!(encode("", "latin1"), Some(Vec::new()));
+    }
+
+    #[test]
+    fn invalid_input() {
+        assert_eq!(
+            decode(b"a\xffb", None),
+            (String::from("a\u{fffd}b"), "utf-8")
+        );
+        // an odd byte at the end of utf-16 is padded, not dropped
+        let (text, _) = decode(&[0xff, 0xfe, b'a', 0, b'b'], None);
+        assert_eq!(text, "ab");
+    }
+}
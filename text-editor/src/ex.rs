@@ -0,0 +1,1008 @@
+use super::completion::Complete;
+use super::options::SetScope;
+
+// what a command does, execute_command_line runs it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ex {
+    BufferAdd,
+    Buffer,
+    BufferDelete,
+    // :bnext with true, :bprevious with false
+    CycleBuffer(bool),
+    ListBuffers,
+    Close,
+    Config,
+    // :copy and :t
+    Copy,
+    CQuit,
+    Delete,
+    Edit,
+    // :vglobal with true
+    Global(bool),
+    Highlight,
+    Join,
+    LineNumber,
+    // every :map, :noremap and :unmap, told apart by name
+    Map,
+    Move,
+    NoHlSearch,
+    Normal,
+    Only,
+    Palette,
+    Quit,
+    QuitAll,
+    Read,
+    SaveAs,
+    Set(SetScope),
+    // :> with true, :< with false
+    Shift(bool),
+    Source,
+    // :vsplit with true
+    Split(bool),
+    // :& with true, it repeats the last one
+    Substitute(bool),
+    TabClose,
+    TabMove,
+    TabNew,
+    // :redo with true
+    Undo(bool),
+    Write,
+    WriteAll,
+    WriteQuit,
+    WriteQuitAll,
+    Xit,
+    Yank,
+}
+
+impl Ex {
+    // writes or leaves the buffer, which the q: window can't
+    pub fn leaves_buffer(self) -> bool {
+        matches!(
+            self,
+            Ex::Write
+                | Ex::WriteQuit
+                | Ex::SaveAs
+                | Ex::Edit
+                | Ex::Read
+                | Ex::BufferAdd
+                | Ex::Buffer
+                | Ex::CycleBuffer(_)
+                | Ex::BufferDelete
+                | Ex::Split(_)
+                | Ex::Close
+                | Ex::Only
+                | Ex::TabNew
+                | Ex::TabClose
+                | Ex::TabMove
+                | Ex::QuitAll
+                | Ex::WriteAll
+                | Ex::Xit
+                | Ex::WriteQuitAll
+        )
+    }
+}
+
+/* an ex command: its full name, how many characters are needed to abbreviate
+ * it, what its argument is completed with, what it does and the keys that
+ * do it too */
+#[derive(Debug, Clone, Copy)]
+pub struct Command {
+    pub name: &'static str,
+    min: usize,
+    pub complete: Complete,
+    // it does nothing useful without an argument, the palette asks for one
+    pub needs_args: bool,
+    pub action: Ex,
+    // the built-in key in normal mode and in the modeless preset, empty for none
+    pub keys: &'static str,
+    pub modeless: &'static str,
+    pub description: &'static str,
+}
+
+const fn command(
+    name: &'static str,
+    min: usize,
+    complete: Complete,
+    action: Ex,
+    description: &'static str,
+) -> Command {
+    Command {
+        name,
+        min,
+        complete,
+        needs_args: false,
+        action,
+        keys: "",
+        modeless: "",
+        description,
+    }
+}
+
+const fn with_keys(command: Command, keys: &'static str, modeless: &'static str) -> Command {
+    Command {
+        keys,
+        modeless,
+        ..command
+    }
+}
+
+const fn with_args(command: Command) -> Command {
+    Command {
+        needs_args: true,
+        ..command
+    }
+}
+
+pub const COMMANDS: [Command; 67] = [
+    with_args(command(
+        "badd",
+        3,
+        Complete::File,
+        Ex::BufferAdd,
+        "add a file to the buffer list",
+    )),
+    command(
+        "bdelete",
+        2,
+        Complete::Buffer,
+        Ex::BufferDelete,
+        "delete a buffer",
+    ),
+    command(
+        "bnext",
+        2,
+        Complete::Nothing,
+        Ex::CycleBuffer(true),
+        "go to the next buffer",
+    ),
+    command(
+        "bprevious",
+        2,
+        Complete::Nothing,
+        Ex::CycleBuffer(false),
+        "go to the previous buffer",
+    ),
+    with_args(command(
+        "buffer",
+        1,
+        Complete::Buffer,
+        Ex::Buffer,
+        "go to a buffer by number or name",
+    )),
+    command(
+        "buffers",
+        7,
+        Complete::Nothing,
+        Ex::ListBuffers,
+        "list the buffers",
+    ),
+    with_keys(
+        command("close", 3, Complete::Nothing, Ex::Close, "close the window"),
+        "<C-w>c",
+        "",
+    ),
+    command(
+        "cmap",
+        2,
+        Complete::Nothing,
+        Ex::Map,
+        "map keys on the command line",
+    ),
+    command(
+        "cnoremap",
+        3,
+        Complete::Nothing,
+        Ex::Map,
+        "map keys on the command line, not remapped",
+    ),
+    command(
+        "config",
+        4,
+        Complete::Nothing,
+        Ex::Config,
+        "show the config file, reload it with an argument",
+    ),
+    with_args(command(
+        "copy",
+        2,
+        Complete::Nothing,
+        Ex::Copy,
+        "copy lines below an address",
+    )),
+    command(
+        "cquit",
+        2,
+        Complete::Nothing,
+        Ex::CQuit,
+        "quit without writing, with an error exit status",
+    ),
+    command(
+        "cunmap",
+        2,
+        Complete::Nothing,
+        Ex::Map,
+        "remove a command line mapping",
+    ),
+    command("delete", 1, Complete::Nothing, Ex::Delete, "delete lines"),
+    command(
+        "edit",
+        1,
+        Complete::File,
+        Ex::Edit,
+        "open a file, or reload the current one",
+    ),
+    with_args(command(
+        "global",
+        1,
+        Complete::Nothing,
+        Ex::Global(false),
+        "run a command on the lines matching a pattern",
+    )),
+    command(
+        "highlight",
+        2,
+        Complete::Nothing,
+        Ex::Highlight,
+        "show or change the colors of the editor",
+    ),
+    command(
+        "imap",
+        2,
+        Complete::Nothing,
+        Ex::Map,
+        "map keys in insert mode",
+    ),
+    command(
+        "inoremap",
+        3,
+        Complete::Nothing,
+        Ex::Map,
+        "map keys in insert mode, not remapped",
+    ),
+    command(
+        "iunmap",
+        2,
+        Complete::Nothing,
+        Ex::Map,
+        "remove an insert mode mapping",
+    ),
+    command("join", 1, Complete::Nothing, Ex::Join, "join lines"),
+    command(
+        "ls",
+        2,
+        Complete::Nothing,
+        Ex::ListBuffers,
+        "list the buffers",
+    ),
+    command(
+        "map",
+        3,
+        Complete::Nothing,
+        Ex::Map,
+        "map keys in normal and visual mode",
+    ),
+    command(
+        "mapclear",
+        4,
+        Complete::Nothing,
+        Ex::Map,
+        "remove every mapping",
+    ),
+    with_args(command(
+        "move",
+        1,
+        Complete::Nothing,
+        Ex::Move,
+        "move lines below an address",
+    )),
+    command(
+        "nmap",
+        2,
+        Complete::Nothing,
+        Ex::Map,
+        "map keys in normal mode",
+    ),
+    command(
+        "nnoremap",
+        2,
+        Complete::Nothing,
+        Ex::Map,
+        "map keys in normal mode, not remapped",
+    ),
+    command(
+        "nohlsearch",
+        3,
+        Complete::Nothing,
+        Ex::NoHlSearch,
+        "stop highlighting the search matches",
+    ),
+    command(
+        "noremap",
+        2,
+        Complete::Nothing,
+        Ex::Map,
+        "map keys in normal and visual mode, not remapped",
+    ),
+    with_args(command(
+        "normal",
+        4,
+        Complete::Nothing,
+        Ex::Normal,
+        "run normal mode keys",
+    )),
+    command(
+        "nunmap",
+        3,
+        Complete::Nothing,
+        Ex::Map,
+        "remove a normal mode mapping",
+    ),
+    with_keys(
+        command(
+            "only",
+            2,
+            Complete::Nothing,
+            Ex::Only,
+            "close every other window",
+        ),
+        "<C-w>o",
+        "",
+    ),
+    with_keys(
+        command(
+            "palette",
+            2,
+            Complete::Nothing,
+            Ex::Palette,
+            "pick a command from a list",
+        ),
+        "<C-p>",
+        "<C-p>",
+    ),
+    command(
+        "qall",
+        2,
+        Complete::Nothing,
+        Ex::QuitAll,
+        "quit the editor, whatever windows are open",
+    ),
+    with_keys(
+        command(
+            "quit",
+            1,
+            Complete::Nothing,
+            Ex::Quit,
+            "close the window, the editor with the last one",
+        ),
+        "",
+        "<C-q>",
+    ),
+    command(
+        "read",
+        1,
+        Complete::File,
+        Ex::Read,
+        "put the lines of a file below the cursor",
+    ),
+    with_keys(
+        command(
+            "redo",
+            3,
+            Complete::Nothing,
+            Ex::Undo(true),
+            "redo the last undone change",
+        ),
+        "<C-r>",
+        "<C-y>",
+    ),
+    with_args(command(
+        "saveas",
+        3,
+        Complete::File,
+        Ex::SaveAs,
+        "write to another file and edit that one",
+    )),
+    command(
+        "set",
+        2,
+        Complete::Option,
+        Ex::Set(SetScope::Both),
+        "show or change options",
+    ),
+    command(
+        "setglobal",
+        4,
+        Complete::Option,
+        Ex::Set(SetScope::Global),
+        "show or change the global value of options",
+    ),
+    command(
+        "setlocal",
+        4,
+        Complete::Option,
+        Ex::Set(SetScope::Local),
+        "show or change the buffer value of options",
+    ),
+    with_args(command(
+        "source",
+        2,
+        Complete::File,
+        Ex::Source,
+        "run the commands of a file",
+    )),
+    with_keys(
+        command(
+            "split",
+            2,
+            Complete::File,
+            Ex::Split(false),
+            "split the window, or open a file above",
+        ),
+        "<C-w>s",
+        "",
+    ),
+    command(
+        "substitute",
+        1,
+        Complete::Nothing,
+        Ex::Substitute(false),
+        "replace a pattern, or repeat the last replacement",
+    ),
+    with_args(command(
+        "t",
+        1,
+        Complete::Nothing,
+        Ex::Copy,
+        "copy lines below an address",
+    )),
+    command(
+        "tabclose",
+        4,
+        Complete::Nothing,
+        Ex::TabClose,
+        "close the tab page",
+    ),
+    command(
+        "tabedit",
+        4,
+        Complete::File,
+        Ex::TabNew,
+        "open a file in a new tab page",
+    ),
+    command(
+        "tabmove",
+        4,
+        Complete::Nothing,
+        Ex::TabMove,
+        "move the tab page after another one",
+    ),
+    command(
+        "tabnew",
+        6,
+        Complete::File,
+        Ex::TabNew,
+        "open a new tab page, on a file or an empty buffer",
+    ),
+    with_keys(
+        command(
+            "undo",
+            1,
+            Complete::Nothing,
+            Ex::Undo(false),
+            "undo the last change",
+        ),
+        "u",
+        "<C-z>",
+    ),
+    command(
+        "unmap",
+        3,
+        Complete::Nothing,
+        Ex::Map,
+        "remove a normal and visual mode mapping",
+    ),
+    with_args(command(
+        "vglobal",
+        1,
+        Complete::Nothing,
+        Ex::Global(true),
+        "run a command on the lines not matching a pattern",
+    )),
+    command(
+        "vmap",
+        2,
+        Complete::Nothing,
+        Ex::Map,
+        "map keys in visual mode",
+    ),
+    command(
+        "vnoremap",
+        2,
+        Complete::Nothing,
+        Ex::Map,
+        "map keys in visual mode, not remapped",
+    ),
+    with_keys(
+        command(
+            "vsplit",
+            2,
+            Complete::File,
+            Ex::Split(true),
+            "split the window side by side, or open a file beside",
+        ),
+        "<C-w>v",
+        "",
+    ),
+    command(
+        "vunmap",
+        2,
+        Complete::Nothing,
+        Ex::Map,
+        "remove a visual mode mapping",
+    ),
+    command(
+        "wall",
+        2,
+        Complete::Nothing,
+        Ex::WriteAll,
+        "write every changed buffer",
+    ),
+    command(
+        "wq",
+        2,
+        Complete::File,
+        Ex::WriteQuit,
+        "write the file and quit",
+    ),
+    command(
+        "wqall",
+        3,
+        Complete::Nothing,
+        Ex::WriteQuitAll,
+        "write every changed buffer and quit",
+    ),
+    with_keys(
+        command("write", 1, Complete::File, Ex::Write, "write the file"),
+        "",
+        "<C-s>",
+    ),
+    command(
+        "xall",
+        2,
+        Complete::Nothing,
+        Ex::WriteQuitAll,
+        "write every changed buffer and quit",
+    ),
+    with_keys(
+        command(
+            "xit",
+            1,
+            Complete::File,
+            Ex::Xit,
+            "write the file if it changed and quit",
+        ),
+        "ZZ",
+        "",
+    ),
+    command("yank", 1, Complete::Nothing, Ex::Yank, "yank lines"),
+    command(
+        "<",
+        1,
+        Complete::Nothing,
+        Ex::Shift(false),
+        "shift lines left",
+    ),
+    command(
+        ">",
+        1,
+        Complete::Nothing,
+        Ex::Shift(true),
+        "shift lines right",
+    ),
+    command(
+        "&",
+        1,
+        Complete::Nothing,
+        Ex::Substitute(true),
+        "repeat the last substitute",
+    ),
+    command(
+        "=",
+        1,
+        Complete::Nothing,
+        Ex::LineNumber,
+        "show the line number",
+    ),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AddressBase {
+    // .
+    Current,
+    // $
+    Last,
+    // a line number, 0 is the line above the first one
+    Line(usize),
+    // 'a, '< and '>
+    Mark(char),
+    // /pattern/ searches forward, ?pattern? backward, an empty pattern reuses the last one
+    Search(String, bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Address {
+    pub base: AddressBase,
+    // the sum of every +N and -N after the base
+    pub offset: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExCommand {
+    pub range: Vec<Address>,
+    // `;` between the addresses, the second one is relative to the first
+    pub chained: bool,
+    // the command name as typed, empty for a bare range like :42
+    pub name: String,
+    pub bang: bool,
+    pub args: String,
+}
+
+impl ExCommand {
+    // the full name of the command, resolving abbreviations like :d or :co
+    pub fn full_name(&self) -> Option<&'static str> {
+        full_name(&self.name)
+    }
+
+    // a trailing count argument, like the 3 in :d 3
+    pub fn count(&self) -> std::result::Result<Option<usize>, String> {
+        let count = self.args.trim();
+        if count.is_empty() {
+            return Ok(None);
+        }
+        match count.parse::<usize>() {
+            Ok(0) => Err(String::from("E939: Positive count required")),
+            Ok(n) => Ok(Some(n)),
+            Err(_) => Err(format!("E488: Trailing characters: {count}")),
+        }
+    }
+
+    /* the first and last line numbers of the range, `resolve` turns an
+     * address into a line number counting from the line it is given */
+    pub fn resolve_range<F>(
+        &self,
+        current: usize,
+        mut resolve: F,
+    ) -> std::result::Result<Option<(usize, usize)>, String>
+    where
+        F: FnMut(&Address, usize) -> std::result::Result<usize, String>,
+    {
+        match self.range.as_slice() {
+            [] => Ok(None),
+            [only] => {
+                let line = resolve(only, current)?;
+                Ok(Some((line, line)))
+            }
+            [first, second, ..] => {
+                let first = resolve(first, current)?;
+                let from = if self.chained { first } else { current };
+                let second = resolve(second, from)?;
+                if second < first {
+                    return Err(String::from("E493: Backwards range given"));
+                }
+                Ok(Some((first, second)))
+            }
+        }
+    }
+}
+
+pub fn full_name(name: &str) -> Option<&'static str> {
+    lookup(name).map(|command| command.name)
+}
+
+// the command a name stands for, abbreviations like :d or :co included
+pub fn lookup(name: &str) -> Option<&'static Command> {
+    // :>> and :<<< are repeated shifts
+    if let Some(first) = name.chars().next().filter(|c| *c == '<' || *c == '>') {
+        if name.len() > 1 && name.chars().all(|c| c == first) {
+            return lookup(&name[..1]);
+        }
+    }
+
+    COMMANDS
+        .iter()
+        .find(|command| name.len() >= command.min && command.name.starts_with(name))
+}
+
+// the command a mapping like `:w<CR>` runs, None when it does more than that
+pub fn mapped_command(rhs: &str) -> Option<&'static str> {
+    let line = rhs.strip_prefix(':')?;
+    let end = line.len().checked_sub(4)?;
+    if !line.is_char_boundary(end) || !line[end..].eq_ignore_ascii_case("<cr>") {
+        return None;
+    }
+    let command = parse(&line[..end]).ok()?;
+    if !command.range.is_empty() || command.bang || !command.args.trim().is_empty() {
+        return None;
+    }
+    command.full_name()
+}
+
+// every command name, for completion
+pub fn command_names() -> Vec<String> {
+    COMMANDS
+        .iter()
+        .map(|command| command.name.to_string())
+        .filter(|full| full.chars().all(|c| c.is_ascii_alphabetic()))
+        .collect()
+}
+
+pub fn completion(full_name: &str) -> Complete {
+    COMMANDS
+        .iter()
+        .find(|command| command.name == full_name)
+        .map_or(Complete::Nothing, |command| command.complete)
+}
+
+/* reads up to the unescaped `delimiter`, returning the text and the rest
+ * after the delimiter (which may be missing at the end of the line) */
+pub fn split_delimited(text: &str, delimiter: char) -> (String, &str) {
+    let mut result = String::new();
+    let mut chars = text.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        if c == delimiter {
+            return (result, &text[i + c.len_utf8()..]);
+        }
+        if c == '\\' {
+            match chars.next() {
+                Some((_, next)) if next == delimiter => result.push(next),
+                Some((_, next)) => {
+                    result.push(c);
+                    result.push(next);
+                }
+                None => result.push(c),
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    (result, "")
+}
+
+fn parse_number(text: &str) -> (Option<usize>, &str) {
+    let digits = text.len() - text.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    (text[..digits].parse().ok(), &text[digits..])
+}
+
+fn parse_address(text: &str) -> std::result::Result<(Option<Address>, &str), String> {
+    let mut rest = text.trim_start();
+
+    let base = match rest.chars().next() {
+        Some('.') => {
+            rest = &rest[1..];
+            Some(AddressBase::Current)
+        }
+        Some('$') => {
+            rest = &rest[1..];
+            Some(AddressBase::Last)
+        }
+        Some('\'') => {
+            let mark = rest[1..]
+                .chars()
+                .next()
+                .ok_or_else(|| String::from("E20: Mark not set"))?;
+            rest = &rest[1 + mark.len_utf8()..];
+            Some(AddressBase::Mark(mark))
+        }
+        Some(delimiter @ ('/' | '?')) => {
+            let (pattern, after) = split_delimited(&rest[1..], delimiter);
+            rest = after;
+            Some(AddressBase::Search(pattern, delimiter == '/'))
+        }
+        Some(c) if c.is_ascii_digit() => {
+            let (line, after) = parse_number(rest);
+            rest = after;
+            line.map(AddressBase::Line)
+        }
+        _ => None,
+    };
+
+    // +N and -N, a sign without a number counts as one
+    let mut offset = 0;
+    let mut has_offset = false;
+    loop {
+        let trimmed = rest.trim_start();
+        let sign = match trimmed.chars().next() {
+            Some('+') => 1,
+            Some('-') => -1,
+            _ => break,
+        };
+        let (n, after) = parse_number(&trimmed[1..]);
+        offset += sign * n.unwrap_or(1) as i64;
+        has_offset = true;
+        rest = after;
+    }
+
+    let address = match base {
+        Some(base) => Some(Address { base, offset }),
+        None if has_offset => Some(Address {
+            base: AddressBase::Current,
+            offset,
+        }),
+        None => None,
+    };
+    Ok((address, rest))
+}
+
+// parses `[range][name][!] [args]`, the leading `:` is optional
+pub fn parse(line: &str) -> std::result::Result<ExCommand, String> {
+    let mut rest = line.trim_start_matches(|c: char| c == ':' || c.is_whitespace());
+    let mut range = Vec::new();
+    let mut chained = false;
+
+    if let Some(after) = rest.strip_prefix('%') {
+        range.push(Address {
+            base: AddressBase::Line(1),
+            offset: 0,
+        });
+        range.push(Address {
+            base: AddressBase::Last,
+            offset: 0,
+        });
+        rest = after;
+    } else {
+        loop {
+            let (address, after) = parse_address(rest)?;
+            rest = after.trim_start();
+
+            let separator = rest.chars().next().filter(|c| *c == ',' || *c == ';');
+            match (address, separator) {
+                (Some(address), _) => range.push(address),
+                // a missing address next to a separator is the current line
+                (None, Some(_)) => range.push(Address {
+                    base: AddressBase::Current,
+                    offset: 0,
+                }),
+                (None, None) if !range.is_empty() => range.push(Address {
+                    base: AddressBase::Current,
+                    offset: 0,
+                }),
+                (None, None) => {}
+            }
+            match separator {
+                Some(separator) => {
+                    chained = separator == ';';
+                    rest = &rest[1..];
+                }
+                None => break,
+            }
+        }
+    }
+
+    // only the last two addresses count
+    if range.len() > 2 {
+        range.drain(..range.len() - 2);
+    }
+
+    rest = rest.trim_start();
+    let name_len = match rest.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len()),
+        // :>>> shifts three times, the repeats are kept in the name
+        Some(c @ ('<' | '>')) => rest.find(|d: char| d != c).unwrap_or(rest.len()),
+        Some('&' | '=') => 1,
+        Some(c) => return Err(format!("E492: Not an editor command: {c}{}", &rest[1..])),
+        None => 0,
+    };
+    let name = rest[..name_len].to_string();
+    rest = &rest[name_len..];
+
+    let bang = !name.is_empty() && rest.starts_with('!');
+    if bang {
+        rest = &rest[1..];
+    }
+
+    Ok(ExCommand {
+        range,
+        chained,
+        name,
+        bang,
+        args: rest.trim_start().to_string(),
+    })
+}
+
+// the destination of :m and :t, a single address with nothing after it
+pub fn parse_target(text: &str) -> std::result::Result<Address, String> {
+    match parse_address(text)? {
+        (Some(address), rest) if rest.trim().is_empty() => Ok(address),
+        (Some(_), rest) => Err(format!("E488: Trailing characters: {}", rest.trim())),
+        (None, _) => Err(String::from("E14: Invalid address")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a ten line buffer with the cursor on line 5 and a selection of lines 3 to 7
+    fn lines(line: &str) -> std::result::Result<Option<(usize, usize)>, String> {
+        parse(line)?.resolve_range(5, |address, from| {
+            let line = match address.base {
+                AddressBase::Current => from,
+                AddressBase::Last => 10,
+                AddressBase::Line(line) => line,
+                AddressBase::Mark('<') => 3,
+                AddressBase::Mark('>') => 7,
+                _ => return Err(String::from("E20: Mark not set")),
+            };
+            Ok((line as i64 + address.offset) as usize)
+        })
+    }
+
+    #[test]
+    fn no_range() {
+        assert_eq!(lines("j"), Ok(None));
+        assert_eq!(parse("j").unwrap().name, "j");
+    }
+
+    #[test]
+    fn line_zero() {
+        assert_eq!(lines("0"), Ok(Some((0, 0))));
+        assert_eq!(lines("0,3j"), Ok(Some((0, 3))));
+        assert_eq!(parse("0,3j").unwrap().full_name(), Some("join"));
+    }
+
+    #[test]
+    fn last_line() {
+        assert_eq!(lines("$"), Ok(Some((10, 10))));
+        assert_eq!(lines(".,$d"), Ok(Some((5, 10))));
+        assert_eq!(lines("$-2,$"), Ok(Some((8, 10))));
+        assert_eq!(lines("%"), Ok(Some((1, 10))));
+    }
+
+    #[test]
+    fn visual_marks() {
+        assert_eq!(lines("'<,'>s/a/b/"), Ok(Some((3, 7))));
+        let command = parse("'<,'>s/a/b/").unwrap();
+        assert_eq!(command.full_name(), Some("substitute"));
+        assert_eq!(command.args, "/a/b/");
+        assert_eq!(lines("'x"), Err(String::from("E20: Mark not set")));
+    }
+
+    #[test]
+    fn missing_addresses() {
+        assert_eq!(lines(",7"), Ok(Some((5, 7))));
+        assert_eq!(lines("3,"), Ok(Some((3, 5))));
+        assert_eq!(lines("+1,+2"), Ok(Some((6, 7))));
+    }
+
+    #[test]
+    fn chained() {
+        // the second address counts from the first one after a `;`
+        assert_eq!(lines("2;+1"), Ok(Some((2, 3))));
+        assert_eq!(lines("6,+1"), Ok(Some((6, 6))));
+    }
+
+    #[test]
+    fn reversed_range() {
+        let backwards = Err(String::from("E493: Backwards range given"));
+        assert_eq!(lines("7,3d"), backwards);
+        assert_eq!(lines("$,1"), backwards);
+        assert_eq!(lines("'>,'<"), backwards);
+        assert_eq!(lines("3,3"), Ok(Some((3, 3))));
+    }
+
+    #[test]
+    fn last_two_addresses() {
+        assert_eq!(lines("1,2,4"), Ok(Some((2, 4))));
+    }
+
+    #[test]
+    fn trailing_count() {
+        assert_eq!(parse("d 3").unwrap().count(), Ok(Some(3)));
+        assert_eq!(parse("d").unwrap().count(), Ok(None));
+        assert!(parse("d 0").unwrap().count().is_err());
+        assert!(parse("d x").unwrap().count().is_err());
+    }
+}
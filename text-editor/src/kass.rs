@@ -1,7 +1,27 @@
+use super::bindings::{self, Found, Lines, Normal};
+use super::buffer::Buffer;
+use super::cmdline::{CommandLine, Input};
+use super::completion::{self, Complete, Completion};
+use super::config::{self, Config};
+use super::editorconfig;
+use super::encoding;
+use super::ex::{self, Address, AddressBase, Ex, ExCommand};
+use super::keymap::{Keymap, Lookup, MapMode, Mapping};
+use super::keys;
 use super::mode::*;
+use super::options::{self, Options, SetScope};
+use super::palette::{Action, Entry, Palette};
+use super::paths;
 use super::row::*;
+use super::save;
 use super::screen::*;
+use super::search::*;
 use super::statusbar::*;
+use super::substitute::*;
+use super::theme::Theme;
+use super::transform::*;
+use super::undo::UndoHistory;
+use super::window::{Direction, Layout, Rect, TabPage, Window};
 // use super::lib::*;
 
 use crossterm::cursor::SetCursorStyle;
@@ -13,14 +33,47 @@ use crossterm::{
     execute, terminal,
 };
 // use std::intrinsics::mir::Move;
-// use std::time::Instant;
 use std::{
-    env,
+    collections::HashMap,
     fs::OpenOptions,
-    io::{stdout, Result, Write},
+    io::{stdout, ErrorKind, Result, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 use text_editor::*;
 
+// the result of the :s command being typed, shown instead of the real rows
+struct SubstitutePreview {
+    // the lines on screen, from line `first` on
+    rows: Vec<Row>,
+    first: usize,
+    highlights: Vec<Highlight>,
+    // changed lines that are off screen, shown in a pane at the bottom
+    pane: Vec<(usize, String)>,
+}
+
+// the buffer hidden behind the q: window, put back when it closes
+#[derive(Debug, Clone)]
+struct HistoryWindow {
+    rows: Vec<Row>,
+    // edits of the history lines aren't changes of the buffer
+    undo_history: UndoHistory,
+    cursor: Position,
+    rowoff: u16,
+    coloff: u16,
+}
+
+// how the text covered by a motion is measured
+enum MotionKind {
+    Exclusive,
+    Inclusive,
+    Linewise,
+}
+
+// how many mappings may expand one another before it counts as recursion
+const MAX_MAP_DEPTH: usize = 1000;
+
 #[derive(Debug, Clone)]
 pub struct Kass {
     current_mode: Mode,
@@ -29,12 +82,17 @@ pub struct Kass {
     key_event: KeyEvent,
     character: char,
 
-    // status_message: String,
-    // status_time: Instant,
+    status_message: String,
+    status_time: Instant,
     quit_kass: bool,
+    // the exit status of the editor, :cq makes it non zero
+    exit_code: u8,
 
     // text: String,
-    command: String,
+    cmdline: CommandLine,
+    history_window: Option<HistoryWindow>,
+    // Tab completion on the command line, cleared by any other key
+    completion: Option<Completion>,
 
     filepath: String,
 
@@ -49,31 +107,140 @@ pub struct Kass {
 
     // cursor position
     cursor: Position,
+    // the other end of the visual selection
+    visual_start: Position,
+    // where a Shift-arrow selection of the modeless preset started
+    selection: Option<Position>,
+    undo_history: UndoHistory,
+    // the other buffers, the current one is in the fields above
+    buffers: Vec<Buffer>,
+    buffer_number: usize,
+    // the highest buffer number given out so far
+    last_buffer: usize,
+    // the buffer before the current one, :b#
+    alternate_buffer: Option<usize>,
+    /* how the windows share the terminal and the ones that aren't current,
+     * the current window is the cursor and view fields above */
+    layout: Layout,
+    windows: Vec<Window>,
+    window_id: usize,
+    // the highest window id given out so far
+    last_window: usize,
+    // the other tab pages, and where the current one goes among them
+    tabs: Vec<TabPage>,
+    tab: usize,
+
+    search: Search,
+    // the pattern being typed after / or ?
+    search_input: String,
+    // where the cursor and view were when the search prompt opened
+    search_origin: (Position, u16, u16),
+    // position in search.history while browsing it with Up / Down
+    search_history_idx: usize,
+
+    // the ends of the last visual selection, the '< and '> marks
+    last_visual: Option<(Position, Position)>,
+    last_substitute: Option<Substitute>,
+    confirm: Option<Confirm>,
+    palette: Option<Palette>,
+
+    options: Options,
+    theme: Theme,
+    config: Config,
+    keymap: Keymap,
+    // typed keys that may still become a mapping, and since when they wait
+    pending_keys: Vec<KeyEvent>,
+    pending_since: Option<Instant>,
+    // since when a prefix like d or <leader> waits, and the keys popped up for it
+    prefix_since: Option<Instant>,
+    which_key: Option<(String, Vec<(String, String)>)>,
+    // how deep mappings are expanding other mappings, E223 past MAX_MAP_DEPTH
+    map_depth: usize,
+    map_aborted: bool,
+    // what was detected when reading the file, it outlives :config reload
+    file_format: Vec<String>,
 
-    number_display: bool,
+    marks: HashMap<char, Position>,
+    // :global is running, it can't be nested
+    in_global: bool,
+    global_substitutions: (usize, usize, bool),
+    // off while :normal runs its keys, the screen is drawn once afterwards
+    redraw: bool,
 
-    normal_mode: NormalMode,
+    // the built-in keys typed so far towards a longer one, like d or <C-w>
+    normal_keys: Vec<KeyEvent>,
     clipboard: Vec<String>,
 
-    terminal_width: usize,
     terminal_height: usize,
 }
 
 impl Kass {
-    pub fn with_file(height: usize, width: usize, filepath: &String) -> Result<Self> {
-        let lines = std::fs::read_to_string(filepath)
-            .expect("Unable to open file")
-            .split('\n')
+    // a file that doesn't exist yet opens as an empty buffer, it is created on :w
+    pub fn with_file(height: usize, width: usize, filepath: &str) -> Result<Self> {
+        let (lines, file_format) = match Kass::read_file(Path::new(filepath)) {
+            Ok(file) => file,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                let mut kass = Kass::open(&[], Vec::new(), height, width, filepath)?;
+                if kass.status_message.is_empty() {
+                    kass.set_message(format!("\"{filepath}\" [New]"));
+                }
+                return Ok(kass);
+            }
+            Err(error) => return Err(error),
+        };
+        Kass::open(&lines, file_format, height, width, filepath)
+    }
+
+    /* the lines of a file and the :setlocal arguments for how it is stored,
+     * its encoding, line endings and whether the last line has one */
+    fn read_file(path: &Path) -> Result<(Vec<String>, Vec<String>)> {
+        let bytes = std::fs::read(path)?;
+        let charset = editorconfig::properties(path).remove("charset");
+        let (text, encoding) = encoding::decode(&bytes, charset.as_deref());
+
+        // the line endings are whichever comes first, they go back the same way
+        let (format, separator) = match text.find(['\n', '\r']) {
+            Some(i) if text[i..].starts_with("\r\n") => ("dos", "\r\n"),
+            Some(i) if text[i..].starts_with('\r') => ("mac", "\r"),
+            _ => ("unix", "\n"),
+        };
+        let end_of_line = text.is_empty() || text.ends_with(separator);
+        let mut lines = text
+            .split(separator)
             .map(|x| x.into())
             .collect::<Vec<String>>();
-        Kass::new(&lines, height, width, filepath)
+        if end_of_line {
+            lines.pop();
+        }
+
+        let file_format = vec![
+            format!("fileencoding={encoding}"),
+            format!("fileformat={format}"),
+            String::from(if end_of_line {
+                "endofline"
+            } else {
+                "noendofline"
+            }),
+        ];
+        Ok((lines, file_format))
+    }
+
+    pub fn new(data: &[String], height: usize, width: usize, filepath: &str) -> Result<Self> {
+        Kass::open(data, Vec::new(), height, width, filepath)
     }
 
-    // constructor
-    pub fn new(data: &[String], height: usize, width: usize, filepath: &String) -> Result<Self> {
-        let statusbar = Statusbar::new(String::from("Normal"), height, width)?;
+    // constructor, `file_format` holds the :setlocal arguments for how the file was stored
+    fn open(
+        data: &[String],
+        file_format: Vec<String>,
+        height: usize,
+        width: usize,
+        filepath: &str,
+    ) -> Result<Self> {
+        let statusbar = Statusbar::new(height, width)?;
+        let rows: Vec<Row> = data.iter().map(|line| Row::new(line.clone())).collect();
 
-        Ok(Kass {
+        let mut kass = Kass {
             current_mode: Mode::Normal,
             mode_changed: false,
             key_event: KeyEvent {
@@ -84,82 +251,122 @@ impl Kass {
             },
             character: 'f',
             statusbar,
-            screen: Screen::new()?,
-            // status_message: String::new(),
-            // status_time: Instant::now(),
-            command: String::from(""),
+            screen: Screen::new(height, width)?,
+            status_message: String::new(),
+            status_time: Instant::now(),
+            cmdline: CommandLine::new(),
+            history_window: None,
+            completion: None,
             quit_kass: false,
-            filepath: String::from(filepath),
+            exit_code: 0,
+            filepath: String::new(),
             absolute_path: String::new(),
             mode: String::from("Normal"),
 
-            rows: if data.is_empty() {
-                Vec::new()
-            } else {
-                let v = Vec::from(data);
-                let mut rows = Vec::new();
-                for row in v {
-                    rows.push(Row::new(row))
-                }
-                if rows.last().unwrap().len() == 0 {
-                    rows.pop();
-                }
-                rows
-            },
+            undo_history: UndoHistory::new(),
+            buffers: Vec::new(),
+            buffer_number: 1,
+            last_buffer: 1,
+            alternate_buffer: None,
+            layout: Layout::Window(1),
+            windows: Vec::new(),
+            window_id: 1,
+            last_window: 1,
+            tabs: Vec::new(),
+            tab: 0,
+            rows,
             rowoff: 0,
             coloff: 0,
             cursor: Position::default(),
+            visual_start: Position::default(),
+            selection: None,
 
-            number_display: false,
+            search: Search::new(),
+            search_input: String::new(),
+            search_origin: (Position::default(), 0, 0),
+            search_history_idx: 0,
+
+            last_visual: None,
+            last_substitute: None,
+            confirm: None,
+            palette: None,
+
+            options: Options::new(),
+            theme: Theme::new(),
+            config: Config::default(),
+            keymap: Keymap::default(),
+            pending_keys: Vec::new(),
+            pending_since: None,
+            prefix_since: None,
+            which_key: None,
+            map_depth: 0,
+            map_aborted: false,
+            file_format,
+
+            marks: HashMap::new(),
+            in_global: false,
+            global_substitutions: (0, 0, false),
+            redraw: true,
 
             clipboard: vec!["".to_string()],
-            normal_mode: NormalMode::Default,
+            normal_keys: Vec::new(),
             terminal_height: height,
-            terminal_width: width,
-        })
+        };
+        kass.set_filepath(filepath);
+        kass.apply_options();
+        kass.load_config(false);
+        kass.apply_modeless();
+        Ok(kass)
     }
-    // get curren directory path
-    fn get_current_working_dir(&mut self) -> String {
-        let res = env::current_dir();
-        match res {
-            Ok(path) => path.into_os_string().into_string().unwrap(),
-            Err(_) => "FAILED".to_string(),
-        }
+    // the buffer is written to `filepath` from now on, an empty one has no name
+    fn set_filepath(&mut self, filepath: &str) {
+        self.filepath = filepath.to_string();
+        self.absolute_path = if filepath.is_empty() {
+            String::from("[No Name]")
+        } else {
+            paths::absolute(Path::new(filepath)).display().to_string()
+        };
     }
+
+    fn modified(&self) -> bool {
+        self.undo_history.modified()
+    }
+
     pub fn run(&mut self) -> Result<()> {
-        self.absolute_path = self.get_current_working_dir() + "/" + &self.filepath;
-        self.statusbar
-            .paint(self.mode.clone(), self.absolute_path.clone())?;
+        self.apply_cursor_style()?;
         self.refresh_screen()?;
 
         loop {
-            if let Event::Key(event) = event::read()? {
-                // set key_event
-                self.key_event = event;
-
-                // set character
-                match event.code {
-                    KeyCode::Char(c) => self.character = c,
-                    _ => {}
-                }
-                self.screen
-                    .move_to(&self.cursor, self.rowoff, self.coloff)?;
-                self.handle_modes()?;
-
-                if !self.mode_changed {
-                    match self.current_mode {
-                        Mode::Insert => {
-                            self.handle_insert_mode()?;
-                        }
-                        Mode::Normal => {
-                            self.handle_normal_mode()?;
-                        }
-                        Mode::Command => {
-                            self.handle_command_mode()?;
-                        }
-                        _ => {}
+            /* keys that may start a longer mapping wait 'timeoutlen' for the next
+             * one, a pending prefix shows what can follow after 'whichkeydelay' */
+            let mapping_due = self
+                .pending_since
+                .filter(|_| self.options.bool("timeout"))
+                .map(|since| {
+                    since + Duration::from_millis(self.options.number("timeoutlen") as u64)
+                });
+            let which_key_due = self
+                .prefix_since
+                .filter(|_| self.which_key.is_none() && self.options.bool("whichkey"))
+                .map(|since| {
+                    since + Duration::from_millis(self.options.number("whichkeydelay") as u64)
+                });
+            if let Some(due) = mapping_due.into_iter().chain(which_key_due).min() {
+                if !event::poll(due.saturating_duration_since(Instant::now()))? {
+                    if mapping_due == Some(due) {
+                        self.resolve_keys(true)?;
+                        self.settle_keys(false)?;
+                    } else {
+                        self.show_which_key()?;
+                    }
+                    if self.quit_kass {
+                        break;
                     }
+                    continue;
                 }
+            }
+            if let Event::Key(event) = event::read()? {
+                self.handle_key(event)?;
 
                 // quit kass
                 if self.quit_kass {
@@ -171,67 +378,294 @@ impl Kass {
         Ok(())
     }
 
-    fn handle_modes(&mut self) -> Result<()> {
-        match self.current_mode {
-            Mode::Normal => match self.key_event {
-                // insert mode
-                KeyEvent {
-                    code: KeyCode::Char('i'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                } => {
-                    self.current_mode = Mode::Insert;
-                    self.mode_changed = true;
-                    self.mode = "Insert".to_string();
+    pub fn exit_code(&self) -> u8 {
+        self.exit_code
+    }
+
+    // runs an ex command from outside, like a -c argument, errors end up on screen
+    pub fn execute(&mut self, line: &str) {
+        if let Err(error) = self.execute_command_line(line) {
+            self.set_message(error);
+        }
+        self.apply_modeless();
+    }
+
+    // a typed key, it goes through the mappings before the current mode sees it
+    pub fn handle_key(&mut self, event: KeyEvent) -> Result<()> {
+        self.pending_keys.push(event);
+        self.resolve_keys(false)?;
+
+        // an insert is one change, in the modeless preset a run of typing is
+        let typing = matches!(event.code, KeyCode::Char(_) | KeyCode::Backspace)
+            && (event.modifiers - KeyModifiers::SHIFT).is_empty();
+        self.settle_keys(typing)
+    }
+
+    // what follows the keys that were run, with `typing` when they were text typed in
+    fn settle_keys(&mut self, typing: bool) -> Result<()> {
+        // what :normal does is a single change, recorded once its command line is done
+        if !self.redraw {
+            return Ok(());
+        }
+        let done = match self.current_mode {
+            Mode::Insert => self.options.bool("modeless") && !typing,
+            _ => true,
+        };
+        self.undo_history.settle(&self.cursor, done);
+
+        if self.apply_modeless() {
+            self.refresh_screen()?;
+            self.apply_cursor_style()?;
+        }
+
+        // once the popup is up it follows every key of a longer prefix
+        match self.pending_prefix() {
+            Some(_) if self.which_key.is_some() => self.show_which_key()?,
+            Some(_) => {
+                self.prefix_since.get_or_insert_with(Instant::now);
+            }
+            None => {
+                self.prefix_since = None;
+                if self.which_key.take().is_some() {
                     self.refresh_screen()?;
-                     execute!(
-                        stdout(),
-                        SetCursorStyle::BlinkingBar,
+                }
+            }
+        }
+        Ok(())
+    }
 
-                    )?; 
-                    
-                     
+    // the keys typed so far towards a longer command, as the popup titles them
+    fn pending_prefix(&self) -> Option<String> {
+        if !matches!(self.current_mode, Mode::Normal | Mode::Visual) {
+            return None;
+        }
+        if !self.pending_keys.is_empty() {
+            return Some(self.pending_keys.iter().map(keys::name).collect());
+        }
+        if !matches!(self.current_mode, Mode::Normal) {
+            return None;
+        }
+        if self.normal_keys.is_empty() {
+            return None;
+        }
+        Some(self.normal_keys.iter().map(keys::name).collect())
+    }
+
+    /* pops up every key that can follow the pending prefix, the mappings first
+     * and then the built-in keys they don't shadow */
+    fn show_which_key(&mut self) -> Result<()> {
+        let Some(prefix) = self.pending_prefix() else {
+            return Ok(());
+        };
+        let mut entries = Vec::new();
+        if !self.pending_keys.is_empty() {
+            let mode = match self.current_mode {
+                Mode::Visual => MapMode::Visual,
+                _ => MapMode::Normal,
+            };
+            entries = self.keymap.continuations(mode, &self.pending_keys);
+            entries.sort();
+        }
+        if let Mode::Normal = self.current_mode {
+            for (keys, description) in bindings::continuations(&prefix) {
+                if !entries.iter().any(|(mapped, _)| *mapped == keys) {
+                    entries.push((keys, description.to_string()));
                 }
-                KeyEvent {
-                    code: KeyCode::Char('a'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                } => {
-                    self.current_mode = Mode::Insert;
+            }
+        }
+        self.which_key = Some((prefix, entries));
+        self.refresh_screen()
+    }
+
+    /* the modeless preset is in insert mode all the time, whatever ends in
+     * normal mode goes back to it. Returns whether the mode changed */
+    fn apply_modeless(&mut self) -> bool {
+        let normal = matches!(self.current_mode, Mode::Normal) && self.normal_keys.is_empty();
+        if !self.options.bool("modeless") || !normal || self.history_window.is_some() {
+            return false;
+        }
+        self.current_mode = Mode::Insert;
+        self.mode = "Insert".to_string();
+        true
+    }
+
+    // u and Ctrl-r, :undo and :redo
+    fn undo(&mut self, redo: bool) {
+        let cursor = if redo {
+            self.undo_history.redo(&mut self.rows, &self.cursor)
+        } else {
+            self.undo_history.undo(&mut self.rows, &self.cursor)
+        };
+        let Some(cursor) = cursor else {
+            self.set_message(if redo {
+                "Already at newest change"
+            } else {
+                "Already at oldest change"
+            });
+            return;
+        };
+        self.cursor = cursor;
+        self.cursor.y = self.cursor.y.min(self.rows.len().saturating_sub(1) as u16);
+        self.clamp_cursor_x();
+        self.selection = None;
+    }
+
+    // the mappings that apply, none while a command waits for its next key
+    fn map_mode(&self) -> Option<MapMode> {
+        match self.current_mode {
+            Mode::Normal if self.normal_keys.is_empty() => Some(MapMode::Normal),
+            Mode::Visual => Some(MapMode::Visual),
+            Mode::Insert => Some(MapMode::Insert),
+            Mode::Command | Mode::Search => Some(MapMode::Command),
+            _ => None,
+        }
+    }
+
+    /* runs the pending keys that can't grow into a longer mapping anymore.
+     * With `flush` nothing waits, the longest mapping there is is used or the
+     * first key goes through as typed */
+    fn resolve_keys(&mut self, flush: bool) -> Result<()> {
+        while let Some(&first) = self.pending_keys.first() {
+            // a recursive mapping drops every key still waiting
+            if self.map_aborted {
+                self.pending_keys.clear();
+                break;
+            }
+            let lookup = match self.map_mode() {
+                Some(mode) => self.keymap.lookup(mode, &self.pending_keys),
+                None => Lookup::None,
+            };
+            let mapping = match lookup {
+                Lookup::Prefix(_) if !flush => {
+                    self.pending_since.get_or_insert_with(Instant::now);
+                    return Ok(());
+                }
+                Lookup::Prefix(mapping) => mapping,
+                Lookup::Match(mapping) => Some(mapping),
+                Lookup::None => None,
+            };
+            match mapping {
+                Some(mapping) => {
+                    self.pending_keys.drain(..mapping.lhs.len());
+                    self.run_mapping(mapping)?;
+                }
+                None => {
+                    self.pending_keys.remove(0);
+                    self.dispatch_key(first)?;
                 }
+            }
+        }
+        self.pending_since = None;
+        Ok(())
+    }
 
-                // visual mode
-                KeyEvent {
-                    code: KeyCode::Char('v'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                } => {
-                    self.current_mode = Mode::Visual;
-                    self.mode = "Visual".to_string();
-                    self.refresh_screen()?;
+    /* the rhs of a mapping in place of its lhs. Unless it is a noremap the
+     * keys can be mappings again, except for the lhs itself at the start */
+    fn run_mapping(&mut self, mapping: Mapping) -> Result<()> {
+        let plain = if mapping.noremap {
+            mapping.rhs.len()
+        } else if mapping.rhs.starts_with(&mapping.lhs) {
+            mapping.lhs.len()
+        } else {
+            0
+        };
+        for key in &mapping.rhs[..plain] {
+            self.dispatch_key(*key)?;
+        }
+        if plain == mapping.rhs.len() {
+            return Ok(());
+        }
+
+        if self.map_depth >= MAX_MAP_DEPTH {
+            self.set_message("E223: Recursive mapping");
+            self.map_aborted = true;
+            return self.refresh_screen();
+        }
+        // the rhs runs on its own, the typed keys after it wait their turn
+        let typed = std::mem::replace(&mut self.pending_keys, mapping.rhs[plain..].to_vec());
+        self.map_depth += 1;
+        let result = self.resolve_keys(true);
+        self.map_depth -= 1;
+        if !self.map_aborted {
+            self.pending_keys = typed;
+        }
+        if self.map_depth == 0 {
+            self.map_aborted = false;
+        }
+        result
+    }
+
+    // runs a single key through the current mode, the terminal isn't needed for this
+    fn dispatch_key(&mut self, event: KeyEvent) -> Result<()> {
+        // set key_event
+        self.key_event = event;
+
+        // listings like :set all stay up until the next key
+        if self.status_message.contains('\n') {
+            self.status_message.clear();
+        }
+
+        // set character
+        self.character = match event.code {
+            KeyCode::Char(c) => c,
+            _ => '\0',
+        };
+        if self.redraw {
+            self.screen
+                .move_to(&self.cursor, self.rowoff, self.coloff, self.current_line())?;
+        }
+        self.handle_modes()?;
+
+        if !self.mode_changed {
+            match self.current_mode {
+                Mode::Insert => {
+                    self.handle_insert_mode()?;
                 }
+                Mode::Normal => {
+                    self.handle_normal_mode()?;
+                }
+                Mode::Command => {
+                    self.handle_command_mode()?;
+                }
+                Mode::Visual => {
+                    self.handle_visual_mode()?;
+                }
+                Mode::Search => {
+                    self.handle_search_mode()?;
+                }
+                Mode::Confirm => {
+                    self.handle_confirm_mode()?;
+                }
+                Mode::Palette => {
+                    self.handle_palette_mode()?;
+                }
+            }
+        }
 
-                // command mode
+        Ok(())
+    }
+
+    fn handle_modes(&mut self) -> Result<()> {
+        match self.current_mode {
+            Mode::Search => match self.key_event {
                 KeyEvent {
-                    code: KeyCode::Char(':'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
+                    code: KeyCode::Esc, ..
                 } => {
-                    self.current_mode = Mode::Command;
-                    self.mode = "Command".to_string();
+                    // throw the incremental search away
+                    (self.cursor, self.rowoff, self.coloff) = self.search_origin.clone();
+                    self.current_mode = Mode::Normal;
+                    self.mode_changed = true;
+                    self.mode = "Normal".to_string();
                     self.refresh_screen()?;
                 }
-                _ => {
-                    self.mode_changed = false;
-                }
+                _ => self.mode_changed = false,
             },
 
             Mode::Command => match self.key_event {
                 KeyEvent {
                     code: KeyCode::Esc, ..
                 } => {
-                    self.command = String::from(":");
-                    // self.refresh_screen()?;
+                    self.cmdline.start("");
                     self.current_mode = Mode::Normal;
                     self.mode_changed = true;
                     self.mode = "Normal".to_string();
@@ -241,19 +675,29 @@ impl Kass {
                 _ => self.mode_changed = false,
             },
 
+            // Esc closes the palette in handle_palette_mode
+            Mode::Palette => self.mode_changed = false,
+
+            // the command line works on the lines of the selection
+            Mode::Visual if self.key_event.code == KeyCode::Char(':') => {
+                self.last_visual = Some(self.visual_range());
+                self.current_mode = Mode::Command;
+                self.mode_changed = true;
+                self.mode = "Command".to_string();
+                self.cmdline.start("'<,'>");
+                self.refresh_screen()?;
+            }
+
             _ => match self.key_event {
                 KeyEvent {
                     code: KeyCode::Esc, ..
                 } => {
                     self.current_mode = Mode::Normal;
+                    self.normal_keys.clear();
                     self.mode_changed = true;
                     self.mode = "Normal".to_string();
                     self.refresh_screen()?;
-                    execute!(
-                        stdout(),
-                        SetCursorStyle::DefaultUserShape,
-
-                    )?; 
+                    self.apply_cursor_style()?;
                 }
                 _ => self.mode_changed = false,
             },
@@ -262,251 +706,3023 @@ impl Kass {
         Ok(())
     }
 
+    fn start_search(&mut self, forward: bool) -> Result<()> {
+        self.current_mode = Mode::Search;
+        self.mode_changed = true;
+        self.mode = "Search".to_string();
+        self.search.forward = forward;
+        self.search_input = String::new();
+        self.search_origin = (self.cursor.clone(), self.rowoff, self.coloff);
+        self.search_history_idx = self.search.history.len();
+        self.refresh_screen()
+    }
+
     fn handle_normal_mode(&mut self) -> Result<()> {
-        match self.normal_mode {
-            NormalMode::Default => match self.key_event {
-                KeyEvent {
-                    code: KeyCode::Char(key),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                } => match key {
-                    'h' => self.move_cursor(MovementKey::Left),
-                    'l' => self.move_cursor(MovementKey::Right),
-                    'j' => self.move_cursor(MovementKey::Down),
-                    'k' => self.move_cursor(MovementKey::Up),
-
-                    'd' => self.normal_mode = NormalMode::Cut,
-                    'y' => self.normal_mode = NormalMode::Copy,
-                    'p' => {
-                        self.paste();
-                        self.refresh_screen()?;
-                    }
-                    _ => {}
-                },
-                KeyEvent { code, .. } => match code {
-                    KeyCode::Left => self.move_cursor(MovementKey::Left),
-                    KeyCode::Right => self.move_cursor(MovementKey::Right),
-                    KeyCode::Down => self.move_cursor(MovementKey::Down),
-                    KeyCode::Up => self.move_cursor(MovementKey::Up),
+        // Enter in the q: window runs the line under the cursor
+        if self.key_event.code == KeyCode::Enter
+            && self.normal_keys.is_empty()
+            && self.history_window.is_some()
+        {
+            let line = self.rows[self.cursor.row()].chars.clone();
+            self.close_history_window();
+            self.cmdline.remember(&line);
+            if let Err(error) = self.execute_command_line(&line) {
+                self.set_message(error);
+            }
+            return self.refresh_screen();
+        }
 
-                    _ => {}
-                },
-            },
-            NormalMode::Cut => match self.key_event {
-                KeyEvent {
-                    code: KeyCode::Char('d'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                } => {
-                    if self.rows.len() != 0 {
-                        self.clipboard[0] = self.rows[self.cursor.y as usize].chars.clone();
-                        self.rows.remove(self.cursor.y as usize);
-                    }
+        let mut key = self.key_event;
+        if self
+            .normal_keys
+            .first()
+            .is_some_and(|first| keys::name(first) == "<C-w>")
+        {
+            // Ctrl-w Ctrl-v is Ctrl-w v and the arrows are h, j, k and l
+            key.code = match key.code {
+                KeyCode::Left => KeyCode::Char('h'),
+                KeyCode::Down => KeyCode::Char('j'),
+                KeyCode::Up => KeyCode::Char('k'),
+                KeyCode::Right => KeyCode::Char('l'),
+                code => code,
+            };
+            key.modifiers -= KeyModifiers::CONTROL;
+        }
+        if key.modifiers.contains(KeyModifiers::ALT) {
+            self.normal_keys.clear();
+            return Ok(());
+        }
+        self.normal_keys.push(key);
 
-                    if self.clipboard.len() > 1 {
-                        self.clipboard.remove(1);
-                    }
+        match bindings::lookup(&self.normal_keys) {
+            Found::Prefix => Ok(()),
+            Found::None => {
+                self.normal_keys.clear();
+                Ok(())
+            }
+            Found::Binding(binding) => {
+                self.normal_keys.clear();
+                self.run_normal(binding.action.clone())
+            }
+            Found::Command(command) => {
+                self.normal_keys.clear();
+                self.run_normal(Normal::Command(command.name))
+            }
+        }
+    }
 
-                    self.cursor.y = if self.cursor.above(self.rows.len()) || self.rows.len() == 0 {
-                        self.cursor.y
-                    } else {
-                        self.cursor.y - 1
-                    };
-                    self.refresh_screen()?;
-                    self.normal_mode = NormalMode::Default;
+    // what a built-in key of normal mode does, the keys are in bindings::NORMAL
+    fn run_normal(&mut self, action: Normal) -> Result<()> {
+        match action {
+            Normal::Move(key) => self.move_cursor(key),
+            Normal::Motion => {
+                if let Some((target, _)) = self.resolve_motion(&self.key_event) {
+                    self.cursor = target;
                 }
-                KeyEvent {
-                    code: KeyCode::Char('j'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                } => {
-                    if self.cursor.above(self.rows.len()) {
-                        self.clipboard[0] = self.rows[self.cursor.y as usize].chars.clone();
-
-                        if self.clipboard.len() > 1 {
-                            self.clipboard.remove(1);
-                        }
-
-                        self.clipboard
-                            .push(self.rows[self.cursor.y as usize + 1].chars.clone());
+                self.refresh_screen()?;
+            }
+            Normal::ScreenLine(pos) => self.cursor_to_screen_line(pos)?,
 
-                        self.rows.remove(self.cursor.y as usize);
-                        self.rows.remove(self.cursor.y as usize);
-                        self.refresh_screen()?;
+            Normal::Insert(key) => {
+                let y = self.cursor.row();
+                let line_len = self.rows.get(y).map_or(0, |row| row.len());
+                match key {
+                    'a' => self.cursor.x = (self.cursor.x + 1).min(line_len as u16),
+                    'A' => self.cursor.x = line_len as u16,
+                    'I' => {
+                        let line = self.rows.get(y).map_or("", |row| row.chars.as_str());
+                        self.cursor.x = (line.len() - line.trim_start().len()) as u16;
                     }
-
-                    self.normal_mode = NormalMode::Default;
+                    'o' if y < self.rows.len() => {
+                        self.insert_row(y + 1, String::new());
+                        self.cursor = Position {
+                            x: 0,
+                            y: y as u16 + 1,
+                        };
+                    }
+                    'O' => {
+                        self.insert_row(y.min(self.rows.len()), String::new());
+                        self.cursor.x = 0;
+                    }
+                    _ => {}
                 }
-                KeyEvent {
-                    code: KeyCode::Char('k'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                } => {
-                    if self.cursor.above(self.rows.len()) && self.cursor.y > 0 {
-                        self.clipboard[0] = self.rows[self.cursor.y as usize - 1].chars.clone();
 
-                        if self.clipboard.len() > 1 {
-                            self.clipboard.remove(1);
-                        }
+                self.current_mode = Mode::Insert;
+                self.mode_changed = true;
+                self.mode = "Insert".to_string();
+                self.refresh_screen()?;
+                self.apply_cursor_style()?;
+            }
+            Normal::Visual => {
+                self.current_mode = Mode::Visual;
+                self.mode_changed = true;
+                self.mode = "Visual".to_string();
+                self.visual_start = self.cursor.clone();
+                self.refresh_screen()?;
+            }
+            Normal::CommandLine => {
+                self.current_mode = Mode::Command;
+                self.mode_changed = true;
+                self.mode = "Command".to_string();
+                self.cmdline.start("");
+                self.refresh_screen()?;
+            }
+            Normal::Search(forward) => self.start_search(forward)?,
+            Normal::NextMatch(same) => {
+                let forward = self.search.forward == same;
+                self.search_next(forward)?;
+            }
+            Normal::SearchWord(forward, whole) => self.search_word(forward, whole)?,
 
-                        self.clipboard
-                            .push(self.rows[self.cursor.y as usize].chars.clone());
+            Normal::Join(smart) => {
+                self.join_lines(self.cursor.row(), 2, smart);
+                self.refresh_screen()?;
+            }
+            Normal::ToggleCase => {
+                let end = Position {
+                    x: self.cursor.x + 1,
+                    y: self.cursor.y,
+                };
+                self.change_case(&self.cursor.clone(), &end, &CaseOp::Toggle);
+                self.move_cursor(MovementKey::Right);
+            }
+            Normal::Paste => {
+                self.paste();
+                self.refresh_screen()?;
+            }
+            Normal::Delete(lines) => self.cut_lines(lines, true)?,
+            Normal::Yank(lines) => self.cut_lines(lines, false)?,
 
-                        self.rows.remove(self.cursor.y as usize);
-                        self.rows.remove(self.cursor.y as usize - 1);
+            Normal::AlignLine(pos) => self.align_cursor_line(pos)?,
+            Normal::AlignColumn(pos) => self.align_cursor_column(pos)?,
+            Normal::ScrollColumns(columns) => self.scroll_columns(columns)?,
 
-                        self.cursor.y -= 1;
-                        self.refresh_screen()?;
+            Normal::Case(op) => {
+                if let Some((target, kind)) = self.resolve_motion(&self.key_event) {
+                    self.case_motion(&op, target, kind)?;
+                }
+            }
+            // doubling the operator key (g~~, guu, gUU) works on the whole line
+            Normal::CaseLine(op) => {
+                self.case_motion(&op, self.cursor.clone(), MotionKind::Linewise)?
+            }
+            Normal::Tab(next) => {
+                if let Err(error) = self.cycle_tab(next) {
+                    self.set_message(error);
+                }
+                self.refresh_screen()?;
+            }
+            Normal::Mark => {
+                if let KeyCode::Char(mark @ 'a'..='z') = self.key_event.code {
+                    self.marks.insert(mark, self.cursor.clone());
+                }
+            }
+            Normal::Jump(exact) => {
+                if let KeyCode::Char(mark) = self.key_event.code {
+                    match self.mark_position(mark) {
+                        Some(position) if position.row() < self.rows.len() => {
+                            self.cursor = Position {
+                                x: if exact { position.x } else { 0 },
+                                y: position.y,
+                            };
+                            self.clamp_cursor_x();
+                        }
+                        _ => self.set_message("E20: Mark not set"),
                     }
-
-                    self.normal_mode = NormalMode::Default;
+                    self.refresh_screen()?;
                 }
-                _ => {
-                    self.normal_mode = NormalMode::Default;
+            }
+            Normal::HistoryWindow => {
+                self.open_history_window();
+                self.refresh_screen()?;
+            }
+            Normal::Command(line) => {
+                if let Err(error) = self.execute_command_line(line) {
+                    self.set_message(error);
                 }
-            },
-            NormalMode::Copy => match self.key_event {
-                KeyEvent {
-                    code: KeyCode::Char('y'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                } => {
-                    if self.rows.len() != 0 {
-                        self.clipboard[0] = self.rows[self.cursor.y as usize].chars.clone();
+                self.refresh_screen()?;
+            }
+
+            Normal::ScrollPage(pages) => self.scroll_page(pages)?,
+            Normal::ScrollHalfPage(halves) => self.scroll_half_page(halves)?,
+            // the cursor stays put unless it leaves the screen
+            Normal::ScrollLines(lines) => self.scroll_lines(lines)?,
+            Normal::Increment(delta) => self.increment_at_cursor(delta)?,
+            Normal::Window(key) => {
+                if let Err(error) = self.window_command(key) {
+                    self.set_message(error);
+                }
+                self.refresh_screen()?;
+            }
+        }
+        Ok(())
+    }
+
+    // dd, dj and dk take the lines out, yy, yj and yk only copy them
+    fn cut_lines(&mut self, lines: Lines, delete: bool) -> Result<()> {
+        let y = self.cursor.y as usize;
+        match lines {
+            Lines::Current => {
+                if !self.rows.is_empty() {
+                    self.clipboard[0] = self.rows[y].chars.clone();
+                    if delete {
+                        self.splice_rows(y..y + 1, Vec::new());
                     }
+                }
+
+                if self.clipboard.len() > 1 {
+                    self.clipboard.remove(1);
+                }
+
+                self.cursor.y = if self.cursor.above(self.rows.len()) || self.rows.is_empty() {
+                    self.cursor.y
+                } else {
+                    self.cursor.y - 1
+                };
+                self.refresh_screen()?;
+            }
+            Lines::Below => {
+                if self.cursor.above(self.rows.len()) {
+                    self.clipboard[0] = self.rows[y].chars.clone();
 
                     if self.clipboard.len() > 1 {
                         self.clipboard.remove(1);
                     }
 
-                    self.cursor.y = if self.cursor.above(self.rows.len()) || self.rows.len() == 0 {
-                        self.cursor.y
-                    } else {
-                        self.cursor.y - 1
-                    };
+                    self.clipboard.push(self.rows[y + 1].chars.clone());
+
+                    if delete {
+                        self.splice_rows(y..y + 2, Vec::new());
+                    }
                     self.refresh_screen()?;
-                    self.normal_mode = NormalMode::Default;
                 }
-                KeyEvent {
-                    code: KeyCode::Char('j'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                } => {
-                    if self.cursor.above(self.rows.len()) {
-                        self.clipboard[0] = self.rows[self.cursor.y as usize].chars.clone();
+            }
+            Lines::Above => {
+                if self.cursor.above(self.rows.len()) && self.cursor.y > 0 {
+                    self.clipboard[0] = self.rows[y - 1].chars.clone();
 
-                        if self.clipboard.len() > 1 {
-                            self.clipboard.remove(1);
-                        }
+                    if self.clipboard.len() > 1 {
+                        self.clipboard.remove(1);
+                    }
 
-                        self.clipboard
-                            .push(self.rows[self.cursor.y as usize + 1].chars.clone());
+                    self.clipboard.push(self.rows[y].chars.clone());
 
-                        self.refresh_screen()?;
+                    if delete {
+                        self.splice_rows(y - 1..y + 1, Vec::new());
+                        self.cursor.y -= 1;
                     }
+                    self.refresh_screen()?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // g~{motion}, gu{motion} and gU{motion}, the cursor ends up at the start of the changed text
+    fn case_motion(&mut self, op: &CaseOp, target: Position, kind: MotionKind) -> Result<()> {
+        let linewise = matches!(kind, MotionKind::Linewise);
+        let (start, end) = self.motion_range(&self.cursor.clone(), &target, kind);
+        self.change_case(&start, &end, op);
+
+        self.cursor.y = start.y;
+        if !linewise {
+            self.cursor.x = start.x;
+        }
+        self.clamp_cursor_x();
+        self.refresh_screen()
+    }
+
+    //cursor handler
+
+    fn move_cursor(&mut self, key: MovementKey) {
+        match key {
+            MovementKey::Left => {
+                if self.cursor.x != 0 {
+                    self.cursor.x -= 1;
+                } else if self.cursor.y > 0 {
+                    self.cursor.y -= 1;
+                    self.cursor.x = self.rows[self.cursor.y as usize].len() as u16;
+                }
+            }
 
-                    self.normal_mode = NormalMode::Default;
+            MovementKey::Right if self.cursor.y < self.rows.len() as u16 => {
+                let idx = self.cursor.y;
+
+                // checks whether cursor exceeds rows length or not
+                if self.cursor.x < self.rows[idx as usize].len() as u16 {
+                    self.cursor.x += 1;
+                } else if self.cursor.y < self.rows.len() as u16 - 1 {
+                    self.cursor.y += 1;
+                    self.cursor.x = 0;
                 }
-                KeyEvent {
-                    code: KeyCode::Char('k'),
-                    modifiers: KeyModifiers::NONE,
-                    ..
-                } => {
-                    if self.cursor.above(self.rows.len()) && self.cursor.y > 0 {
-                        self.clipboard[0] = self.rows[self.cursor.y as usize - 1].chars.clone();
+            }
 
-                        if self.clipboard.len() > 1 {
-                            self.clipboard.remove(1);
-                        }
+            MovementKey::Up => self.cursor.y = self.cursor.y.saturating_sub(1),
+            MovementKey::Down if self.cursor.y + 1 < self.rows.len() as u16 => self.cursor.y += 1,
+            _ => {}
+        }
+
+        // for clamping the cursor to the front of the line after the end of the previous line
+        let rowlen = if self.cursor.y as usize >= self.rows.len() {
+            0
+        } else {
+            self.rows[self.cursor.y as usize].len() as u16
+        };
 
-                        self.clipboard
-                            .push(self.rows[self.cursor.y as usize].chars.clone());
+        // compare length of the row and cursor x position and gives min value between them
+        self.cursor.x = self.cursor.x.min(rowlen);
 
-                        self.refresh_screen()?;
-                    }
+        self.refresh_screen()
+            .expect("not working refresh screen in move cursor function");
+    }
+
+    fn scroll(&mut self) -> Result<()> {
+        let bounds = self.screen.boundary();
+        let so = self.effective_scrolloff();
+
+        // for vertical scrolling, keeping `scrolloff` lines of context around the cursor
+        if self.cursor.y < self.rowoff + so {
+            self.rowoff = self.cursor.y.saturating_sub(so);
+        }
+        // no context is needed below the last line
+        let so_below = so.min(self.last_row().saturating_sub(self.cursor.y));
+        if self.cursor.y + so_below >= self.rowoff + bounds.y {
+            self.rowoff = self.cursor.y + so_below + 1 - bounds.y;
+        }
+
+        // for horizontal scrolling
+        if self.cursor.x < self.coloff {
+            self.coloff = self.cursor.x;
+        }
+        if self.cursor.x >= self.coloff + bounds.x {
+            self.coloff = self.cursor.x - bounds.x + 1;
+        }
+
+        self.screen.clear()?;
+        Ok(())
+    }
+
+    // motions
+
+    fn char_class(c: u8) -> u8 {
+        if c.is_ascii_whitespace() {
+            0
+        } else if c.is_ascii_alphanumeric() || c == b'_' || !c.is_ascii() {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn line_bytes(&self, y: u16) -> &[u8] {
+        self.rows
+            .get(y as usize)
+            .map(|row| row.chars.as_bytes())
+            .unwrap_or_default()
+    }
+
+    // w: start of the next word, an empty line also counts as a word
+    fn word_forward(&self, from: &Position) -> Position {
+        let (mut x, mut y) = (from.x as usize, from.y);
+        let mut line = self.line_bytes(y);
+
+        if x < line.len() {
+            let class = Self::char_class(line[x]);
+            while x < line.len() && class != 0 && Self::char_class(line[x]) == class {
+                x += 1;
+            }
+        }
+
+        loop {
+            while x < line.len() && Self::char_class(line[x]) == 0 {
+                x += 1;
+            }
+            if x < line.len() || y + 1 >= self.rows.len() as u16 {
+                break;
+            }
+            y += 1;
+            x = 0;
+            line = self.line_bytes(y);
+            if line.is_empty() {
+                break;
+            }
+        }
+
+        Position { x: x as u16, y }
+    }
+
+    // b: start of the current or previous word
+    fn word_backward(&self, from: &Position) -> Position {
+        let (mut x, mut y) = (from.x as usize, from.y);
+        let mut line = self.line_bytes(y);
+
+        loop {
+            if x == 0 {
+                if y == 0 {
+                    return Position { x: 0, y: 0 };
+                }
+                y -= 1;
+                line = self.line_bytes(y);
+                x = line.len();
+                if line.is_empty() {
+                    return Position { x: 0, y };
+                }
+            }
+            x -= 1;
+            if Self::char_class(line[x]) != 0 {
+                break;
+            }
+        }
+
+        let class = Self::char_class(line[x]);
+        while x > 0 && Self::char_class(line[x - 1]) == class {
+            x -= 1;
+        }
+
+        Position { x: x as u16, y }
+    }
+
+    // e: end of the current or next word
+    fn word_end(&self, from: &Position) -> Position {
+        let (mut x, mut y) = (from.x as usize + 1, from.y);
+        let mut line = self.line_bytes(y);
+
+        loop {
+            while x < line.len() && Self::char_class(line[x]) == 0 {
+                x += 1;
+            }
+            if x < line.len() {
+                break;
+            }
+            if y + 1 >= self.rows.len() as u16 {
+                return Position {
+                    x: line.len().saturating_sub(1) as u16,
+                    y,
+                };
+            }
+            y += 1;
+            x = 0;
+            line = self.line_bytes(y);
+        }
+
+        let class = Self::char_class(line[x]);
+        while x + 1 < line.len() && Self::char_class(line[x + 1]) == class {
+            x += 1;
+        }
+
+        Position { x: x as u16, y }
+    }
+
+    // the target of a motion key, used by operators and visual mode
+    fn resolve_motion(&self, event: &KeyEvent) -> Option<(Position, MotionKind)> {
+        let cursor = &self.cursor;
+        let rowlen = self.line_bytes(cursor.y).len() as u16;
+
+        let key = match event.code {
+            KeyCode::Char(c)
+                if event.modifiers == KeyModifiers::NONE
+                    || event.modifiers == KeyModifiers::SHIFT =>
+            {
+                c
+            }
+            KeyCode::Left => 'h',
+            KeyCode::Right => 'l',
+            KeyCode::Down => 'j',
+            KeyCode::Up => 'k',
+            KeyCode::Home => '0',
+            KeyCode::End => '$',
+            _ => return None,
+        };
+
+        let motion = match key {
+            'h' => (
+                Position {
+                    x: cursor.x.saturating_sub(1),
+                    y: cursor.y,
+                },
+                MotionKind::Exclusive,
+            ),
+            'l' => (
+                Position {
+                    x: (cursor.x + 1).min(rowlen),
+                    y: cursor.y,
+                },
+                MotionKind::Exclusive,
+            ),
+            'j' => (
+                Position {
+                    x: cursor.x,
+                    y: (cursor.y + 1).min(self.last_row()),
+                },
+                MotionKind::Linewise,
+            ),
+            'k' => (
+                Position {
+                    x: cursor.x,
+                    y: cursor.y.saturating_sub(1),
+                },
+                MotionKind::Linewise,
+            ),
+            '0' => (Position { x: 0, y: cursor.y }, MotionKind::Exclusive),
+            '$' => (
+                Position {
+                    x: rowlen.saturating_sub(1),
+                    y: cursor.y,
+                },
+                MotionKind::Inclusive,
+            ),
+            'w' => (self.word_forward(cursor), MotionKind::Exclusive),
+            'b' => (self.word_backward(cursor), MotionKind::Exclusive),
+            'e' => (self.word_end(cursor), MotionKind::Inclusive),
+            _ => return None,
+        };
+
+        Some(motion)
+    }
+
+    // turns a motion into an ordered [start, end) range of text
+    fn motion_range(
+        &self,
+        from: &Position,
+        to: &Position,
+        kind: MotionKind,
+    ) -> (Position, Position) {
+        let (start, end) = if (to.y, to.x) < (from.y, from.x) {
+            (to.clone(), from.clone())
+        } else {
+            (from.clone(), to.clone())
+        };
+
+        match kind {
+            MotionKind::Exclusive => (start, end),
+            MotionKind::Inclusive => (
+                start,
+                Position {
+                    x: end.x + 1,
+                    y: end.y,
+                },
+            ),
+            MotionKind::Linewise => (
+                Position { x: 0, y: start.y },
+                Position {
+                    x: self.line_bytes(end.y).len() as u16,
+                    y: end.y,
+                },
+            ),
+        }
+    }
+
+    // the visual selection as an ordered [start, end) range
+    fn visual_range(&self) -> (Position, Position) {
+        self.motion_range(
+            &self.visual_start.clone(),
+            &self.cursor.clone(),
+            MotionKind::Inclusive,
+        )
+    }
+
+    fn selection_highlights(&self) -> Vec<Highlight> {
+        let mut highlights = Vec::new();
+        let range = match self.current_mode {
+            Mode::Visual => Some(self.visual_range()),
+            Mode::Insert => self.selection_range(),
+            _ => None,
+        };
+        if let Some((start, end)) = range {
+            for y in start.y..=end.y {
+                let len = self.line_bytes(y).len();
+                highlights.push(Highlight {
+                    row: y as usize,
+                    start: if y == start.y { start.x as usize } else { 0 },
+                    end: if y == end.y {
+                        (end.x as usize).min(len)
+                    } else {
+                        len
+                    },
+                    kind: HighlightKind::Selection,
+                });
+            }
+        }
+        highlights
+    }
+
+    // text transformations
+
+    // the byte range of row `y` covered by the [start, end) range
+    fn range_on_row(&self, y: u16, start: &Position, end: &Position) -> (usize, usize) {
+        let len = self.line_bytes(y).len();
+        let from = if y == start.y { start.x as usize } else { 0 };
+        let to = if y == end.y { end.x as usize } else { len };
+        (from.min(len), to.min(len))
+    }
+
+    fn change_case(&mut self, start: &Position, end: &Position, op: &CaseOp) {
+        for y in start.y..=end.y.min(self.last_row()) {
+            let (from, to) = self.range_on_row(y, start, end);
+            let line = &self.rows[y as usize].chars;
+
+            if let Some(text) = line.get(from..to) {
+                let changed = format!("{}{}{}", &line[..from], change_case(text, op), &line[to..]);
+                self.set_line(y as usize, changed);
+            }
+        }
+    }
+
+    // J and gJ, joins `count` lines starting at `at`
+    fn join_lines(&mut self, at: usize, count: usize, smart: bool) {
+        let comments = self.options.string("comments");
+        for _ in 1..count.max(2) {
+            if at + 1 >= self.rows.len() {
+                break;
+            }
+            let (joined, join_at) = join_lines(
+                &self.rows[at].chars,
+                &self.rows[at + 1].chars,
+                smart,
+                &comments,
+            );
+
+            self.splice_rows(at..at + 2, vec![joined]);
+            self.cursor = Position {
+                x: join_at as u16,
+                y: at as u16,
+            };
+        }
+    }
 
-                    self.normal_mode = NormalMode::Default;
+    // Ctrl-a / Ctrl-x
+    fn increment_at_cursor(&mut self, delta: i64) -> Result<()> {
+        if !self.cursor.above(self.rows.len()) {
+            return Ok(());
+        }
+        let line = &self.rows[self.cursor.row()].chars;
+
+        if let Some((changed, last)) =
+            increment_number(line, self.cursor.x as usize, line.len(), delta)
+        {
+            self.set_line(self.cursor.row(), changed);
+            self.cursor.x = last as u16;
+        }
+        self.refresh_screen()
+    }
+
+    /* Ctrl-a / Ctrl-x on a selection, the first number of every line is changed.
+     * With `progressive` (g Ctrl-a) the n-th changed line is changed by n * delta */
+    fn increment_selection(&mut self, delta: i64, progressive: bool) {
+        let (start, end) = self.visual_range();
+        let mut step = 1;
+
+        for y in start.y..=end.y.min(self.last_row()) {
+            let (from, to) = self.range_on_row(y, &start, &end);
+            let amount = if progressive { delta * step } else { delta };
+
+            if let Some((changed, _)) =
+                increment_number(&self.rows[y as usize].chars, from, to, amount)
+            {
+                self.set_line(y as usize, changed);
+                step += 1;
+            }
+        }
+        self.cursor = start;
+    }
+
+    // handle visual mode
+    fn handle_visual_mode(&mut self) -> Result<()> {
+        let pending_g = std::mem::take(&mut self.normal_keys)
+            .first()
+            .is_some_and(|key| key.code == KeyCode::Char('g'));
+
+        let (start, end) = self.visual_range();
+        self.last_visual = Some((start.clone(), end.clone()));
+        let mut done = true;
+
+        match self.key_event {
+            KeyEvent {
+                code: KeyCode::Char(key),
+                modifiers: KeyModifiers::CONTROL,
+                ..
+            } if key == 'a' || key == 'x' => {
+                let delta = if key == 'a' { 1 } else { -1 };
+                self.increment_selection(delta, pending_g);
+            }
+            KeyEvent {
+                code: KeyCode::Char(key),
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                ..
+            } => match key {
+                'g' if !pending_g => {
+                    self.normal_keys.push(self.key_event);
+                    done = false;
+                }
+                '~' | 'u' | 'U' => {
+                    let op = match key {
+                        '~' => CaseOp::Toggle,
+                        'u' => CaseOp::Lower,
+                        _ => CaseOp::Upper,
+                    };
+                    self.change_case(&start, &end, &op);
+                    self.cursor = start;
+                }
+                'J' => {
+                    let count = (end.y - start.y + 1) as usize;
+                    self.join_lines(start.row(), count, !pending_g);
+                }
+                'o' => {
+                    std::mem::swap(&mut self.cursor, &mut self.visual_start);
+                    done = false;
+                }
+                // a search matches within a line, so a selection over several can't be found
+                '*' | '#' if start.y != end.y => {
+                    self.set_message("Can't search for a selection of more than one line");
+                }
+                '*' | '#' => {
+                    let (from, to) = self.range_on_row(start.y, &start, &end);
+                    let text = &self.rows[start.row()].chars[from..to];
+
+                    self.cursor = start;
+                    self.search.forward = key == '*';
+                    self.search.remember(&regex::escape(text));
+                    self.search_next(self.search.forward)?;
                 }
                 _ => {
-                    self.normal_mode = NormalMode::Default;
+                    if let Some((target, _)) = self.resolve_motion(&self.key_event) {
+                        self.cursor = target;
+                    }
+                    done = false;
                 }
             },
+            _ => {
+                if let Some((target, _)) = self.resolve_motion(&self.key_event) {
+                    self.cursor = target;
+                }
+                done = false;
+            }
+        }
+
+        if done {
+            self.current_mode = Mode::Normal;
+            self.mode = "Normal".to_string();
+        }
+        self.clamp_cursor_x();
+        self.refresh_screen()
+    }
+
+    // search
+
+    fn handle_search_mode(&mut self) -> Result<()> {
+        match self.key_event.code {
+            KeyCode::Enter => {
+                let pattern = if self.search_input.is_empty() {
+                    // an empty pattern repeats the last search
+                    self.search.pattern.clone()
+                } else {
+                    self.search_input.clone()
+                };
+
+                self.current_mode = Mode::Normal;
+                self.mode = "Normal".to_string();
+                (self.cursor, self.rowoff, self.coloff) = self.search_origin.clone();
+
+                if !pattern.is_empty() {
+                    self.search.remember(&pattern);
+                    self.search_next(self.search.forward)?;
+                }
+                return self.refresh_screen();
+            }
+            // backspace on an empty prompt cancels the search
+            KeyCode::Backspace if self.search_input.is_empty() => {
+                (self.cursor, self.rowoff, self.coloff) = self.search_origin.clone();
+                self.current_mode = Mode::Normal;
+                self.mode = "Normal".to_string();
+                return self.refresh_screen();
+            }
+            KeyCode::Backspace => {
+                self.search_input.pop();
+            }
+            KeyCode::Up => {
+                self.search_history_idx = self.search_history_idx.saturating_sub(1);
+                if let Some(entry) = self.search.history.get(self.search_history_idx) {
+                    self.search_input = entry.clone();
+                }
+            }
+            KeyCode::Down => {
+                self.search_history_idx =
+                    (self.search_history_idx + 1).min(self.search.history.len());
+                self.search_input = self
+                    .search
+                    .history
+                    .get(self.search_history_idx)
+                    .cloned()
+                    .unwrap_or_default();
+            }
+            KeyCode::Char(c) if !c.is_control() => self.search_input.push(c),
+            _ => {}
+        }
+
+        if self.options.bool("incsearch") {
+            self.incremental_search();
+        }
+        self.refresh_screen()
+    }
+
+    // moves the cursor to the first match of the pattern being typed
+    fn incremental_search(&mut self) {
+        let (origin, rowoff, coloff) = self.search_origin.clone();
+        (self.cursor, self.rowoff, self.coloff) = (origin.clone(), rowoff, coloff);
+
+        if let Some(regex) = self.search.compile(&self.search_input) {
+            if let Some((found, _)) = find_from(&regex, &self.rows, &origin, self.search.forward) {
+                self.cursor = Position {
+                    x: found.start as u16,
+                    y: found.row as u16,
+                };
+            }
+        }
+    }
+
+    // n / N
+    fn search_next(&mut self, forward: bool) -> Result<()> {
+        let pattern = self.search.pattern.clone();
+        if pattern.is_empty() {
+            self.set_message("E35: No previous regular expression");
+            return self.refresh_screen();
+        }
+        let Some(regex) = self.search.compile(&pattern) else {
+            self.set_message(format!("E383: Invalid search string: {pattern}"));
+            return self.refresh_screen();
+        };
+        self.search.highlight = true;
+
+        match find_from(&regex, &self.rows, &self.cursor, forward) {
+            Some((_, true)) if !self.options.bool("wrapscan") => {
+                self.set_message(if forward {
+                    format!("E385: Search hit BOTTOM without match for: {pattern}")
+                } else {
+                    format!("E384: Search hit TOP without match for: {pattern}")
+                });
+            }
+            Some((found, wrapped)) => {
+                self.cursor = Position {
+                    x: found.start as u16,
+                    y: found.row as u16,
+                };
+                if wrapped && forward {
+                    self.set_message("search hit BOTTOM, continuing at TOP");
+                } else if wrapped {
+                    self.set_message("search hit TOP, continuing at BOTTOM");
+                } else {
+                    let prompt = if forward { '/' } else { '?' };
+                    self.set_message(format!("{prompt}{pattern}"));
+                }
+            }
+            None => self.set_message(format!("E486: Pattern not found: {pattern}")),
+        }
+        self.refresh_screen()
+    }
+
+    // * and # (g* and g# when `whole` is false), searches for the word under the cursor
+    fn search_word(&mut self, forward: bool, whole: bool) -> Result<()> {
+        let word = self.rows.get(self.cursor.row()).and_then(|row| {
+            self.search
+                .word_at(&row.chars, self.cursor.x as usize)
+                .map(|(start, end)| (start, row.chars[start..end].to_string()))
+        });
+        let Some((start, word)) = word else {
+            self.set_message("E348: No string under cursor");
+            return self.refresh_screen();
+        };
+
+        // start from the beginning of the word so # doesn't land on the word itself
+        self.cursor.x = start as u16;
+        self.search.forward = forward;
+        let pattern = self.search.word_pattern(&word, whole);
+        self.search.remember(&pattern);
+        self.search_next(forward)
+    }
+
+    // the pattern that is highlighted, the prompt while typing, the last search otherwise
+    fn active_search(&self) -> Option<Pattern> {
+        match self.current_mode {
+            Mode::Search if self.options.bool("incsearch") => {
+                self.search.compile(&self.search_input)
+            }
+            Mode::Search if !self.options.bool("incsearch") => None,
+            _ if self.search.highlight && self.options.bool("hlsearch") => {
+                self.search.compile(&self.search.pattern)
+            }
+            _ => None,
+        }
+    }
+
+    fn search_highlights(&self) -> Vec<Highlight> {
+        let Some(regex) = self.active_search() else {
+            return Vec::new();
+        };
+        let first = self.rowoff as usize;
+        let last = (first + self.screen.boundary().y as usize).min(self.rows.len());
+
+        find_all(&regex, &self.rows[first.min(last)..last])
+            .into_iter()
+            .map(|m| {
+                let row = m.row + first;
+                let current = row == self.cursor.row() && m.start == self.cursor.x as usize;
+                Highlight {
+                    row,
+                    start: m.start,
+                    end: m.end,
+                    kind: if current {
+                        HighlightKind::CurrentMatch
+                    } else {
+                        HighlightKind::Search
+                    },
+                }
+            })
+            .collect()
+    }
+
+    // [current/total] for the status bar
+    fn search_count(&mut self) -> Option<(usize, usize)> {
+        if !self.search.highlight {
+            return None;
+        }
+        let cursor = (self.cursor.row(), self.cursor.x as usize);
+        let starts = self
+            .search
+            .match_starts(&self.rows, self.undo_history.version());
+        if starts.is_empty() {
+            return None;
+        }
+        let current = starts.partition_point(|start| *start <= cursor);
+
+        Some((current, starts.len()))
+    }
+
+    // ex commands
+
+    // the position of a mark, '< and '> are the ends of the last visual selection
+    fn mark_position(&self, mark: char) -> Option<Position> {
+        match mark {
+            '<' => self.last_visual.clone().map(|(start, _)| start),
+            '>' => self.last_visual.clone().map(|(_, end)| end),
+            _ => self.marks.get(&mark).cloned(),
+        }
+    }
+
+    /* resolves an address to a one based line number, 0 being the line above
+     * the first one. `current` is the line `.` refers to */
+    fn resolve_address(
+        &self,
+        address: &Address,
+        current: usize,
+    ) -> std::result::Result<usize, String> {
+        let line = match &address.base {
+            AddressBase::Current => current,
+            AddressBase::Last => self.rows.len(),
+            AddressBase::Line(line) => *line,
+            AddressBase::Mark(mark) => {
+                self.mark_position(*mark)
+                    .ok_or_else(|| String::from("E20: Mark not set"))?
+                    .row()
+                    + 1
+            }
+            AddressBase::Search(pattern, forward) => {
+                let pattern = if pattern.is_empty() {
+                    &self.search.pattern
+                } else {
+                    pattern
+                };
+                let regex = self
+                    .search
+                    .compile(pattern)
+                    .ok_or_else(|| String::from("E35: No previous regular expression"))?;
+
+                // the search starts on the line after (or before) the current one and wraps
+                let len = self.rows.len();
+                (1..=len)
+                    .map(|step| {
+                        if *forward {
+                            (current.saturating_sub(1) + step) % len
+                        } else {
+                            (current.saturating_sub(1) + len * 2 - step) % len
+                        }
+                    })
+                    .find(|row| regex.is_match(&self.rows[*row].chars))
+                    .ok_or_else(|| format!("E486: Pattern not found: {pattern}"))?
+                    + 1
+            }
+        };
+
+        let line = line as i64 + address.offset;
+        if line < 0 || line > self.rows.len() as i64 {
+            return Err(String::from("E16: Invalid range"));
+        }
+        Ok(line as usize)
+    }
+
+    // the one based, inclusive range of a command, None when no range was given
+    fn resolve_range(
+        &self,
+        command: &ExCommand,
+    ) -> std::result::Result<Option<(usize, usize)>, String> {
+        command.resolve_range(self.cursor.row() + 1, |address, from| {
+            self.resolve_address(address, from)
+        })
+    }
+
+    /* the zero based rows a command works on: the given range, or `default`
+     * lines starting at the cursor. A `count` counts from the end of the range */
+    fn command_rows(
+        &self,
+        command: &ExCommand,
+        default: usize,
+        count: Option<usize>,
+    ) -> std::result::Result<(usize, usize), String> {
+        if self.rows.is_empty() {
+            return Err(String::from("E16: Invalid range"));
+        }
+        let last_row = self.rows.len() - 1;
+        let (first, last) = match self.resolve_range(command)? {
+            Some((first, last)) => (first.max(1) - 1, last.max(1) - 1),
+            None => {
+                let row = self.cursor.row();
+                (row, (row + default - 1).min(last_row))
+            }
+        };
+
+        match count {
+            Some(count) => Ok((last, (last + count - 1).min(last_row))),
+            None => Ok((first, last)),
+        }
+    }
+
+    fn execute_command_line(&mut self, line: &str) -> std::result::Result<(), String> {
+        let command = ex::parse(line)?;
+
+        // a bare range moves to its last line, :42 or :$
+        if command.name.is_empty() {
+            if let Some((_, line)) = self.resolve_range(&command)? {
+                self.cursor = Position {
+                    x: 0,
+                    y: line.max(1).min(self.rows.len().max(1)) as u16 - 1,
+                };
+            }
+            return Ok(());
+        }
+
+        let &ex::Command { name, action, .. } = ex::lookup(&command.name).ok_or_else(|| {
+            format!(
+                "E492: Not an editor command: {}",
+                line.trim_start_matches(':')
+            )
+        })?;
+
+        match action {
+            // the q: window closes instead of the editor and has nothing to write
+            Ex::Quit if self.history_window.is_some() => self.close_history_window(),
+            action if action.leaves_buffer() && self.history_window.is_some() => {
+                return Err(String::from("E11: Invalid in command-line window"));
+            }
+            Ex::Quit => self.quit_window(command.bang)?,
+            Ex::QuitAll => {
+                if !command.bang {
+                    self.check_changes()?;
+                }
+                self.quit_kass = true;
+            }
+            // :cq quits with an error status, for git and the like to abort
+            Ex::CQuit => {
+                let args = command.args.trim();
+                self.exit_code = match args {
+                    "" => 1,
+                    args => args
+                        .parse()
+                        .map_err(|_| format!("E488: Trailing characters: {args}"))?,
+                };
+                self.quit_kass = true;
+            }
+            Ex::Write => self.write(&command)?,
+            Ex::WriteAll => self.write_all(command.bang)?,
+            Ex::WriteQuit => {
+                self.write(&command)?;
+                self.quit_window(false)?;
+            }
+            // :x only writes when there are changes
+            Ex::Xit => {
+                if self.modified() {
+                    self.write(&command)?;
+                }
+                self.quit_window(false)?;
+            }
+            Ex::WriteQuitAll => {
+                self.write_all(command.bang)?;
+                self.quit_kass = true;
+            }
+            Ex::Split(vertical) => {
+                self.split_window(vertical)?;
+                if !command.args.trim().is_empty() {
+                    self.edit(&command)?;
+                }
+            }
+            Ex::Close => self.close_window()?,
+            Ex::TabNew => self.new_tab(&command)?,
+            Ex::TabClose => self.close_tab()?,
+            Ex::TabMove => self.move_tab(command.args.trim())?,
+            Ex::Only => self.only_window(),
+            Ex::SaveAs => {
+                let (create_dirs, name) = Kass::create_dirs_arg(command.args.trim());
+                if name.is_empty() {
+                    return Err(String::from("E471: Argument required"));
+                }
+                let path = paths::expand(name);
+                if path.exists() && !command.bang {
+                    return Err(String::from("E13: File exists (add ! to override)"));
+                }
+                if self.buffer_with_path(&paths::absolute(&path)).is_some() {
+                    return Err(String::from("E139: File is loaded in another buffer"));
+                }
+                if create_dirs {
+                    Kass::create_parent(&path)?;
+                }
+                // the buffer keeps its old name when the write fails
+                let old = self.filepath.clone();
+                self.set_filepath(&path.to_string_lossy());
+                if let Err(error) = self.write_to_file(command.bang) {
+                    self.set_filepath(&old);
+                    return Err(error.to_string());
+                }
+            }
+            Ex::Edit => self.edit(&command)?,
+            Ex::BufferAdd => {
+                let name = command.args.trim();
+                if name.is_empty() {
+                    return Err(String::from("E471: Argument required"));
+                }
+                let path = paths::expand(name);
+                let absolute = paths::absolute(&path);
+                let own = !self.filepath.is_empty()
+                    && absolute == paths::absolute(Path::new(&self.filepath));
+                if !own && self.buffer_with_path(&absolute).is_none() {
+                    self.last_buffer += 1;
+                    let buffer = Buffer::unloaded(self.last_buffer, &path.to_string_lossy());
+                    self.buffers.push(buffer);
+                }
+            }
+            Ex::Buffer => match command.args.trim() {
+                "" => {}
+                arg => {
+                    let number = self.find_buffer(arg)?;
+                    self.switch_buffer(number, command.bang)?;
+                }
+            },
+            Ex::CycleBuffer(next) => self.cycle_buffer(next, command.bang)?,
+            Ex::BufferDelete => self.delete_buffer(command.args.trim(), command.bang)?,
+            Ex::ListBuffers => self.set_message(format!(":ls\n{}", self.list_buffers())),
+            Ex::Read => self.read(&command)?,
+            Ex::Set(scope) => self.set_options(&command.args, scope)?,
+            Ex::NoHlSearch => self.search.highlight = false,
+            Ex::Palette => self.open_palette().map_err(|e| e.to_string())?,
+            Ex::Undo(redo) => self.undo(redo),
+            Ex::Highlight => {
+                if let Some(listing) = self.theme.highlight(&command.args)? {
+                    self.set_message(listing);
+                }
+                self.apply_theme();
+            }
+            Ex::Config => match command.args.trim() {
+                "reload" => self.load_config(true),
+                "" => self.set_message(config::user_config_path().map_or_else(
+                    || String::from("No config file"),
+                    |path| path.display().to_string(),
+                )),
+                args => return Err(format!("E475: Invalid argument: {args}")),
+            },
+            Ex::Source => {
+                let args = command.args.trim();
+                if args.is_empty() {
+                    return Err(String::from("E471: Argument required"));
+                }
+                let path = paths::expand(args);
+                self.source(&path)?;
+            }
+
+            Ex::Substitute(repeat) => {
+                let (first, last) = self.command_rows(&command, 1, None)?;
+                let substitute = if repeat || command.args.is_empty() {
+                    // :s and :& repeat the last substitute, :&& keeps its flags
+                    let mut last = self
+                        .last_substitute
+                        .clone()
+                        .ok_or("E35: No previous regular expression")?;
+                    if command.args.trim() != "&" {
+                        last.flags = Flags::default();
+                    }
+                    last
+                } else {
+                    Substitute::parse(&command.args)?
+                };
+                self.substitute(first, last, substitute)?;
+            }
+
+            Ex::Delete | Ex::Yank => {
+                let (first, last) = self.command_rows(&command, 1, command.count()?)?;
+                self.clipboard = self.rows[first..=last]
+                    .iter()
+                    .map(|row| row.chars.clone())
+                    .collect();
+
+                let count = last - first + 1;
+                if action == Ex::Delete {
+                    self.splice_rows(first..last + 1, Vec::new());
+                    self.cursor = Position {
+                        x: 0,
+                        y: first.min(self.rows.len().saturating_sub(1)) as u16,
+                    };
+                    if count > 2 {
+                        self.set_message(format!("{count} fewer lines"));
+                    }
+                } else if count > 2 {
+                    self.set_message(format!("{count} lines yanked"));
+                }
+            }
+
+            Ex::Move | Ex::Copy => {
+                let (first, last) = self.command_rows(&command, 1, None)?;
+                let target = ex::parse_target(&command.args)?;
+                let target = self.resolve_address(&target, self.cursor.row() + 1)?;
+                let lines: Vec<String> = self.rows[first..=last]
+                    .iter()
+                    .map(|row| row.chars.clone())
+                    .collect();
+                let count = lines.len();
+
+                // the lines end up below the one based `target` line
+                let at = if action == Ex::Move {
+                    if target > first && target <= last {
+                        return Err(String::from(
+                            "E134: Cannot move a range of lines into itself",
+                        ));
+                    }
+                    self.splice_rows(first..last + 1, Vec::new());
+                    if target > last {
+                        target - count
+                    } else {
+                        target
+                    }
+                } else {
+                    target
+                };
+
+                self.splice_rows(at..at, lines);
+                self.cursor = Position {
+                    x: 0,
+                    y: (at + count - 1) as u16,
+                };
+                if count > 2 && action == Ex::Move {
+                    self.set_message(format!("{count} lines moved"));
+                }
+            }
+
+            Ex::Shift(right) => {
+                let (first, last) = self.command_rows(&command, 1, command.count()?)?;
+                let width = self.options.number("shiftwidth") * command.name.len();
+                for y in first..=last {
+                    let line = self.rows[y].chars.clone();
+                    if right {
+                        if !line.is_empty() {
+                            self.set_line(y, format!("{}{line}", " ".repeat(width)));
+                        }
+                    } else {
+                        // a tab counts as a full shift
+                        let mut removed = 0;
+                        let rest = line.trim_start_matches(|c: char| {
+                            let remove = removed < width && (c == ' ' || c == '\t');
+                            removed += if c == '\t' { width } else { 1 };
+                            remove
+                        });
+                        let rest = rest.to_string();
+                        self.set_line(y, rest);
+                    }
+                }
+                self.cursor = Position {
+                    x: 0,
+                    y: last as u16,
+                };
+            }
+
+            Ex::Join => {
+                // a single address still joins two lines
+                let (first, last) = self.command_rows(&command, 2, command.count()?)?;
+                self.join_lines(first, last.max(first + 1) - first + 1, !command.bang);
+            }
+
+            Ex::Global(invert) => self.global(&command, invert || command.bang)?,
+
+            Ex::Map => self.map(name, &command)?,
+
+            Ex::Normal => {
+                if command.args.is_empty() {
+                    return Err(String::from("E471: Argument required"));
+                }
+                let rows = match self.resolve_range(&command)? {
+                    Some(_) => Some(self.command_rows(&command, 1, None)?),
+                    None => None,
+                };
+                self.normal(&keys::parse(&command.args), rows, !command.bang)?;
+            }
+
+            Ex::LineNumber => {
+                let line = match self.resolve_range(&command)? {
+                    Some((_, line)) => line,
+                    None => self.rows.len(),
+                };
+                self.set_message(line.to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    // the file a command names, the buffer's own one when it names none
+    fn command_path(&self, name: &str) -> std::result::Result<PathBuf, String> {
+        match name {
+            "" if self.filepath.is_empty() => Err(String::from("E32: No file name")),
+            "" => Ok(PathBuf::from(&self.filepath)),
+            name => Ok(paths::expand(name)),
+        }
+    }
+
+    // `++p` in front of a file name creates the directories it is in
+    fn create_dirs_arg(args: &str) -> (bool, &str) {
+        match args.strip_prefix("++p") {
+            Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => {
+                (true, rest.trim_start())
+            }
+            _ => (false, args),
+        }
+    }
+
+    fn create_parent(path: &Path) -> std::result::Result<(), String> {
+        match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => std::fs::create_dir_all(dir)
+                .map_err(|e| format!("E212: Can't create {}: {e}", dir.display())),
+            _ => Ok(()),
+        }
+    }
+
+    /* :w, :w {file}, :w >> {file} and :[range]w {file}. Writing to another
+     * file keeps the buffer's name, unless it has none yet */
+    fn write(&mut self, command: &ExCommand) -> std::result::Result<(), String> {
+        let (create_dirs, args) = Kass::create_dirs_arg(command.args.trim());
+        let (append, name) = match args.strip_prefix(">>") {
+            Some(rest) => (true, rest.trim()),
+            None => (false, args),
+        };
+        let range = match self.resolve_range(command)? {
+            Some(_) => Some(self.command_rows(command, 1, None)?),
+            None => None,
+        };
+        let path = self.command_path(name)?;
+        if create_dirs {
+            Kass::create_parent(&path)?;
+        }
+        if self.filepath.is_empty() && !append {
+            self.set_filepath(&path.to_string_lossy());
+        }
+
+        let own = paths::absolute(&path) == paths::absolute(Path::new(&self.filepath));
+        if own && !append {
+            if range.is_some() && !command.bang {
+                return Err(String::from("E140: Use ! to write partial buffer"));
+            }
+            if range.is_none() {
+                return self.write_to_file(command.bang).map_err(|e| e.to_string());
+            }
+        } else if !append && !command.bang && path.exists() {
+            return Err(String::from("E13: File exists (add ! to override)"));
+        }
+        self.write_file(&path, range, append, command.bang)
+            .map_err(|e| e.to_string())
+    }
+
+    /* :e reloads the file, :e {file} opens another one in a new buffer or
+     * goes to the buffer it already has. Changes are thrown away or left in a
+     * hidden buffer only with ! or 'hidden' */
+    fn edit(&mut self, command: &ExCommand) -> std::result::Result<(), String> {
+        let path = self.command_path(command.args.trim())?;
+        let absolute = paths::absolute(&path);
+        if absolute == paths::absolute(Path::new(&self.filepath)) {
+            if self.modified() && !command.bang {
+                return Err(String::from(
+                    "E37: No write since last change (add ! to override)",
+                ));
+            }
+            return self.load_file();
+        }
+
+        match self.buffer_with_path(&absolute) {
+            Some(number) => self.switch_buffer(number, command.bang),
+            None => {
+                self.leave_buffer(command.bang)?;
+                self.last_buffer += 1;
+                let buffer = Buffer::unloaded(self.last_buffer, &path.to_string_lossy());
+                self.enter_buffer(buffer)
+            }
+        }
+    }
+
+    /* reads the file of the current buffer into it again, the cursor stays
+     * where it was as far as the lines go */
+    fn load_file(&mut self) -> std::result::Result<(), String> {
+        let path = PathBuf::from(&self.filepath);
+        let (lines, file_format) = match Kass::read_file(&path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == ErrorKind::NotFound => (Vec::new(), Vec::new()),
+            Err(error) => return Err(format!("E484: Can't open file {}: {error}", path.display())),
+        };
+        let new = !path.exists();
+
+        self.rows = lines.iter().map(|line| Row::new(line.clone())).collect();
+        self.undo_history = UndoHistory::new();
+        self.selection = None;
+        self.cursor.y = self.cursor.y.min(self.rows.len().saturating_sub(1) as u16);
+        self.clamp_cursor_x();
+
+        // the options of the buffer come from the new file
+        self.file_format = file_format;
+        self.options.buffer = options::Local::default();
+        for arg in &self.file_format {
+            let _ = self.options.set(arg, SetScope::Local);
+        }
+        let errors = self.apply_file_options();
+        self.apply_options();
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+
+        if !self.filepath.is_empty() {
+            self.set_message(if new {
+                format!("\"{}\" [New]", self.filepath)
+            } else {
+                format!("\"{}\" {}L", self.filepath, self.rows.len())
+            });
+        }
+        Ok(())
+    }
+
+    // the current buffer can be left for another one, with changes only if it stays hidden
+    fn leave_buffer(&self, force: bool) -> std::result::Result<(), String> {
+        // another window still showing it is as good as hidden
+        let shown = self
+            .windows
+            .iter()
+            .chain(
+                self.tabs
+                    .iter()
+                    .flat_map(|tab| tab.windows.iter().chain([&tab.window])),
+            )
+            .any(|window| window.buffer == self.buffer_number);
+        if self.modified() && !force && !self.options.bool("hidden") && !shown {
+            return Err(String::from(
+                "E37: No write since last change (add ! to override)",
+            ));
+        }
+        Ok(())
+    }
+
+    // the current buffer moved out of the fields of kass
+    fn take_buffer(&mut self) -> Buffer {
+        Buffer {
+            number: self.buffer_number,
+            filepath: self.filepath.clone(),
+            loaded: true,
+            rows: std::mem::take(&mut self.rows),
+            undo_history: std::mem::replace(&mut self.undo_history, UndoHistory::new()),
+            file_format: std::mem::take(&mut self.file_format),
+            options: std::mem::take(&mut self.options.buffer),
+            cursor: std::mem::take(&mut self.cursor),
+            rowoff: std::mem::take(&mut self.rowoff),
+            coloff: std::mem::take(&mut self.coloff),
+            marks: std::mem::take(&mut self.marks),
+            last_visual: self.last_visual.take(),
+        }
+    }
+
+    // `buffer` becomes the current one, its file is read if it wasn't yet
+    fn put_buffer(&mut self, buffer: Buffer) -> std::result::Result<(), String> {
+        self.buffer_number = buffer.number;
+        self.set_filepath(&buffer.filepath);
+        self.cursor = buffer.cursor;
+        (self.rowoff, self.coloff) = (buffer.rowoff, buffer.coloff);
+        self.marks = buffer.marks;
+        self.last_visual = buffer.last_visual;
+        self.selection = None;
+        if !buffer.loaded {
+            return self.load_file();
+        }
+        self.rows = buffer.rows;
+        self.undo_history = buffer.undo_history;
+        self.file_format = buffer.file_format;
+        self.options.buffer = buffer.options;
+        self.apply_options();
+        Ok(())
+    }
+
+    // the current buffer goes to the list and `buffer` takes its place
+    fn enter_buffer(&mut self, buffer: Buffer) -> std::result::Result<(), String> {
+        let current = self.take_buffer();
+        self.alternate_buffer = Some(current.number);
+        self.buffers.push(current);
+        self.buffers.sort_by_key(|buffer| buffer.number);
+        self.put_buffer(buffer)
+    }
+
+    fn switch_buffer(&mut self, number: usize, force: bool) -> std::result::Result<(), String> {
+        if number == self.buffer_number {
+            return Ok(());
+        }
+        let i = self
+            .buffers
+            .iter()
+            .position(|buffer| buffer.number == number)
+            .ok_or_else(|| format!("E86: Buffer {number} does not exist"))?;
+        self.leave_buffer(force)?;
+        let buffer = self.buffers.remove(i);
+        self.enter_buffer(buffer)
+    }
+
+    // the other buffer that has the file at `absolute`
+    fn buffer_with_path(&self, absolute: &Path) -> Option<usize> {
+        self.buffers
+            .iter()
+            .find(|buffer| {
+                !buffer.filepath.is_empty()
+                    && paths::absolute(Path::new(&buffer.filepath)) == absolute
+            })
+            .map(|buffer| buffer.number)
+    }
+
+    // every buffer number in order, the current one among them
+    fn buffer_numbers(&self) -> Vec<usize> {
+        let mut numbers: Vec<usize> = self.buffers.iter().map(|buffer| buffer.number).collect();
+        numbers.push(self.buffer_number);
+        numbers.sort();
+        numbers
+    }
+
+    /* the buffer :b and :bd name with a number, `%` or `#`, or a part of the
+     * file name that only one buffer has */
+    fn find_buffer(&self, arg: &str) -> std::result::Result<usize, String> {
+        match arg {
+            "%" => return Ok(self.buffer_number),
+            "#" => {
+                return self
+                    .alternate_buffer
+                    .ok_or_else(|| String::from("E23: No alternate file"))
+            }
+            _ => {}
+        }
+        if let Ok(number) = arg.parse::<usize>() {
+            if !self.buffer_numbers().contains(&number) {
+                return Err(format!("E86: Buffer {number} does not exist"));
+            }
+            return Ok(number);
+        }
+
+        let mut names: Vec<(usize, &str)> = self
+            .buffers
+            .iter()
+            .map(|buffer| (buffer.number, buffer.filepath.as_str()))
+            .collect();
+        names.push((self.buffer_number, &self.filepath));
+        // a full name wins over the ones it is a part of
+        if let Some((number, _)) = names.iter().find(|(_, name)| *name == arg) {
+            return Ok(*number);
+        }
+        let matches: Vec<usize> = names
+            .iter()
+            .filter(|(_, name)| name.contains(arg))
+            .map(|(number, _)| *number)
+            .collect();
+        match matches.as_slice() {
+            [number] => Ok(*number),
+            [] => Err(format!("E94: No matching buffer for {arg}")),
+            _ => Err(format!("E93: More than one match for {arg}")),
+        }
+    }
+
+    // :bn and :bp, past the last buffer comes the first one again
+    fn cycle_buffer(&mut self, forward: bool, force: bool) -> std::result::Result<(), String> {
+        let numbers = self.buffer_numbers();
+        let i = numbers
+            .iter()
+            .position(|number| *number == self.buffer_number)
+            .unwrap_or(0);
+        let next = if forward {
+            (i + 1) % numbers.len()
+        } else {
+            (i + numbers.len() - 1) % numbers.len()
+        };
+        self.switch_buffer(numbers[next], force)
+    }
+
+    /* :bd, a buffer with changes only with !. The current one is replaced by
+     * the alternate or the next buffer, or an empty one when it was the last */
+    fn delete_buffer(&mut self, arg: &str, force: bool) -> std::result::Result<(), String> {
+        let number = match arg {
+            "" => self.buffer_number,
+            arg => self.find_buffer(arg)?,
+        };
+        let modified = match self.buffers.iter().find(|buffer| buffer.number == number) {
+            Some(buffer) => buffer.modified(),
+            None => self.modified(),
+        };
+        if modified && !force {
+            return Err(format!(
+                "E89: No write since last change for buffer {number} (add ! to override)"
+            ));
+        }
+        if self.alternate_buffer == Some(number) {
+            self.alternate_buffer = None;
+        }
+        // the other windows showing it are closed
+        for window in self.windows.iter().filter(|window| window.buffer == number) {
+            self.layout.remove(window.id);
+        }
+        self.windows.retain(|window| window.buffer != number);
+        // and so are the tab pages left without a window
+        let mut i = 0;
+        self.tabs.retain_mut(|tab| {
+            let kept = tab.close_buffer(number);
+            if !kept && i < self.tab {
+                self.tab -= 1;
+            }
+            i += 1;
+            kept
+        });
+        self.apply_layout();
+        if number != self.buffer_number {
+            self.buffers.retain(|buffer| buffer.number != number);
+            return Ok(());
+        }
+
+        let next = self.alternate_buffer.or_else(|| {
+            self.buffers
+                .iter()
+                .find(|buffer| buffer.number > number)
+                .or(self.buffers.first())
+                .map(|buffer| buffer.number)
+        });
+        self.take_buffer();
+        self.alternate_buffer = None;
+        match next {
+            Some(next) => {
+                let i = self
+                    .buffers
+                    .iter()
+                    .position(|buffer| buffer.number == next)
+                    .unwrap_or(0);
+                let buffer = self.buffers.remove(i);
+                self.put_buffer(buffer)
+            }
+            None => {
+                self.last_buffer += 1;
+                self.put_buffer(Buffer::unloaded(self.last_buffer, ""))
+            }
+        }
+    }
+
+    /* :ls, a line per buffer with its flags: % the current one, # the
+     * alternate one, a shown, h hidden and + changed */
+    fn list_buffers(&self) -> String {
+        let line = |number: usize, flags: String, name: &str, line: usize| {
+            format!(
+                "{number:>3} {flags} {:<30} line {line}",
+                format!("\"{name}\"")
+            )
+        };
+        let mut lines = vec![(
+            self.buffer_number,
+            line(
+                self.buffer_number,
+                format!("%a {}", if self.modified() { '+' } else { ' ' }),
+                if self.filepath.is_empty() {
+                    "[No Name]"
+                } else {
+                    &self.filepath
+                },
+                self.cursor.row() + 1,
+            ),
+        )];
+        for buffer in &self.buffers {
+            let flags = format!(
+                "{}{} {}",
+                if self.alternate_buffer == Some(buffer.number) {
+                    '#'
+                } else {
+                    ' '
+                },
+                if self
+                    .windows
+                    .iter()
+                    .any(|window| window.buffer == buffer.number)
+                {
+                    'a'
+                } else if buffer.loaded {
+                    'h'
+                } else {
+                    ' '
+                },
+                if buffer.modified() { '+' } else { ' ' }
+            );
+            let at = if buffer.loaded {
+                buffer.cursor.row() + 1
+            } else {
+                0
+            };
+            lines.push((buffer.number, line(buffer.number, flags, buffer.name(), at)));
+        }
+        lines.sort_by_key(|(number, _)| *number);
+        let lines: Vec<String> = lines.into_iter().map(|(_, line)| line).collect();
+        lines.join("\n")
+    }
+
+    // the part of the terminal the windows share, all but the tabline and the message line
+    fn window_area(&self) -> Rect {
+        let tabline = usize::from(self.tabline_shown());
+        Rect {
+            top: tabline,
+            left: 0,
+            height: self.terminal_height - 1 - tabline,
+            width: self.screen.width(),
+        }
+    }
+
+    // the screen and the status bar draw the current window from now on
+    fn apply_layout(&mut self) {
+        let area = self.window_area();
+        let rect = self
+            .layout
+            .rects(area)
+            .into_iter()
+            .find(|(id, _)| *id == self.window_id)
+            .map_or(area, |(_, rect)| rect);
+        self.screen.set_area(rect);
+        self.statusbar.set_area(rect);
+        self.apply_options();
+    }
+
+    // the current window moved out of the fields of kass, its buffer stays
+    fn take_window(&mut self) -> Window {
+        Window {
+            id: self.window_id,
+            buffer: self.buffer_number,
+            cursor: self.cursor.clone(),
+            rowoff: self.rowoff,
+            coloff: self.coloff,
+            options: std::mem::take(&mut self.options.window),
+        }
+    }
+
+    /* :split and Ctrl-w s, the new window shows the same buffer from the same
+     * place and becomes the current one */
+    fn split_window(&mut self, vertical: bool) -> std::result::Result<(), String> {
+        let id = self.last_window + 1;
+        self.layout
+            .split(self.window_id, id, vertical, self.window_area())?;
+        self.last_window = id;
+        let current = self.take_window();
+        self.options.window = current.options.clone();
+        self.windows.push(current);
+        self.window_id = id;
+        self.apply_layout();
+        Ok(())
+    }
+
+    /* the window `id` becomes the current one, with its buffer when that is
+     * another one than the current window shows */
+    fn enter_window(&mut self, id: usize) -> std::result::Result<(), String> {
+        if id == self.window_id {
+            return Ok(());
+        }
+        let i = self
+            .windows
+            .iter()
+            .position(|window| window.id == id)
+            .ok_or_else(|| String::from("E957: Invalid window number"))?;
+        let current = self.take_window();
+        self.windows.push(current);
+        let window = self.windows.remove(i);
+        self.put_window(window)
+    }
+
+    // `window` becomes the current one, its buffer with it
+    fn put_window(&mut self, window: Window) -> std::result::Result<(), String> {
+        self.switch_buffer(window.buffer, true)?;
+        self.window_id = window.id;
+        self.cursor = window.cursor;
+        (self.rowoff, self.coloff) = (window.rowoff, window.coloff);
+        self.options.window = window.options;
+        // the lines may have changed in another window
+        self.cursor.y = self.cursor.y.min(self.last_row());
+        self.clamp_cursor_x();
+        self.apply_layout();
+        Ok(())
+    }
+
+    /* :close and Ctrl-w c, the window used before takes over. The last
+     * window of a tab page closes the tab page */
+    fn close_window(&mut self) -> std::result::Result<(), String> {
+        let Some(previous) = self.windows.last().map(|window| window.id) else {
+            if !self.tabs.is_empty() {
+                return self.close_tab();
+            }
+            return Err(String::from("E444: Cannot close last window"));
+        };
+        let closing = self.window_id;
+        self.enter_window(previous)?;
+        self.windows.retain(|window| window.id != closing);
+        self.layout.remove(closing);
+        self.apply_layout();
+        Ok(())
+    }
+
+    /* :q closes the window, the editor only goes with the last one. Without
+     * `force` changes that would be lost stop it */
+    fn quit_window(&mut self, force: bool) -> std::result::Result<(), String> {
+        if !self.windows.is_empty() || !self.tabs.is_empty() {
+            self.leave_buffer(force)?;
+            return self.close_window();
+        }
+        if !force {
+            self.check_changes()?;
+        }
+        self.quit_kass = true;
+        Ok(())
+    }
+
+    // E37 when the current buffer has changes, E162 when another one has
+    fn check_changes(&self) -> std::result::Result<(), String> {
+        if self.modified() {
+            return Err(String::from(
+                "E37: No write since last change (add ! to override)",
+            ));
+        }
+        match self.buffers.iter().find(|buffer| buffer.modified()) {
+            Some(buffer) => Err(format!(
+                "E162: No write since last change for buffer \"{}\"",
+                buffer.name()
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /* :wa, every buffer with changes is written. They become the current
+     * one in turn, the one that was comes back at the end */
+    fn write_all(&mut self, force: bool) -> std::result::Result<(), String> {
+        let (current, alternate, redraw) = (self.buffer_number, self.alternate_buffer, self.redraw);
+        let mut changed: Vec<usize> = self
+            .buffers
+            .iter()
+            .filter(|buffer| buffer.modified())
+            .map(|buffer| buffer.number)
+            .collect();
+        if self.modified() {
+            changed.insert(0, current);
+        }
+
+        self.redraw = false;
+        let mut result = Ok(());
+        for number in changed {
+            result = self.switch_buffer(number, true).and_then(|_| {
+                if self.filepath.is_empty() {
+                    return Err(format!("E141: No file name for buffer {number}"));
+                }
+                self.write_to_file(force).map_err(|e| e.to_string())
+            });
+            if result.is_err() {
+                break;
+            }
+        }
+        self.redraw = redraw;
+        self.switch_buffer(current, true)?;
+        self.alternate_buffer = alternate;
+        result
+    }
+
+    // :only and Ctrl-w o, the buffers of the other windows stay in the list
+    fn only_window(&mut self) {
+        self.windows.clear();
+        self.layout = Layout::Window(self.window_id);
+        self.apply_layout();
+    }
+
+    // the keys after Ctrl-w
+    fn window_command(&mut self, key: char) -> std::result::Result<(), String> {
+        let area = self.window_area();
+        let order = self.layout.windows();
+        let at = order
+            .iter()
+            .position(|id| *id == self.window_id)
+            .unwrap_or(0);
+        match key {
+            // <C-w>s, v, c and o are the keys of :split, :vsplit, :close and :only
+            'S' => self.split_window(false)?,
+            'w' => self.enter_window(order[(at + 1) % order.len()])?,
+            'W' => self.enter_window(order[(at + order.len() - 1) % order.len()])?,
+            'p' => {
+                if let Some(previous) = self.windows.last().map(|window| window.id) {
+                    self.enter_window(previous)?;
+                }
+            }
+            'h' | 'j' | 'k' | 'l' => {
+                let direction = match key {
+                    'h' => Direction::Left,
+                    'j' => Direction::Down,
+                    'k' => Direction::Up,
+                    _ => Direction::Right,
+                };
+                let rect = self
+                    .layout
+                    .rects(area)
+                    .into_iter()
+                    .find(|(id, _)| *id == self.window_id)
+                    .map_or(area, |(_, rect)| rect);
+                let cursor = (
+                    rect.top + self.cursor.row().saturating_sub(self.rowoff as usize),
+                    rect.left + (self.cursor.x as usize).saturating_sub(self.coloff as usize),
+                );
+                if let Some(id) = self
+                    .layout
+                    .neighbor(self.window_id, direction, cursor, area)
+                {
+                    self.enter_window(id)?;
+                }
+            }
+            'q' => self.quit_window(false)?,
+            '+' | '-' | '>' | '<' => {
+                let delta = if matches!(key, '+' | '>') { 1 } else { -1 };
+                self.layout
+                    .resize(self.window_id, matches!(key, '>' | '<'), delta, area);
+                self.apply_layout();
+            }
+            '=' => {
+                self.layout.equalize(area);
+                self.apply_layout();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    // the current tab page moved out of the fields of kass
+    fn take_tab(&mut self) -> TabPage {
+        TabPage {
+            layout: std::mem::replace(&mut self.layout, Layout::Window(self.window_id)),
+            window: self.take_window(),
+            windows: std::mem::take(&mut self.windows),
+        }
+    }
+
+    // `tab` becomes the current tab page, its current window with it
+    fn put_tab(&mut self, tab: TabPage) -> std::result::Result<(), String> {
+        self.layout = tab.layout;
+        self.windows = tab.windows;
+        self.put_window(tab.window)
+    }
+
+    /* :tabnew and :tabedit, a tab page after the current one with a window on
+     * the file, or on an empty buffer */
+    fn new_tab(&mut self, command: &ExCommand) -> std::result::Result<(), String> {
+        let current = self.take_tab();
+        self.options.window = current.window.options.clone();
+        self.tabs.insert(self.tab, current);
+        self.tab += 1;
+        self.last_window += 1;
+        self.window_id = self.last_window;
+        self.layout = Layout::Window(self.window_id);
+        self.apply_layout();
+        if command.args.trim().is_empty() {
+            self.last_buffer += 1;
+            self.enter_buffer(Buffer::unloaded(self.last_buffer, ""))
+        } else {
+            self.edit(command)
+        }
+    }
+
+    // the tab page at `index` of all of them becomes the current one
+    fn enter_tab(&mut self, index: usize) -> std::result::Result<(), String> {
+        if index == self.tab || index > self.tabs.len() {
+            return Ok(());
+        }
+        let current = self.take_tab();
+        self.tabs.insert(self.tab, current);
+        let tab = self.tabs.remove(index);
+        self.tab = index;
+        self.put_tab(tab)
+    }
+
+    // gt and gT, past the last tab page comes the first one again
+    fn cycle_tab(&mut self, forward: bool) -> std::result::Result<(), String> {
+        let count = self.tabs.len() + 1;
+        let next = if forward {
+            (self.tab + 1) % count
+        } else {
+            (self.tab + count - 1) % count
+        };
+        self.enter_tab(next)
+    }
+
+    /* :tabclose, the tab page to the right takes over, or the one to the left
+     * for the last one. The buffers of its windows stay in the list */
+    fn close_tab(&mut self) -> std::result::Result<(), String> {
+        if self.tabs.is_empty() {
+            return Err(String::from("E784: Cannot close last tab page"));
+        }
+        self.take_tab();
+        self.tab = self.tab.min(self.tabs.len() - 1);
+        let tab = self.tabs.remove(self.tab);
+        self.put_tab(tab)
+    }
+
+    /* :tabmove N puts the current tab page after the Nth one, 0 makes it the
+     * first and nothing the last. +N and -N move it by N */
+    fn move_tab(&mut self, arg: &str) -> std::result::Result<(), String> {
+        let invalid = || format!("E474: Invalid argument: {arg}");
+        let last = self.tabs.len();
+        let to = if arg.is_empty() {
+            last
+        } else if let Some(n) = arg.strip_prefix('+') {
+            let n = if n.is_empty() {
+                Ok(1)
+            } else {
+                n.parse::<usize>()
+            };
+            self.tab + n.map_err(|_| invalid())?
+        } else if let Some(n) = arg.strip_prefix('-') {
+            let n = if n.is_empty() {
+                Ok(1)
+            } else {
+                n.parse::<usize>()
+            };
+            self.tab
+                .checked_sub(n.map_err(|_| invalid())?)
+                .ok_or_else(invalid)?
+        } else {
+            // N counts the tab pages before the move
+            let n = arg.parse::<usize>().map_err(|_| invalid())?;
+            if n > self.tab {
+                n - 1
+            } else {
+                n
+            }
+        };
+        if to > last {
+            return Err(invalid());
+        }
+        // the other tab pages keep their order around it
+        self.tab = to;
+        Ok(())
+    }
+
+    // the labels of the tabline, the name of the buffer in the current window of each tab page
+    fn tab_labels(&self) -> Vec<String> {
+        let label = |number: usize| {
+            let (filepath, modified) = if number == self.buffer_number {
+                (self.filepath.as_str(), self.modified())
+            } else {
+                match self.buffers.iter().find(|buffer| buffer.number == number) {
+                    Some(buffer) => (buffer.filepath.as_str(), buffer.modified()),
+                    None => ("", false),
+                }
+            };
+            let name = Path::new(filepath)
+                .file_name()
+                .map_or(String::from("[No Name]"), |name| {
+                    name.to_string_lossy().into_owned()
+                });
+            if modified {
+                format!("+ {name}")
+            } else {
+                name
+            }
+        };
+        let mut labels: Vec<String> = self
+            .tabs
+            .iter()
+            .map(|tab| label(tab.window.buffer))
+            .collect();
+        labels.insert(self.tab, label(self.buffer_number));
+        labels
+    }
+
+    // 'showtabline' 1 shows it once there is more than one tab page
+    fn tabline_shown(&self) -> bool {
+        match self.options.number("showtabline") {
+            0 => false,
+            1 => !self.tabs.is_empty(),
+            _ => true,
+        }
+    }
+
+    /* the windows other than the current one with their status lines, and
+     * the separators between windows side by side */
+    fn draw_windows(&mut self) -> Result<()> {
+        for (id, rect) in self.layout.rects(self.window_area()) {
+            if rect.left > 0 {
+                self.screen
+                    .draw_separator(rect.left - 1, rect.top, rect.height)?;
+            }
+            let Some(window) = self.windows.iter().find(|window| window.id == id) else {
+                continue;
+            };
+            // the same buffer as the current window shows its changes as they are typed
+            let (rows, local, name, modified) = if window.buffer == self.buffer_number {
+                (
+                    &self.rows,
+                    &self.options.buffer,
+                    self.absolute_path.clone(),
+                    self.modified(),
+                )
+            } else {
+                let Some(buffer) = self
+                    .buffers
+                    .iter()
+                    .find(|buffer| buffer.number == window.buffer)
+                else {
+                    continue;
+                };
+                let name = match buffer.filepath.as_str() {
+                    "" => buffer.name().to_string(),
+                    path => paths::absolute(Path::new(path)).display().to_string(),
+                };
+                (&buffer.rows, &buffer.options, name, buffer.modified())
+            };
+            let mut options = self.options.clone();
+            options.buffer = local.clone();
+            options.window = window.options.clone();
+            self.screen.set_area(rect);
+            self.screen.set_options(&options);
+            self.screen.draw_screen(
+                rows,
+                0,
+                window.rowoff as usize,
+                window.coloff as usize,
+                window.cursor.row(),
+                &[],
+            )?;
+            self.statusbar.set_area(rect);
+            self.statusbar.paint(String::new(), name, modified, None)?;
+        }
+        self.apply_layout();
+        Ok(())
+    }
+
+    // :r {file} puts the lines of a file below the cursor line, or below the addressed one
+    fn read(&mut self, command: &ExCommand) -> std::result::Result<(), String> {
+        let path = self.command_path(command.args.trim())?;
+        let (lines, _) = Kass::read_file(&path)
+            .map_err(|_| format!("E484: Can't open file {}", path.display()))?;
+        let below = match self.resolve_range(command)? {
+            Some((_, line)) => line,
+            None => self.cursor.row() + 1,
+        }
+        .min(self.rows.len());
+
+        for (i, line) in lines.iter().enumerate() {
+            self.insert_row(below + i, line.clone());
+        }
+        if !lines.is_empty() {
+            self.cursor = Position {
+                x: 0,
+                y: below as u16,
+            };
+        }
+        Ok(())
+    }
+
+    /* :g/pattern/cmd runs cmd on every line matching pattern, :v and :g! on the
+     * other ones. The lines are marked first so that commands deleting or adding
+     * lines don't shift the ones still to visit */
+    fn global(&mut self, command: &ExCommand, invert: bool) -> std::result::Result<(), String> {
+        if self.in_global {
+            return Err(String::from("E147: Cannot do :global recursive"));
+        }
+
+        let mut args = command.args.chars();
+        let delimiter = match args.next() {
+            Some(c) if !c.is_alphanumeric() && !matches!(c, '\\' | '"' | '|') => c,
+            _ => {
+                return Err(String::from(
+                    "E146: Regular expressions can't be delimited by letters",
+                ))
+            }
+        };
+        let (pattern, ex_command) = ex::split_delimited(args.as_str(), delimiter);
+        let pattern = if pattern.is_empty() {
+            self.search.pattern.clone()
+        } else {
+            pattern
+        };
+        let regex = self
+            .search
+            .compile(&pattern)
+            .ok_or_else(|| String::from("E35: No previous regular expression"))?;
+        self.search.remember(&pattern);
+
+        // the whole file unless a range was given
+        let (first, last) = match self.resolve_range(command)? {
+            Some(_) => self.command_rows(command, 1, None)?,
+            None if self.rows.is_empty() => return Ok(()),
+            None => (0, self.rows.len() - 1),
+        };
+
+        let mut marked = 0;
+        for (y, row) in self.rows.iter_mut().enumerate() {
+            row.marked = (first..=last).contains(&y) && regex.is_match(&row.chars) != invert;
+            marked += row.marked as usize;
+        }
+        if marked == 0 {
+            return Err(if invert {
+                format!("Pattern found in every line: {pattern}")
+            } else {
+                format!("Pattern not found: {pattern}")
+            });
+        }
+
+        // :g/pattern/ alone just moves to the last matching line
+        let ex_command = ex_command.trim();
+        self.in_global = true;
+        self.global_substitutions = (0, 0, false);
+        let mut from = 0;
+        let mut result = Ok(());
+        while let Some(y) = self.rows[from..]
+            .iter()
+            .position(|row| row.marked)
+            .map(|y| from + y)
+            // :m can move marked lines above the last visited one
+            .or_else(|| self.rows.iter().position(|row| row.marked))
+        {
+            self.rows[y].marked = false;
+            self.cursor = Position { x: 0, y: y as u16 };
+            from = y;
+
+            if !ex_command.is_empty() {
+                result = self.execute_command_line(ex_command);
+                if result.is_err() {
+                    break;
+                }
+            }
+        }
+        self.in_global = false;
+
+        let (count, lines, count_only) = self.global_substitutions;
+        if count > 0 {
+            self.set_message(report(count, lines, count_only));
+        }
+
+        self.rows.iter_mut().for_each(|row| row.marked = false);
+        self.cursor.y = self.cursor.y.min(self.rows.len().saturating_sub(1) as u16);
+        result
+    }
+
+    /* runs `keys` as if they were typed in normal mode, once on every line of
+     * `rows` with the cursor at its start or once where the cursor is. A command
+     * left unfinished by the keys is aborted like with <Esc> */
+    fn normal(
+        &mut self,
+        keys: &[KeyEvent],
+        rows: Option<(usize, usize)>,
+        remap: bool,
+    ) -> std::result::Result<(), String> {
+        let cmdline = self.cmdline.clone();
+        // whatever is still waiting to be typed comes after the :normal keys
+        let pending = std::mem::take(&mut self.pending_keys);
+        let (current_mode, mode) = (self.current_mode, self.mode.clone());
+        let redraw = std::mem::replace(&mut self.redraw, false);
+
+        let lines: Vec<Option<usize>> = match rows {
+            Some((first, last)) => (first..=last).map(Some).collect(),
+            None => vec![None],
+        };
+        let mut result = Ok(());
+        for line in lines {
+            if let Some(y) = line {
+                // earlier lines may have deleted this one
+                if y >= self.rows.len() {
+                    break;
+                }
+                self.cursor = Position { x: 0, y: y as u16 };
+            }
+            self.current_mode = Mode::Normal;
+            self.mode = "Normal".to_string();
+
+            result = self.feed_keys(keys, remap).map_err(|e| e.to_string());
+            self.normal_keys.clear();
+            if result.is_err() || self.quit_kass {
+                break;
+            }
+        }
+
+        self.redraw = redraw;
+        (self.current_mode, self.mode) = (current_mode, mode);
+        self.cmdline = cmdline;
+        self.pending_keys = pending;
+        self.clamp_cursor_x();
+        result
+    }
+
+    /* the :map family. A leading n, v, i or c picks the mode, without one
+     * it is normal and visual mode, with a bang insert and command-line mode */
+    fn map(&mut self, name: &str, command: &ExCommand) -> std::result::Result<(), String> {
+        let (letter, base) = match name {
+            "map" | "noremap" | "unmap" | "mapclear" => (None, name),
+            _ => (name.chars().next(), &name[1..]),
+        };
+        let modes = match (letter, command.bang) {
+            (Some('n'), _) => vec![MapMode::Normal],
+            (Some('v'), _) => vec![MapMode::Visual],
+            (Some('i'), _) => vec![MapMode::Insert],
+            (Some('c'), _) => vec![MapMode::Command],
+            (_, false) => vec![MapMode::Normal, MapMode::Visual],
+            (_, true) => vec![MapMode::Insert, MapMode::Command],
+        };
+        let leader = self.options.string("mapleader");
+        let args = command.args.trim();
+        let (lhs, rhs) = match args.split_once(char::is_whitespace) {
+            Some((lhs, rhs)) => (lhs, rhs.trim_start()),
+            None => (args, ""),
+        };
+
+        match base {
+            "mapclear" => modes.iter().for_each(|mode| self.keymap.clear(*mode)),
+            "unmap" => {
+                if lhs.is_empty() {
+                    return Err(String::from("E474: Invalid argument"));
+                }
+                let mut found = false;
+                for mode in &modes {
+                    found |= self.keymap.unmap(*mode, lhs, &leader);
+                }
+                if !found {
+                    return Err(String::from("E31: No such mapping"));
+                }
+            }
+            // :map and :map {lhs} list what is there
+            _ if rhs.is_empty() => {
+                let lines = self.keymap.list(&modes, lhs, &leader);
+                if lines.is_empty() {
+                    self.set_message("No mapping found");
+                } else {
+                    // a single line would stay on the message line, the listing should stay up
+                    self.set_message(format!("--- Mappings ---\n{}", lines.join("\n")));
+                }
+            }
+            _ => {
+                for mode in modes {
+                    self.keymap.map(mode, lhs, rhs, base == "noremap", &leader);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // `remap` runs the keys through the mappings like typed ones, :normal! doesn't
+    fn feed_keys(&mut self, keys: &[KeyEvent], remap: bool) -> Result<()> {
+        for key in keys {
+            if self.quit_kass {
+                return Ok(());
+            }
+            if remap {
+                self.handle_key(*key)?;
+            } else {
+                self.dispatch_key(*key)?;
+            }
+        }
+        // a mapping still waiting for more keys doesn't get them
+        self.resolve_keys(true)?;
+        if !matches!(self.current_mode, Mode::Normal) {
+            self.dispatch_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE))?;
+        }
+        Ok(())
+    }
+
+    /* :set, :setlocal and :setglobal. Without arguments they list the options
+     * that differ from their defaults, `all` lists every one */
+    fn set_options(&mut self, args: &str, scope: SetScope) -> std::result::Result<(), String> {
+        let args = options::split_args(args);
+        if args.is_empty() || args == ["all"] {
+            let defaults = Options::new();
+            let listing = self.options.all();
+            let lines: Vec<&str> = listing
+                .lines()
+                .zip(defaults.all().lines().collect::<Vec<_>>())
+                .filter(|(line, default)| args == ["all"] || line != default)
+                .map(|(line, _)| line)
+                .collect();
+            self.set_message(format!("--- Options ---\n{}", lines.join("\n")));
+            return Ok(());
+        }
+
+        let mut shown = Vec::new();
+        let mut result = Ok(());
+        for arg in &args {
+            match self.options.set(arg, scope) {
+                Ok(Some(value)) => shown.push(value),
+                Ok(None) => {}
+                Err(error) => {
+                    result = Err(error);
+                    break;
+                }
+            }
+        }
+        self.apply_layout();
+        if !shown.is_empty() {
+            self.set_message(shown.join("  "));
+        }
+        result
+    }
+
+    // hands the option values to the parts of the editor that keep their own copy
+    fn apply_options(&mut self) {
+        self.search.ignorecase = self.options.bool("ignorecase");
+        self.search.smartcase = self.options.bool("smartcase");
+        self.search.iskeyword = self.options.string("iskeyword");
+        self.search.history_size = self.options.number("history");
+        self.cmdline.history_size = self.options.number("history");
+        self.undo_history
+            .set_levels(self.options.number("undolevels"));
+        self.screen.set_options(&self.options);
+    }
+
+    fn apply_theme(&mut self) {
+        self.screen.set_theme(&self.theme);
+        self.statusbar.set_theme(&self.theme);
+    }
+
+    /* runs the user config, then the filetype options, the project's
+     * .kass.toml and the .editorconfig of the file, each one more specific
+     * than the one before. With `reset` options and colors go back to their defaults
+     * first so that whatever was taken out of the files is undone */
+    fn load_config(&mut self, reset: bool) {
+        if reset {
+            self.options = Options::new();
+            self.theme = Theme::new();
+            self.config = Config::default();
+        }
+        for arg in &self.file_format {
+            let _ = self.options.set(arg, SetScope::Local);
+        }
+
+        let mut errors = Vec::new();
+        if let Some(path) = config::user_config_path() {
+            if let Err(error) = self.source(&path) {
+                errors.push(error);
+            }
+        }
+        errors.extend(self.apply_file_options());
+        self.apply_options();
+        self.apply_theme();
+
+        // a broken config shouldn't keep the file from opening, the errors are shown instead
+        if !errors.is_empty() {
+            self.set_message(errors.join("\n"));
+        }
+    }
+
+    /* the defaults of the file's type and the options the config, .kass.toml
+     * and .editorconfig files have for the open file. A project config comes with the file's tree, so every
+     * file opened gets the one of its own tree, as local options */
+    fn apply_file_options(&mut self) -> Vec<String> {
+        let mut errors = Vec::new();
+        let file = PathBuf::from(&self.filepath);
+        let extension = file.extension().and_then(|ext| ext.to_str());
+        for arg in options::filetype_defaults(extension.unwrap_or_default()) {
+            let _ = self.options.set(&arg, SetScope::Local);
+        }
+        if let Err(error) = self.apply_filetype() {
+            errors.push(error);
+        }
+        if let Some(path) = config::project_config_path(&file) {
+            match config::load_project(&path, extension.unwrap_or_default()) {
+                Ok(args) => errors.extend(args.iter().filter_map(|arg| {
+                    let error = self.options.set(arg, SetScope::Local).err()?;
+                    Some(format!("{}: {error}", path.display()))
+                })),
+                Err(error) => errors.push(error),
+            }
+        }
+        for arg in editorconfig::set_args(&editorconfig::properties(&file)) {
+            if let Err(error) = self.options.set(&arg, SetScope::Local) {
+                errors.push(format!(".editorconfig: {error}"));
+            }
+        }
+        errors
+    }
+
+    /* :source, a .toml file is read as a config and anything else as ex
+     * commands. A failing command doesn't stop the ones after it */
+    fn source(&mut self, path: &Path) -> std::result::Result<(), String> {
+        let loaded = config::load(path)?;
+        self.run_config(path, loaded)
+    }
+
+    fn run_config(&mut self, path: &Path, loaded: Config) -> std::result::Result<(), String> {
+        let mut errors = Vec::new();
+        for command in &loaded.commands {
+            if let Err(error) = self.execute_command_line(command) {
+                errors.push(format!("{command}: {error}"));
+            }
+        }
+        self.config.filetypes.extend(loaded.filetypes);
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Error detected while processing {}:\n{}",
+                path.display(),
+                errors.join("\n")
+            ))
+        }
+    }
+
+    // the [filetype.<extension>] options of the config for the open file
+    fn apply_filetype(&mut self) -> std::result::Result<(), String> {
+        let extension = Path::new(&self.filepath)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default();
+        let Some(args) = self.config.filetypes.get(extension).cloned() else {
+            return Ok(());
+        };
+        let errors: Vec<String> = args
+            .iter()
+            .filter_map(|arg| self.options.set(arg, SetScope::Local).err())
+            .map(|error| format!("filetype.{extension}: {error}"))
+            .collect();
+        self.apply_options();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    // the cursor shape for the current mode, from 'insertcursor' and 'normalcursor'
+    fn apply_cursor_style(&self) -> Result<()> {
+        if !self.redraw {
+            return Ok(());
+        }
+        let style = match self.current_mode {
+            Mode::Insert => self.options.string("insertcursor"),
+            _ => self.options.string("normalcursor"),
+        };
+        let style = match style.as_str() {
+            "block" => SetCursorStyle::SteadyBlock,
+            "blinkblock" => SetCursorStyle::BlinkingBlock,
+            "bar" => SetCursorStyle::SteadyBar,
+            "blinkbar" => SetCursorStyle::BlinkingBar,
+            "underline" => SetCursorStyle::SteadyUnderScore,
+            "blinkunderline" => SetCursorStyle::BlinkingUnderScore,
+            _ => SetCursorStyle::DefaultUserShape,
+        };
+        execute!(stdout(), style)
+    }
+
+    // :[range]s/pattern/replacement/[flags]
+    fn substitute(
+        &mut self,
+        first: usize,
+        last: usize,
+        substitute: Substitute,
+    ) -> std::result::Result<(), String> {
+        let mut pattern = if substitute.pattern.is_empty() {
+            self.search.pattern.clone()
+        } else {
+            substitute.pattern.clone()
+        };
+        if pattern.is_empty() {
+            return Err(String::from("E35: No previous regular expression"));
+        }
+
+        // the pattern becomes the last search pattern, so n and :s// reuse it
+        self.search.remember(&pattern);
+        self.last_substitute = Some(substitute.clone());
+
+        match substitute.flags.ignore_case {
+            Some(true) => pattern.push_str("\\c"),
+            Some(false) => pattern.push_str("\\C"),
+            None => {}
+        }
+        let regex = self
+            .search
+            .compile(&pattern)
+            .ok_or(format!("E383: Invalid search string: {pattern}"))?;
+        if self.rows.is_empty() {
+            return Err(format!("E486: Pattern not found: {pattern}"));
+        }
+        let flags = substitute.flags;
+
+        if flags.confirm && !flags.count_only {
+            self.confirm = Some(Confirm {
+                pattern: regex,
+                replacement: substitute.replacement,
+                global: flags.global,
+                row: first,
+                col: 0,
+                last_row: last,
+                count: 0,
+                lines: 0,
+                changed_row: None,
+            });
+            self.current_mode = Mode::Confirm;
+            self.mode = "Confirm".to_string();
+            self.confirm_next();
+            return Ok(());
+        }
+
+        let (mut count, mut lines) = (0, 0);
+        let (mut y, mut last) = (first, last.min(self.rows.len() - 1));
+        let mut last_changed = None;
+        while y <= last {
+            if let Some((changed, n)) = substitute_line(
+                &regex,
+                &self.rows[y].chars,
+                &substitute.replacement,
+                flags.global,
+            ) {
+                count += n;
+                lines += 1;
+                if !flags.count_only {
+                    let added = self.set_row_text(y, changed);
+                    y += added;
+                    last += added;
+                    last_changed = Some(y);
+                }
+            }
+            y += 1;
+        }
+
+        if let Some(y) = last_changed {
+            self.cursor = Position { x: 0, y: y as u16 };
+        }
+        // :global reports the total once it's done, lines without a match are fine there
+        if self.in_global {
+            let total = &mut self.global_substitutions;
+            *total = (total.0 + count, total.1 + lines, flags.count_only);
+            return Ok(());
+        }
+        if count == 0 {
+            return Err(format!("E486: Pattern not found: {pattern}"));
+        }
+        self.set_message(report(count, lines, flags.count_only));
+        Ok(())
+    }
+
+    fn substitute_preview(&self) -> Option<SubstitutePreview> {
+        if !matches!(self.current_mode, Mode::Command) || self.rows.is_empty() {
+            return None;
+        }
+        let command = ex::parse(&self.cmdline.text).ok()?;
+        if command.full_name() != Some("substitute") || command.args.is_empty() {
+            return None;
+        }
+        let (first, last) = self.command_rows(&command, 1, None).ok()?;
+
+        let substitute = Substitute::parse(&command.args).ok()?;
+        if substitute.flags.count_only {
+            return None;
+        }
+        let mut pattern = if substitute.pattern.is_empty() {
+            self.search.pattern.clone()
+        } else {
+            substitute.pattern.clone()
+        };
+        match substitute.flags.ignore_case {
+            Some(true) => pattern.push_str("\\c"),
+            Some(false) => pattern.push_str("\\C"),
+            None => {}
+        }
+        let regex = self.search.compile(&pattern)?;
+
+        let rowoff = self.rowoff as usize;
+        let visible = rowoff..(rowoff + self.screen.boundary().y as usize).min(self.rows.len());
+        let mut preview = SubstitutePreview {
+            rows: self.rows[visible.clone()].to_vec(),
+            first: visible.start,
+            highlights: Vec::new(),
+            pane: Vec::new(),
+        };
+        let pane_height = self.options.number("previewheight");
+        let mut offscreen = false;
+
+        for y in first..=last.min(self.rows.len() - 1) {
+            // once the pane is full only the lines on screen are left to preview
+            if !visible.contains(&y) && offscreen && preview.pane.len() >= pane_height {
+                if y >= visible.end {
+                    break;
+                }
+                continue;
+            }
+            let Some((changed, spans)) = preview_line(
+                &regex,
+                &self.rows[y].chars,
+                &substitute.replacement,
+                substitute.flags.global,
+                "⏎",
+            ) else {
+                continue;
+            };
+
+            if preview.pane.len() < pane_height {
+                preview.pane.push((y, changed.clone()));
+            }
+            if !visible.contains(&y) {
+                offscreen = true;
+                continue;
+            }
+            preview
+                .highlights
+                .extend(spans.into_iter().map(|(start, end)| Highlight {
+                    row: y,
+                    start,
+                    end,
+                    kind: HighlightKind::Search,
+                }));
+            preview.rows[y - visible.start].set_chars(changed);
+        }
+
+        // every line fits on the screen, the in place preview is enough
+        if !offscreen {
+            preview.pane.clear();
+        }
+        Some(preview)
+    }
+
+    /* replaces row `y` with `text`, splitting it where the replacement
+     * inserted line breaks. Returns the number of rows added */
+    fn set_row_text(&mut self, y: usize, text: String) -> usize {
+        let lines: Vec<String> = text.split('\n').map(String::from).collect();
+        let added = lines.len() - 1;
+        self.splice_rows(y..y + 1, lines);
+        added
+    }
+
+    // moves to the next match of an interactive substitute, or finishes it
+    fn confirm_next(&mut self) {
+        let Some(confirm) = self.confirm.as_mut() else {
+            return;
+        };
+
+        while confirm.row <= confirm.last_row.min(self.rows.len().saturating_sub(1)) {
+            let line = &self.rows[confirm.row].chars;
+            let found = confirm
+                .pattern
+                .find_iter(line)
+                .find(|(start, _)| *start >= confirm.col && (confirm.global || confirm.col == 0));
+
+            if let Some((start, _)) = found {
+                self.cursor = Position {
+                    x: start as u16,
+                    y: confirm.row as u16,
+                };
+                let message = format!("replace with {} (y/n/a/q/l)", confirm.replacement);
+                self.set_message(message);
+                return;
+            }
+            confirm.row += 1;
+            confirm.col = 0;
+        }
+
+        self.finish_confirm();
+    }
+
+    fn finish_confirm(&mut self) {
+        if let Some(confirm) = self.confirm.take() {
+            if confirm.count == 0 {
+                self.set_message(format!("E486: Pattern not found: {}", self.search.pattern));
+            } else {
+                self.set_message(report(confirm.count, confirm.lines, false));
+            }
+        }
+        self.current_mode = Mode::Normal;
+        self.mode = "Normal".to_string();
+    }
+
+    // replaces the match under the cursor and moves past it
+    fn confirm_replace(&mut self) {
+        let Some(mut confirm) = self.confirm.take() else {
+            return;
+        };
+        let (y, x) = (self.cursor.row(), self.cursor.x as usize);
+        let line = self.rows[y].chars.clone();
+
+        if let Some(captures) = confirm.pattern.captures_at(&line, x) {
+            let m = captures.get(0).unwrap();
+            let replacement = expand(&captures, &confirm.replacement);
+            let changed = format!("{}{}{}", &line[..m.start()], replacement, &line[m.end()..]);
+
+            let added = self.set_row_text(y, changed);
+            confirm.count += 1;
+            if confirm.changed_row != Some(y) {
+                confirm.lines += 1;
+            }
+
+            // continue right after the replacement, which may have split the line
+            let tail = replacement.rsplit('\n').next().unwrap_or_default().len();
+            confirm.row = y + added;
+            confirm.col = if added > 0 { tail } else { m.start() + tail };
+            confirm.last_row += added;
+            confirm.changed_row = Some(confirm.row);
+            if m.start() == m.end() {
+                confirm.col += 1;
+            }
         }
-        Ok(())
+        self.confirm = Some(confirm);
     }
 
-    //cursor handler
-
-    fn move_cursor(&mut self, key: MovementKey) {
-        match key {
-            MovementKey::Left => {
-                if self.cursor.x != 0 {
-                    self.cursor.x -= 1;
-                } else if self.cursor.y > 0 {
-                    self.cursor.y -= 1;
-                    self.cursor.x = self.rows[self.cursor.y as usize].len() as u16;
+    fn handle_confirm_mode(&mut self) -> Result<()> {
+        match self.key_event.code {
+            KeyCode::Char('y') => {
+                self.confirm_replace();
+                self.skip_confirm_line();
+                self.confirm_next();
+            }
+            KeyCode::Char('l') => {
+                self.confirm_replace();
+                self.finish_confirm();
+            }
+            KeyCode::Char('a') => {
+                while self.confirm.is_some() {
+                    self.confirm_replace();
+                    self.skip_confirm_line();
+                    self.confirm_next();
                 }
             }
-
-            MovementKey::Right => {
-                if self.cursor.y < self.rows.len() as u16 {
-                    let idx = self.cursor.y;
-
-                    // checks whether cursor exceeds rows length or not
-                    if self.cursor.x < self.rows[idx as usize].len() as u16 {
-                        self.cursor.x += 1;
-                    } else if self.cursor.y < self.rows.len() as u16 - 1 {
-                        self.cursor.y += 1;
-                        self.cursor.x = 0;
-                    }
+            KeyCode::Char('n') => {
+                if let Some(confirm) = self.confirm.as_mut() {
+                    confirm.row = self.cursor.row();
+                    confirm.col = self.cursor.x as usize + 1;
                 }
+                self.skip_confirm_line();
+                self.confirm_next();
             }
-
-            MovementKey::Up => self.cursor.y = self.cursor.y.saturating_sub(1),
-            MovementKey::Down if self.cursor.y < self.rows.len() as u16 - 1 => self.cursor.y += 1,
+            KeyCode::Char('q') | KeyCode::Esc => self.finish_confirm(),
             _ => {}
         }
+        self.refresh_screen()
+    }
 
-        // for clamping the cursor to the front of the line after the end of the previous line
-        let rowlen = if self.cursor.y as usize >= self.rows.len() {
+    // without the g flag only the first match of every line is considered
+    fn skip_confirm_line(&mut self) {
+        if let Some(confirm) = self.confirm.as_mut() {
+            if !confirm.global {
+                confirm.row += 1;
+                confirm.col = 0;
+            }
+        }
+    }
+
+    // scrolloff can never be more than half the screen, otherwise the cursor couldn't move
+    fn effective_scrolloff(&self) -> u16 {
+        let height = self.screen.boundary().y;
+        (self.options.number("scrolloff") as u16).min(height.saturating_sub(1) / 2)
+    }
+
+    fn last_row(&self) -> u16 {
+        (self.rows.len() as u16).saturating_sub(1)
+    }
+
+    // keeps the cursor inside the visible rows after the view was moved
+    fn clamp_cursor_to_view(&mut self) {
+        let height = self.screen.boundary().y;
+        let so = self.effective_scrolloff();
+
+        let top = if self.rowoff == 0 {
             0
         } else {
-            self.rows[self.cursor.y as usize].len() as u16
+            self.rowoff + so
         };
+        let bottom = (self.rowoff + height).saturating_sub(so + 1);
 
-        // compare length of the row and cursor x position and gives min value between them
+        self.cursor.y = self.cursor.y.max(top).min(bottom).min(self.last_row());
+        self.clamp_cursor_x();
+    }
+
+    fn clamp_cursor_x(&mut self) {
+        let rowlen = if self.cursor.above(self.rows.len()) {
+            self.rows[self.cursor.row()].len() as u16
+        } else {
+            0
+        };
         self.cursor.x = self.cursor.x.min(rowlen);
+    }
 
+    // Ctrl-f / Ctrl-b, keeps two lines of the previous page visible
+    fn scroll_page(&mut self, direction: i32) -> Result<()> {
+        let height = self.screen.boundary().y;
+        let amount = height.saturating_sub(2).max(1);
+
+        if direction > 0 {
+            self.rowoff = (self.rowoff + amount).min(self.last_row());
+        } else {
+            self.rowoff = self.rowoff.saturating_sub(amount);
+        }
+        self.clamp_cursor_to_view();
         self.refresh_screen()
-            .expect("not working refresh screen in move cursor function");
     }
 
-    fn scroll(&mut self) -> Result<()> {
-        let bounds = self.screen.boundary();
+    // Ctrl-d / Ctrl-u, moves both the view and the cursor by half a screen
+    fn scroll_half_page(&mut self, direction: i32) -> Result<()> {
+        let amount = (self.screen.boundary().y / 2).max(1);
 
-        // for vertical scrolling
-        if self.cursor.y < self.rowoff {
-            self.rowoff = self.cursor.y;
+        if direction > 0 {
+            self.rowoff = (self.rowoff + amount).min(self.last_row());
+            self.cursor.y = (self.cursor.y + amount).min(self.last_row());
+        } else {
+            self.rowoff = self.rowoff.saturating_sub(amount);
+            self.cursor.y = self.cursor.y.saturating_sub(amount);
         }
-        if self.cursor.y >= self.rowoff + bounds.y {
-            self.rowoff = self.cursor.y - bounds.y + 1;
+        self.clamp_cursor_to_view();
+        self.refresh_screen()
+    }
+
+    // Ctrl-e / Ctrl-y
+    fn scroll_lines(&mut self, direction: i32) -> Result<()> {
+        if direction > 0 {
+            self.rowoff = (self.rowoff + 1).min(self.last_row());
+        } else {
+            self.rowoff = self.rowoff.saturating_sub(1);
         }
+        self.clamp_cursor_to_view();
+        self.refresh_screen()
+    }
 
-        // for horizontal scrolling
-        if self.cursor.x < self.coloff {
-            self.coloff = self.cursor.x;
+    // zz / zt / zb
+    fn align_cursor_line(&mut self, at: ScreenPos) -> Result<()> {
+        let height = self.screen.boundary().y;
+        let so = self.effective_scrolloff();
+
+        self.rowoff = match at {
+            ScreenPos::Start => self.cursor.y.saturating_sub(so),
+            ScreenPos::Middle => self.cursor.y.saturating_sub(height / 2),
+            ScreenPos::End => (self.cursor.y + so + 1).saturating_sub(height),
+        };
+        self.refresh_screen()
+    }
+
+    // H / M / L
+    fn cursor_to_screen_line(&mut self, at: ScreenPos) -> Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
         }
-        if self.cursor.x >= self.coloff + bounds.x {
-            self.coloff = self.cursor.x - bounds.x + 1;
+        let height = self.screen.boundary().y;
+        let visible = height.min((self.rows.len() as u16).saturating_sub(self.rowoff));
+
+        self.cursor.y = match at {
+            ScreenPos::Start => self.rowoff,
+            ScreenPos::Middle => self.rowoff + visible.saturating_sub(1) / 2,
+            ScreenPos::End => self.rowoff + visible.saturating_sub(1),
+        };
+        self.clamp_cursor_to_view();
+        self.refresh_screen()
+    }
+
+    // zh / zl
+    fn scroll_columns(&mut self, direction: i32) -> Result<()> {
+        let width = self.screen.boundary().x;
+
+        if direction > 0 {
+            self.coloff += 1;
+        } else {
+            self.coloff = self.coloff.saturating_sub(1);
         }
+        self.cursor.x = self
+            .cursor
+            .x
+            .max(self.coloff)
+            .min((self.coloff + width).saturating_sub(1));
+        self.clamp_cursor_x();
+        self.refresh_screen()
+    }
 
-        self.screen.clear()?;
-        Ok(())
+    // zs / ze
+    fn align_cursor_column(&mut self, at: ScreenPos) -> Result<()> {
+        let width = self.screen.boundary().x;
+
+        self.coloff = match at {
+            ScreenPos::End => (self.cursor.x + 1).saturating_sub(width),
+            ScreenPos::Start | ScreenPos::Middle => self.cursor.x,
+        };
+        self.refresh_screen()
     }
 
     // handle insert mode
     fn handle_insert_mode(&mut self) -> Result<()> {
-               match self.key_event {
+        if self.options.bool("modeless") && self.handle_modeless_key()? {
+            return Ok(());
+        }
+        match self.key_event {
             KeyEvent {
                 code: KeyCode::Backspace,
                 modifiers: KeyModifiers::NONE,
@@ -552,91 +3768,534 @@ impl Kass {
                 modifiers: KeyModifiers::NONE,
                 ..
             } => {
-                self.insert_char('\t');
+                if self.options.bool("expandtab") {
+                    // spaces up to the next tab stop
+                    let tabstop = self.options.number("tabstop");
+                    for _ in 0..tabstop - self.cursor.x as usize % tabstop {
+                        self.insert_char(' ');
+                    }
+                } else {
+                    self.insert_char('\t');
+                }
                 self.refresh_screen()?;
-
-                // cursor::MoveTo(self.cursor.x + 4, self.cursor.y);
-                // cursor::MoveToColumn(self.cursor.x);
             }
             _ => {
                 // // print
-                if !self.character.is_control() {
+                let modifiers = self.key_event.modifiers - KeyModifiers::SHIFT;
+                if !self.character.is_control() && modifiers.is_empty() {
                     self.insert_char(self.character);
                     self.refresh_screen()?;
                 }
             }
         }
-        
+
         self.mode_changed = false;
 
         Ok(())
     }
 
-    fn handle_command_mode(&mut self) -> Result<()> {
-        let position_x = 0;
-        let position_y = self.terminal_height - 1;
+    // the Shift-arrow selection of the modeless preset, None when it is empty
+    fn selection_range(&self) -> Option<(Position, Position)> {
+        let anchor = self.selection.as_ref()?;
+        let (start, end) = self.motion_range(anchor, &self.cursor, MotionKind::Exclusive);
+        ((start.y, start.x) != (end.y, end.x)).then_some((start, end))
+    }
 
-        execute!(
-            stdout(),
-            cursor::MoveTo(position_x as u16, position_y as u16)
-        )?;
+    // the text of [start, end), a line each
+    fn range_lines(&self, start: &Position, end: &Position) -> Vec<String> {
+        (start.y..=end.y.min(self.last_row()))
+            .map(|y| {
+                let (from, to) = self.range_on_row(y, start, end);
+                self.rows[y as usize].chars[from..to].to_string()
+            })
+            .collect()
+    }
 
-        match self.key_event {
-            KeyEvent {
-                code: KeyCode::Backspace,
-                // modifiers: KeyModifiers::NONE,
-                ..
-            } => {
-                self.move_cursor(MovementKey::Left);
+    // removes [start, end), joining what is left of the first and last line
+    fn delete_range(&mut self, start: &Position, end: &Position) {
+        let end_y = end.row().min(self.rows.len().saturating_sub(1));
+        if start.row() > end_y {
+            return;
+        }
+        let (from, _) = self.range_on_row(start.y, start, end);
+        let (_, to) = self.range_on_row(end_y as u16, start, end);
+        let head = self.rows[start.row()].chars[..from].to_string();
+        let tail = self.rows[end_y].chars[to..].to_string();
+        self.splice_rows(start.row()..end_y + 1, vec![head + &tail]);
+        self.cursor = Position {
+            x: from as u16,
+            y: start.y,
+        };
+    }
 
-                self.del_char();
-            }
+    // types `text` at the cursor, line breaks split the line
+    fn insert_text(&mut self, text: &str) {
+        if self.rows.is_empty() {
+            self.insert_row(0, String::new());
+        }
+        let y = self.cursor.row();
+        let x = (self.cursor.x as usize).min(self.rows[y].len());
+        let tail = self.rows[y].chars[x..].to_string();
+        let head = self.rows[y].chars[..x].to_string();
 
-            KeyEvent {
-                code: KeyCode::Enter,
-                ..
-            } => {
-                match self.command.as_str() {
-                    // quit
-                    ":q" => self.quit_kass = true,
+        let mut lines: Vec<String> = text.split('\n').map(String::from).collect();
+        lines[0].insert_str(0, &head);
+        let last = lines.len() - 1;
+        self.cursor = Position {
+            x: lines[last].len() as u16,
+            y: (y + last) as u16,
+        };
+        lines[last].push_str(&tail);
+        self.splice_rows(y..y + 1, lines);
+    }
+
+    /* the keys of the modeless preset on top of insert mode, returns whether
+     * the key was one of them. Without a selection Ctrl-C and Ctrl-X take the
+     * whole line */
+    fn handle_modeless_key(&mut self) -> Result<bool> {
+        let KeyEvent {
+            code, modifiers, ..
+        } = self.key_event;
+        let selection = self.selection_range();
+        let movement = matches!(
+            code,
+            KeyCode::Left
+                | KeyCode::Right
+                | KeyCode::Up
+                | KeyCode::Down
+                | KeyCode::Home
+                | KeyCode::End
+        );
 
-                    // write to file
-                    ":w" => self.write_to_file()?,
+        // the keys ex commands have in this preset, like Ctrl-s for :write
+        let name = keys::name(&self.key_event);
+        if let Some(command) = ex::COMMANDS
+            .iter()
+            .find(|command| !command.modeless.is_empty() && command.modeless == name)
+        {
+            if let Err(error) = self.execute_command_line(command.name) {
+                self.set_message(error);
+            }
+            // the palette (or whatever took over) starts without a selection
+            if matches!(self.current_mode, Mode::Insert) {
+                self.refresh_screen()?;
+            } else {
+                self.selection = None;
+            }
+            return Ok(true);
+        }
 
-                    // write and quit
-                    ":wq" => {
-                        self.write_to_file()?;
-                        self.quit_kass = true;
+        match (code, modifiers) {
+            (KeyCode::Char(key), KeyModifiers::CONTROL) => match key {
+                'c' | 'x' => {
+                    let line = Position {
+                        x: 0,
+                        y: self.cursor.y,
+                    };
+                    let (start, end) = selection.clone().unwrap_or((
+                        line.clone(),
+                        Position {
+                            x: 0,
+                            y: line.y + 1,
+                        },
+                    ));
+                    self.clipboard = self.range_lines(&start, &end);
+                    // a whole line that is the last one still ends in a line break
+                    if selection.is_none() && self.clipboard.len() == 1 {
+                        self.clipboard.push(String::new());
                     }
-                    ":set nu" => {
-                        self.number_display = true;
+                    if key == 'x' && !self.rows.is_empty() {
+                        if selection.is_none() && end.row() >= self.rows.len() {
+                            self.splice_rows(start.row()..start.row() + 1, Vec::new());
+                            self.cursor = Position {
+                                x: 0,
+                                y: start.y.min(self.rows.len().saturating_sub(1) as u16),
+                            };
+                        } else {
+                            self.delete_range(&start, &end);
+                        }
+                        self.selection = None;
                     }
-
-                    _ => {}
                 }
+                'v' => {
+                    if let Some((start, end)) = selection {
+                        self.delete_range(&start, &end);
+                        self.selection = None;
+                    }
+                    self.insert_text(&self.clipboard.join("\n"));
+                }
+                'f' => {
+                    self.selection = None;
+                    self.start_search(true)?;
+                    return Ok(true);
+                }
+                _ => return Ok(false),
+            },
 
-                self.command = String::from("");
-                self.refresh_screen()?;
-                self.current_mode = Mode::Normal;
+            // Shift with a movement key selects, without it the selection goes away
+            _ if movement => {
+                if modifiers.contains(KeyModifiers::SHIFT) {
+                    self.selection.get_or_insert(self.cursor.clone());
+                } else {
+                    self.selection = None;
+                }
+                match code {
+                    KeyCode::Home => self.cursor.x = 0,
+                    KeyCode::End => self.cursor.x = self.current_line().len() as u16,
+                    KeyCode::Left => self.move_cursor(MovementKey::Left),
+                    KeyCode::Right => self.move_cursor(MovementKey::Right),
+                    KeyCode::Up => self.move_cursor(MovementKey::Up),
+                    _ => self.move_cursor(MovementKey::Down),
+                }
             }
 
+            // typing replaces the selection, Backspace and Delete only remove it
+            (KeyCode::Char(_) | KeyCode::Enter | KeyCode::Tab, _)
+            | (KeyCode::Backspace | KeyCode::Delete, _)
+                if selection.is_some() =>
+            {
+                if let Some((start, end)) = selection {
+                    self.delete_range(&start, &end);
+                }
+                self.selection = None;
+                if !matches!(code, KeyCode::Backspace | KeyCode::Delete) {
+                    return Ok(false);
+                }
+            }
             _ => {
-                if !self.character.is_control() {
-                    self.command.push(self.character);
+                self.selection = None;
+                return Ok(false);
+            }
+        }
+        self.refresh_screen()?;
+        Ok(true)
+    }
 
-                    write!(stdout(), "{}", self.command)?;
-                    stdout().flush()?;
+    fn handle_command_mode(&mut self) -> Result<()> {
+        match self.key_event.code {
+            KeyCode::Tab => return self.complete(true),
+            KeyCode::BackTab => return self.complete(false),
+            _ => self.completion = None,
+        }
+
+        match self.cmdline.handle_key(&self.key_event) {
+            Input::Execute => {
+                let line = std::mem::take(&mut self.cmdline.text);
+                // lines run by :normal are not typed by the user
+                if self.redraw {
+                    self.cmdline.remember(&line);
+                }
+
+                // commands like :s///c pick their own mode
+                self.current_mode = Mode::Normal;
+                self.mode = "Normal".to_string();
+                if let Err(error) = self.execute_command_line(&line) {
+                    self.set_message(error);
+                }
+            }
+            Input::Cancel => {
+                self.current_mode = Mode::Normal;
+                self.mode = "Normal".to_string();
+            }
+            Input::Register(register) => {
+                if let Some(text) = self.register(register) {
+                    self.cmdline.insert(&text);
+                }
+            }
+            Input::Word => {
+                let line = self
+                    .rows
+                    .get(self.cursor.row())
+                    .map(|row| row.chars.clone());
+                if let Some(line) = line {
+                    if let Some((start, end)) = self.search.word_at(&line, self.cursor.x as usize) {
+                        self.cmdline.insert(&line[start..end]);
+                    }
                 }
             }
+            Input::Edited | Input::Ignored => {}
         }
 
+        // redraws the command line and the live :s preview
+        self.refresh_screen()?;
         self.mode_changed = false;
 
         Ok(())
     }
 
+    // Ctrl-p and :palette
+    fn open_palette(&mut self) -> Result<()> {
+        self.palette = Some(Palette::new(self.palette_entries()));
+        self.current_mode = Mode::Palette;
+        self.mode = "Palette".to_string();
+        self.mode_changed = true;
+        self.refresh_screen()
+    }
+
+    /* every ex command, then the mappings and the built-in keys of the mode the
+     * editor is used in, with the keys that run them */
+    fn palette_entries(&self) -> Vec<Entry> {
+        let modeless = self.options.bool("modeless");
+        let mode = if modeless {
+            MapMode::Insert
+        } else {
+            MapMode::Normal
+        };
+        let mut mappings = self.keymap.continuations(mode, &[]);
+        mappings.sort();
+
+        let mut entries: Vec<Entry> = ex::COMMANDS
+            .iter()
+            .map(|command| {
+                // a mapping to the bare command wins over the built-in key
+                let binding = mappings
+                    .iter()
+                    .find(|(_, rhs)| ex::mapped_command(rhs) == Some(command.name))
+                    .map(|(lhs, _)| lhs.clone())
+                    .unwrap_or_else(|| {
+                        let keys = if modeless {
+                            command.modeless
+                        } else {
+                            command.keys
+                        };
+                        keys.to_string()
+                    });
+                Entry {
+                    name: format!(":{}", command.name),
+                    description: command.description.to_string(),
+                    binding,
+                    action: if command.needs_args {
+                        Action::Prompt(format!("{} ", command.name))
+                    } else {
+                        Action::Command(command.name.to_string())
+                    },
+                }
+            })
+            .collect();
+
+        entries.extend(mappings.iter().map(|(lhs, rhs)| Entry {
+            name: rhs.clone(),
+            description: String::from("mapping"),
+            binding: lhs.clone(),
+            action: Action::Keys(lhs.clone()),
+        }));
+        // keys waiting for another one, like m{a-z}, can't be run from here
+        if !modeless {
+            entries.extend(
+                bindings::NORMAL
+                    .iter()
+                    .filter(|binding| !binding.keys.contains('{'))
+                    .filter(|binding| !mappings.iter().any(|(lhs, _)| lhs == binding.keys))
+                    .map(|binding| Entry {
+                        name: binding.description.to_string(),
+                        description: String::new(),
+                        binding: binding.keys.to_string(),
+                        action: Action::Keys(binding.keys.to_string()),
+                    }),
+            );
+        }
+        entries
+    }
+
+    fn handle_palette_mode(&mut self) -> Result<()> {
+        let Some(palette) = &mut self.palette else {
+            return Ok(());
+        };
+        let mut action = None;
+        let mut close = false;
+        match (self.key_event.code, self.key_event.modifiers) {
+            (KeyCode::Esc, _) => close = true,
+            (KeyCode::Enter, _) => {
+                action = palette.selection().map(|entry| entry.action.clone());
+                close = true;
+            }
+            (KeyCode::Down | KeyCode::Tab, _)
+            | (KeyCode::Char('n' | 'j'), KeyModifiers::CONTROL) => palette.step(true),
+            (KeyCode::Up | KeyCode::BackTab, _)
+            | (KeyCode::Char('p' | 'k'), KeyModifiers::CONTROL) => palette.step(false),
+            (KeyCode::Backspace, _) => palette.pop(),
+            (KeyCode::Char('u'), KeyModifiers::CONTROL) => palette.clear(),
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => palette.push(c),
+            _ => {}
+        }
+
+        if close {
+            self.palette = None;
+            self.current_mode = Mode::Normal;
+            self.mode = "Normal".to_string();
+        }
+        match action {
+            Some(Action::Command(line)) => {
+                self.cmdline.remember(&line);
+                if let Err(error) = self.execute_command_line(&line) {
+                    self.set_message(error);
+                }
+            }
+            Some(Action::Prompt(text)) => {
+                self.current_mode = Mode::Command;
+                self.mode = "Command".to_string();
+                self.cmdline.start(&text);
+            }
+            Some(Action::Keys(text)) => {
+                // like a mapping, the keys run before any typed after Enter
+                self.apply_modeless();
+                let typed = std::mem::replace(&mut self.pending_keys, keys::parse(&text));
+                self.resolve_keys(true)?;
+                self.pending_keys = typed;
+            }
+            None => {}
+        }
+        self.refresh_screen()?;
+        self.apply_cursor_style()
+    }
+
+    /* q: lists the command history in place of the buffer, the lines can be
+     * edited and Enter runs the one under the cursor */
+    fn open_history_window(&mut self) {
+        if self.history_window.is_some() {
+            return;
+        }
+        let mut rows: Vec<Row> = self
+            .cmdline
+            .history
+            .iter()
+            .map(|line| Row::new(line.clone()))
+            .collect();
+        rows.push(Row::new(String::new()));
+
+        self.history_window = Some(HistoryWindow {
+            rows: std::mem::replace(&mut self.rows, rows),
+            undo_history: std::mem::replace(&mut self.undo_history, UndoHistory::new()),
+            cursor: std::mem::take(&mut self.cursor),
+            rowoff: self.rowoff,
+            coloff: self.coloff,
+        });
+        self.cursor.y = (self.rows.len() - 1) as u16;
+        (self.rowoff, self.coloff) = (0, 0);
+    }
+
+    fn close_history_window(&mut self) {
+        if let Some(window) = self.history_window.take() {
+            self.rows = window.rows;
+            self.undo_history = window.undo_history;
+            self.cursor = window.cursor;
+            (self.rowoff, self.coloff) = (window.rowoff, window.coloff);
+        }
+    }
+
+    // Tab and Shift-Tab on the command line
+    fn complete(&mut self, forward: bool) -> Result<()> {
+        if self.completion.is_none() {
+            let before = &self.cmdline.text[..self.cmdline.cursor];
+            if let Some((start, candidates)) = self.completion_candidates(before) {
+                if !candidates.is_empty() {
+                    let typed = &before[start..];
+                    self.completion = Some(Completion::new(start, typed, candidates));
+                }
+            }
+        }
+
+        if let Some(completion) = self.completion.as_mut() {
+            let start = completion.start;
+            let text = completion.step(forward).to_string();
+            self.cmdline
+                .text
+                .replace_range(start..self.cmdline.cursor, &text);
+            self.cmdline.cursor = start + text.len();
+
+            // a single match is done, the next Tab completes from there
+            if completion.candidates.len() == 1 {
+                self.completion = None;
+            }
+        }
+
+        self.mode_changed = false;
+        self.refresh_screen()
+    }
+
+    /* where the word before the cursor starts and what it could be completed
+     * to: a command name, or the argument of the command */
+    fn completion_candidates(&self, before: &str) -> Option<(usize, Vec<String>)> {
+        let command = ex::parse(before).ok()?;
+        // the arguments are the end of the line as typed
+        let args_start = before.len() - command.args.len();
+
+        if command.args.is_empty() && !command.bang && before.ends_with(&command.name) {
+            let start = before.len() - command.name.len();
+            return Some((
+                start,
+                completion::filter(&command.name, &ex::command_names()),
+            ));
+        }
+
+        let start = before[args_start..]
+            .rfind(char::is_whitespace)
+            .map_or(args_start, |i| args_start + i + 1);
+        let word = &before[start..];
+
+        match ex::completion(command.full_name()?) {
+            // the allowed values of an option, or its current one
+            Complete::Option => match word.split_once('=') {
+                Some((name, value)) => {
+                    let name = name.trim_end_matches(['+', '-', '^']);
+                    let option = options::definition(name)?;
+                    let start = start + word.len() - value.len();
+                    if option.allowed.is_empty() {
+                        Some((start, vec![self.options.string(name)]))
+                    } else {
+                        let allowed: Vec<String> =
+                            option.allowed.iter().map(|v| v.to_string()).collect();
+                        Some((start, completion::filter(value, &allowed)))
+                    }
+                }
+                None => Some((
+                    start,
+                    completion::filter(word, &options::names(word.starts_with("no"))),
+                )),
+            },
+            Complete::File => Some((start, completion::paths(word))),
+            Complete::Buffer => {
+                let mut names: Vec<String> = self
+                    .buffers
+                    .iter()
+                    .map(|buffer| buffer.filepath.clone())
+                    .collect();
+                names.push(self.filepath.clone());
+                names.retain(|name| !name.is_empty());
+                Some((start, completion::filter(word, &names)))
+            }
+            Complete::Nothing => None,
+        }
+    }
+
+    /* the text of a register for Ctrl-r: `"` and `0` the last yank or delete,
+     * `/` the last search, `:` the last command line and `%` the file name */
+    fn register(&self, register: char) -> Option<String> {
+        match register {
+            '"' | '0' => Some(self.clipboard.join("\n")),
+            '/' => Some(self.search.pattern.clone()),
+            ':' => self.cmdline.history.last().cloned(),
+            '%' => Some(self.filepath.clone()),
+            '#' => self
+                .buffers
+                .iter()
+                .find(|buffer| Some(buffer.number) == self.alternate_buffer)
+                .map(|buffer| buffer.filepath.clone()),
+            _ => None,
+        }
+    }
+
     fn refresh_screen(&mut self) -> Result<()> {
+        if !self.redraw {
+            return Ok(());
+        }
         self.scroll()?;
+        if self.tabline_shown() {
+            self.screen.draw_tabline(&self.tab_labels(), self.tab)?;
+        }
+        if !self.windows.is_empty() {
+            self.draw_windows()?;
+        }
 
         // for displaying position of cursor if needed
         // print!("{} {}", self.cursor.x, self.cursor.y);
@@ -648,59 +4307,202 @@ impl Kass {
         //     .queue(terminal::Clear(terminal::ClearType::All))?
         //     .queue(cursor::MoveTo(0, 0))?;
 
-        self.statusbar
-            .paint(self.mode.clone(), self.absolute_path.clone())?;
+        // a :s being typed is previewed on a copy, self.rows is left alone
+        let preview = self.substitute_preview();
+        let mut highlights = match &preview {
+            Some(preview) => preview.highlights.clone(),
+            None => self.search_highlights(),
+        };
+        highlights.extend(self.selection_highlights());
+
+        let search_count = self.search_count();
+        self.statusbar.paint(
+            self.mode.clone(),
+            match self.history_window {
+                Some(_) => String::from("[Command Line]"),
+                None => self.absolute_path.clone(),
+            },
+            self.history_window.is_none() && self.modified(),
+            search_count,
+        )?;
         self.screen.draw_screen(
-            &self.rows,
+            preview.as_ref().map_or(&self.rows, |p| &p.rows),
+            preview.as_ref().map_or(0, |p| p.first),
             self.rowoff as usize,
             self.coloff as usize,
             self.cursor.y as usize,
+            &highlights,
         )?;
 
-        self.screen
-            .move_to(&self.cursor, self.rowoff, self.coloff)?;
+        if let Some(preview) = &preview {
+            if !preview.pane.is_empty() {
+                self.screen.draw_preview_pane(&preview.pane)?;
+            }
+        }
+
+        if let Some((prefix, entries)) = &self.which_key {
+            if !entries.is_empty() {
+                self.screen.draw_which_key(prefix, entries)?;
+            }
+        }
+
+        if let (Mode::Command, Some(completion)) = (self.current_mode, &self.completion) {
+            self.screen
+                .draw_wildmenu(&completion.candidates, completion.selected)?;
+        }
+
+        let message_line = self.terminal_height as u16 - 1;
+        if let Mode::Command = self.current_mode {
+            // long lines scroll to keep the cursor on screen
+            let width = self.screen.width().saturating_sub(2);
+            let before = self.cmdline.text[..self.cmdline.cursor].chars().count();
+            let start = self
+                .cmdline
+                .text
+                .char_indices()
+                .nth(before.saturating_sub(width))
+                .map_or(0, |(i, _)| i);
+            stdout()
+                .queue(cursor::MoveTo(0, message_line))?
+                .queue(Print(format!(":{}", &self.cmdline.text[start..])))?
+                .queue(cursor::MoveTo(
+                    1 + self.cmdline.text[start..self.cmdline.cursor]
+                        .chars()
+                        .count() as u16,
+                    message_line,
+                ))?;
+        } else if let (Mode::Palette, Some(palette)) = (self.current_mode, &self.palette) {
+            self.screen
+                .draw_palette(&palette.matches(), palette.selected)?;
+            stdout()
+                .queue(cursor::MoveTo(0, message_line))?
+                .queue(Print(format!("> {}", palette.input)))?;
+        } else if let Mode::Search = self.current_mode {
+            let prompt = if self.search.forward { '/' } else { '?' };
+            stdout()
+                .queue(cursor::MoveTo(0, message_line))?
+                .queue(Print(format!("{prompt}{}", self.search_input)))?;
+        } else {
+            let message_time = Duration::from_millis(self.options.number("messagetime") as u64);
+            if self.status_message.contains('\n') {
+                self.screen.draw_message_pane(&self.status_message)?;
+            } else if self.status_time.elapsed() < message_time {
+                stdout()
+                    .queue(cursor::MoveTo(0, message_line))?
+                    .queue(Print(&self.status_message))?;
+            }
+            self.screen
+                .move_to(&self.cursor, self.rowoff, self.coloff, self.current_line())?;
+        }
 
         stdout().flush()?;
 
         Ok(())
     }
 
-    // save file
-    fn rows_to_string(&self) -> String {
-        let mut content = String::new();
-
-        for row in &self.rows {
-            content.push_str(row.chars.as_str());
-            content.push('\n');
+    /* save file, in the line endings and with the last line break of the
+     * buffer. 'trimtrailingwhitespace' trims what is written, not the rows */
+    fn rows_to_string(&self, rows: &[Row]) -> String {
+        let separator = match self.options.string("fileformat").as_str() {
+            "dos" => "\r\n",
+            "mac" => "\r",
+            _ => "\n",
+        };
+        let trim = self.options.bool("trimtrailingwhitespace");
+        let mut content = rows
+            .iter()
+            .map(|row| match trim {
+                true => row.chars.trim_end_matches([' ', '\t']),
+                false => row.chars.as_str(),
+            })
+            .collect::<Vec<_>>()
+            .join(separator);
+        if !rows.is_empty() && (self.options.bool("endofline") || self.options.bool("fixendofline"))
+        {
+            content.push_str(separator);
         }
         content
     }
 
-    fn write_to_file(&mut self) -> Result<()> {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .open(&self.filepath)?;
-
-        let text = self.rows_to_string();
-        let len = text.as_bytes().len();
-
-        file.write_all(text.as_bytes())?;
+    // writes the whole buffer to its file, a read-only one too with `force`
+    fn write_to_file(&mut self, force: bool) -> Result<()> {
+        if self.filepath.is_empty() {
+            return Err(std::io::Error::other("E32: No file name"));
+        }
+        self.write_file(&PathBuf::from(&self.filepath), None, false, force)?;
+        if self.options.bool("trimtrailingwhitespace") {
+            self.trim_trailing_whitespace();
+        }
+        self.undo_history.mark_saved();
+        Ok(())
+    }
 
-        println!("hwllo rodsljflkdshfjkshdfdshg fhsdg fhdsasdgh f jghsahg sadjf");
+    /* once the file is written trimmed, the buffer follows it. The lines
+     * from the first to the last trimmed one are one undo step */
+    fn trim_trailing_whitespace(&mut self) {
+        let trailing = |row: &Row| row.chars.ends_with([' ', '\t']);
+        let (Some(first), Some(last)) = (
+            self.rows.iter().position(trailing),
+            self.rows.iter().rposition(trailing),
+        ) else {
+            return;
+        };
+        let lines = self.rows[first..=last]
+            .iter()
+            .map(|row| row.chars.trim_end_matches([' ', '\t']).to_string())
+            .collect();
+        self.splice_rows(first..last + 1, lines);
+        self.clamp_cursor_x();
+    }
 
-        self.draw_statusbar(len)?;
-        self.refresh_screen()?;
+    /* the zero based `range` of rows or all of them, appended to the file
+     * with `append`. `force` is :w!, it writes over a read-only file */
+    fn write_file(
+        &mut self,
+        path: &Path,
+        range: Option<(usize, usize)>,
+        append: bool,
+        force: bool,
+    ) -> Result<()> {
+        // encoded before the file is truncated, a failed conversion leaves it alone
+        let rows = match range {
+            Some((first, last)) => &self.rows[first..=last],
+            None => &self.rows[..],
+        };
+        let text = self.rows_to_string(rows);
+        let bytes = encoding::encode(&text, &self.options.string("fileencoding"))
+            .ok_or_else(|| std::io::Error::other("E513: Write error, conversion failed"))?;
+        let lines = rows.len();
 
-        Ok(())
-    }
-    pub fn draw_statusbar(&mut self, len: usize) -> Result<()> {
-        println!("hello world");
-        stdout()
-            .queue(cursor::MoveTo(0, self.terminal_height as u16 - 1))?
-            .queue(Print(format!("{len} bytes written to the disk")))?;
+        // appending can't be atomic, whatever is there already stays
+        let written = if append {
+            OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(&bytes))
+        } else {
+            save::write_atomic(path, &bytes, force)
+        };
+        written.map_err(|error| {
+            let reason = match path.parent() {
+                Some(dir) if error.kind() == ErrorKind::NotFound && !dir.exists() => {
+                    format!("{} doesn't exist (add ++p to create it)", dir.display())
+                }
+                _ => error.to_string(),
+            };
+            std::io::Error::new(
+                error.kind(),
+                format!("E212: Can't open file for writing: {reason}"),
+            )
+        })?;
 
-        self.refresh_screen()?;
+        self.set_message(format!(
+            "\"{}\" {lines}L, {}B {}",
+            path.display(),
+            bytes.len(),
+            if append { "appended" } else { "written" }
+        ));
         Ok(())
     }
 
@@ -709,7 +4511,9 @@ impl Kass {
         if !self.cursor.above(self.rows.len()) {
             self.insert_row(self.rows.len(), String::new());
         }
-        self.rows[self.cursor.y as usize].insert_char(self.cursor.x as usize, c);
+        let mut row = self.rows[self.cursor.y as usize].clone();
+        row.insert_char(self.cursor.x as usize, c);
+        self.set_line(self.cursor.y as usize, row.chars);
         self.cursor.x += 1;
     }
 
@@ -717,20 +4521,31 @@ impl Kass {
         if idx > self.rows.len() {
             return;
         }
-        self.rows.insert(idx, Row::new(row_content));
+        self.splice_rows(idx..idx, vec![row_content]);
+    }
+
+    /* every change of the lines goes through here, the undo history keeps
+     * what `range` held */
+    fn splice_rows(&mut self, range: Range<usize>, lines: Vec<String>) {
+        self.undo_history.splice(&mut self.rows, range, lines);
+    }
+
+    fn set_line(&mut self, y: usize, text: String) {
+        self.splice_rows(y..y + 1, vec![text]);
     }
 
     pub fn paste(&mut self) {
         if self.clipboard.len() > 1 {
-            for row in 0..self.clipboard.len() as usize {
+            for row in 0..self.clipboard.len() {
                 self.insert_row(
                     self.cursor.y as usize + 1 + row,
                     self.clipboard[row].clone(),
                 )
             }
         } else {
-            self.rows[self.cursor.y as usize]
-                .append_char(self.cursor.x as usize, self.clipboard[0].clone())
+            let mut row = self.rows[self.cursor.y as usize].clone();
+            row.append_char(self.cursor.x as usize, self.clipboard[0].clone());
+            self.set_line(self.cursor.y as usize, row.chars);
         }
     }
 
@@ -739,8 +4554,9 @@ impl Kass {
         if self.cursor.x == 0 {
             self.insert_row(row_idx, String::from(""));
         } else {
-            let content = self.rows[self.cursor.y as usize].split(self.cursor.x as usize);
-            self.insert_row(row_idx + 1, content);
+            let mut row = self.rows[row_idx].clone();
+            let content = row.split(self.cursor.x as usize);
+            self.splice_rows(row_idx..row_idx + 1, vec![row.chars, content]);
         };
 
         self.cursor.x = 0;
@@ -768,7 +4584,9 @@ impl Kass {
         let curr_row = self.cursor.y as usize;
 
         if self.cursor.x > 0 {
-            if self.rows[curr_row].del_char(self.cursor.x as usize - 1) {
+            let mut row = self.rows[curr_row].clone();
+            if row.del_char(self.cursor.x as usize - 1) {
+                self.set_line(curr_row, row.chars);
                 if self.cursor.x >= self.rows[curr_row].len() as u16 {
                     self.cursor.x = self.rows[curr_row].len() as u16;
                 } else {
@@ -779,8 +4597,9 @@ impl Kass {
             let row_content = self.rows[curr_row].chars.clone();
 
             self.cursor.x = self.rows[curr_row - 1].len() as u16;
-            self.rows[curr_row - 1].append_string(row_content);
-            self.rows.remove(curr_row);
+            let mut row = self.rows[curr_row - 1].clone();
+            row.append_string(row_content);
+            self.splice_rows(curr_row - 1..curr_row + 1, vec![row.chars]);
             self.cursor.y -= 1;
         }
     }
@@ -795,9 +4614,14 @@ impl Kass {
 
     // prints messages on saving;
 
-    //     fn set_message<T: Into<String>>(&mut self, T)
-    //     {
-    //         self.status_time = Instant::now();
-    //         self.status_message = message.into();
-    //     }
+    fn current_line(&self) -> &str {
+        self.rows
+            .get(self.cursor.row())
+            .map_or("", |row| row.chars.as_str())
+    }
+
+    fn set_message<T: Into<String>>(&mut self, message: T) {
+        self.status_time = Instant::now();
+        self.status_message = message.into();
+    }
 }
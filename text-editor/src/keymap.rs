@@ -0,0 +1,204 @@
+use super::keys;
+use crossterm::event::KeyEvent;
+use std::collections::HashMap;
+
+// the modes a mapping can be defined for, :map covers normal and visual
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MapMode {
+    Normal,
+    Visual,
+    Insert,
+    // the : and / prompts
+    Command,
+}
+
+impl MapMode {
+    // the letter :map lists a mapping with
+    fn letter(&self) -> char {
+        match self {
+            MapMode::Normal => 'n',
+            MapMode::Visual => 'v',
+            MapMode::Insert => 'i',
+            MapMode::Command => 'c',
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Mapping {
+    pub lhs: Vec<KeyEvent>,
+    pub rhs: Vec<KeyEvent>,
+    // the rhs keys are run as they are, not through other mappings
+    pub noremap: bool,
+    // as typed in :map, for listing
+    lhs_text: String,
+    rhs_text: String,
+}
+
+// a node per typed key, a mapping ends where its last key is
+#[derive(Debug, Clone, Default)]
+struct Node {
+    children: HashMap<KeyEvent, Node>,
+    mapping: Option<Mapping>,
+}
+
+impl Node {
+    fn mappings(&self) -> Vec<&Mapping> {
+        let mut mappings: Vec<&Mapping> = self.mapping.iter().collect();
+        for child in self.children.values() {
+            mappings.extend(child.mappings());
+        }
+        mappings
+    }
+
+    // drops the mapping at the end of `keys`, with the nodes it leaves empty
+    fn remove(&mut self, keys: &[KeyEvent]) -> bool {
+        let Some((first, rest)) = keys.split_first() else {
+            return self.mapping.take().is_some();
+        };
+        let Some(child) = self.children.get_mut(first) else {
+            return false;
+        };
+        let removed = child.remove(rest);
+        if child.mapping.is_none() && child.children.is_empty() {
+            self.children.remove(first);
+        }
+        removed
+    }
+}
+
+// what the keys typed so far amount to
+pub enum Lookup {
+    // no mapping starts with them
+    None,
+    // they are a mapping and no longer one starts with them
+    Match(Mapping),
+    /* a longer mapping starts with them, more keys are needed. The longest
+     * mapping among them is used when the wait times out */
+    Prefix(Option<Mapping>),
+}
+
+// the key mappings of every mode, as a trie each
+#[derive(Debug, Clone, Default)]
+pub struct Keymap {
+    modes: HashMap<MapMode, Node>,
+}
+
+// the terminal and keys::parse can disagree on the kind and state of a key
+fn normalize(key: &KeyEvent) -> KeyEvent {
+    KeyEvent::new(key.code, key.modifiers)
+}
+
+// `<leader>` becomes 'mapleader' when the mapping is defined, like in vim
+fn expand_leader(text: &str, leader: &str) -> String {
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(i) = rest.to_ascii_lowercase().find("<leader>") {
+        expanded.push_str(&rest[..i]);
+        expanded.push_str(leader);
+        rest = &rest[i + "<leader>".len()..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+impl Keymap {
+    pub fn map(&mut self, mode: MapMode, lhs: &str, rhs: &str, noremap: bool, leader: &str) {
+        let lhs_text = expand_leader(lhs, leader);
+        let rhs_text = expand_leader(rhs, leader);
+        let lhs: Vec<KeyEvent> = keys::parse(&lhs_text).iter().map(normalize).collect();
+
+        let mut node = self.modes.entry(mode).or_default();
+        for key in &lhs {
+            node = node.children.entry(*key).or_default();
+        }
+        node.mapping = Some(Mapping {
+            rhs: keys::parse(&rhs_text),
+            lhs,
+            noremap,
+            lhs_text,
+            rhs_text,
+        });
+    }
+
+    pub fn unmap(&mut self, mode: MapMode, lhs: &str, leader: &str) -> bool {
+        let lhs: Vec<KeyEvent> = keys::parse(&expand_leader(lhs, leader))
+            .iter()
+            .map(normalize)
+            .collect();
+        self.modes
+            .get_mut(&mode)
+            .is_some_and(|root| root.remove(&lhs))
+    }
+
+    pub fn clear(&mut self, mode: MapMode) {
+        self.modes.remove(&mode);
+    }
+
+    pub fn lookup(&self, mode: MapMode, keys: &[KeyEvent]) -> Lookup {
+        let Some(mut node) = self.modes.get(&mode) else {
+            return Lookup::None;
+        };
+        // the longest mapping the keys start with
+        let mut longest = None;
+        for key in keys {
+            match node.children.get(&normalize(key)) {
+                Some(child) => node = child,
+                None => return longest.map_or(Lookup::None, Lookup::Match),
+            }
+            if let Some(mapping) = &node.mapping {
+                longest = Some(mapping.clone());
+            }
+        }
+        match (node.children.is_empty(), longest) {
+            (false, longest) => Lookup::Prefix(longest),
+            (true, Some(mapping)) => Lookup::Match(mapping),
+            (true, None) => Lookup::None,
+        }
+    }
+
+    // the mappings of `mode` that go on after `prefix`, with their rhs
+    pub fn continuations(&self, mode: MapMode, prefix: &[KeyEvent]) -> Vec<(String, String)> {
+        let prefix: Vec<KeyEvent> = prefix.iter().map(normalize).collect();
+        let Some(root) = self.modes.get(&mode) else {
+            return Vec::new();
+        };
+        root.mappings()
+            .into_iter()
+            .filter(|mapping| mapping.lhs.len() > prefix.len() && mapping.lhs.starts_with(&prefix))
+            .map(|mapping| {
+                let rest: String = mapping.lhs[prefix.len()..].iter().map(keys::name).collect();
+                (rest, mapping.rhs_text.clone())
+            })
+            .collect()
+    }
+
+    /* the mappings of `modes` whose lhs starts with `prefix`, one line each
+     * like `n  <Space>w    * :w<CR>`, a `*` marks noremap */
+    pub fn list(&self, modes: &[MapMode], prefix: &str, leader: &str) -> Vec<String> {
+        let prefix = expand_leader(prefix, leader);
+        let mut lines = Vec::new();
+        for mode in modes {
+            let Some(root) = self.modes.get(mode) else {
+                continue;
+            };
+            let mut mappings = root.mappings();
+            mappings.sort_by(|a, b| a.lhs_text.cmp(&b.lhs_text));
+            lines.extend(
+                mappings
+                    .into_iter()
+                    .filter(|mapping| mapping.lhs_text.starts_with(&prefix))
+                    .map(|mapping| {
+                        format!(
+                            "{}  {:<12} {} {}",
+                            mode.letter(),
+                            mapping.lhs_text,
+                            if mapping.noremap { '*' } else { ' ' },
+                            mapping.rhs_text
+                        )
+                    }),
+            );
+        }
+        lines
+    }
+}
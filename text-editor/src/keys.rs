@@ -0,0 +1,101 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+// a key as the terminal would report it, capital letters come with shift
+fn key(code: KeyCode, modifiers: KeyModifiers) -> KeyEvent {
+    KeyEvent::new(code, modifiers)
+}
+
+fn char_key(c: char) -> KeyEvent {
+    if c.is_uppercase() {
+        key(KeyCode::Char(c), KeyModifiers::SHIFT)
+    } else {
+        key(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+}
+
+// the key for a `<...>` name like `Esc`, `CR` or `C-w`, matched without case
+fn named_key(name: &str) -> Option<KeyEvent> {
+    let lower = name.to_ascii_lowercase();
+
+    if let Some(c) = lower.strip_prefix("c-") {
+        let mut chars = c.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(key(KeyCode::Char(c), KeyModifiers::CONTROL)),
+            _ => None,
+        };
+    }
+
+    let code = match lower.as_str() {
+        "esc" => KeyCode::Esc,
+        "cr" | "enter" | "return" => KeyCode::Enter,
+        "bs" | "backspace" => KeyCode::Backspace,
+        "del" => KeyCode::Delete,
+        "tab" => KeyCode::Tab,
+        "s-tab" => return Some(key(KeyCode::BackTab, KeyModifiers::SHIFT)),
+        "space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "bar" => KeyCode::Char('|'),
+        "bslash" => KeyCode::Char('\\'),
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        _ => return None,
+    };
+    Some(key(code, KeyModifiers::NONE))
+}
+
+/* turns typed text into key events, `<Esc>`, `<CR>`, `<C-w>` and friends are
+ * special keys, anything else (including an unknown `<name>`) is typed as is */
+pub fn parse(text: &str) -> Vec<KeyEvent> {
+    let mut keys = Vec::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(end) = rest.find('>') {
+                if let Some(named) = named_key(&rest[1..end]) {
+                    keys.push(named);
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        }
+        keys.push(char_key(c));
+        rest = &rest[c.len_utf8()..];
+    }
+    keys
+}
+
+// the name of a key as parse reads it back, `<C-w>`, `<Space>` or the character
+pub fn name(key: &KeyEvent) -> String {
+    let named = match key.code {
+        KeyCode::Char(c) if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            return format!("<C-{c}>")
+        }
+        KeyCode::Char(' ') => "Space",
+        KeyCode::Char('<') => "lt",
+        KeyCode::Char(c) => return c.to_string(),
+        KeyCode::Esc => "Esc",
+        KeyCode::Enter => "CR",
+        KeyCode::Backspace => "BS",
+        KeyCode::Delete => "Del",
+        KeyCode::Tab => "Tab",
+        KeyCode::BackTab => "S-Tab",
+        KeyCode::Up => "Up",
+        KeyCode::Down => "Down",
+        KeyCode::Left => "Left",
+        KeyCode::Right => "Right",
+        KeyCode::Home => "Home",
+        KeyCode::End => "End",
+        KeyCode::PageUp => "PageUp",
+        KeyCode::PageDown => "PageDown",
+        KeyCode::F(n) => return format!("<F{n}>"),
+        _ => "Nop",
+    };
+    format!("<{named}>")
+}
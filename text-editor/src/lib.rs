@@ -7,10 +7,10 @@ pub struct Position {
 }
 
 #[derive(Debug, Clone)]
-pub enum NormalMode {
-    Default,
-    Cut,
-    Copy,
+pub enum CaseOp {
+    Toggle,
+    Lower,
+    Upper,
 }
 
 impl Position {
@@ -33,6 +33,7 @@ impl Position {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
 pub enum MovementKey {
     Left,
     Right,
@@ -40,3 +41,11 @@ pub enum MovementKey {
     Down,
     // Tab,
 }
+
+// where the cursor line (or column) ends up on the screen
+#[derive(Debug, Clone, Copy)]
+pub enum ScreenPos {
+    Start,
+    Middle,
+    End,
+}
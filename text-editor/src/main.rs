@@ -1,53 +1,117 @@
 use crossterm::{cursor, execute, terminal};
 use std::{
     env::args,
-    io::{stdout, Result},
+    io::{stdout, Error, Result},
+    panic,
+    process::ExitCode,
 };
 
+mod bindings;
+mod buffer;
+mod cmdline;
+mod completion;
+mod config;
+mod editorconfig;
+mod encoding;
+mod ex;
 mod kass;
+mod keymap;
+mod keys;
 mod mode;
+mod options;
+mod palette;
+mod paths;
 mod row;
+mod save;
 mod screen;
+mod search;
 mod statusbar;
+mod substitute;
+mod theme;
+mod transform;
+mod undo;
+mod window;
 
-fn main() -> Result<()> {
-    // move cursor to 0,0
-    // enter alternate screen
-    execute!(
-        stdout(),
-        cursor::MoveTo(0, 0),
-        terminal::EnterAlternateScreen
-    )?;
-    // enable raw mode
-    terminal::enable_raw_mode()?;
+// raw mode and the alternate screen, for as long as it lives
+struct Terminal;
 
-    // get terminal size
-    let mut height: usize = 0;
-    let mut width: usize = 0;
-    if let Some((w, h)) = term_size::dimensions() {
-        height = h as usize;
-        width = w as usize
-    } else {
-        print!("Unable to get term size :(\n")
+impl Terminal {
+    fn enter() -> Result<Self> {
+        // made first, whatever fails below is undone when it drops
+        let guard = Terminal;
+        // move cursor to 0,0
+        // enter alternate screen
+        execute!(
+            stdout(),
+            cursor::MoveTo(0, 0),
+            terminal::EnterAlternateScreen
+        )?;
+        // enable raw mode
+        terminal::enable_raw_mode()?;
+        Ok(guard)
     }
 
-    // get file path
-    let args: Vec<String> = args().collect();
-    let mut filepath: &String = &String::from("n/a");
+    // errors are ignored, there is nothing left to do about them
+    fn restore() {
+        let _ = terminal::disable_raw_mode();
+        let _ = execute!(
+            stdout(),
+            cursor::SetCursorStyle::DefaultUserShape,
+            terminal::LeaveAlternateScreen
+        );
+    }
+}
 
-    // text editor
-    let mut editor = if args.len() > 1 {
-        filepath = &args[1];
-        kass::Kass::with_file(height, width, filepath)?
-    } else {
-        kass::Kass::new(&[], height, width, filepath)?
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        Terminal::restore();
+    }
+}
+
+/* the exit status is what :cq asked for, or 1 when the editor couldn't go
+ * on. The terminal is back to normal before anything is printed */
+fn main() -> ExitCode {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        Terminal::restore();
+        default_hook(info);
+    }));
+
+    match run() {
+        Ok(code) => ExitCode::from(code),
+        Err(error) => {
+            eprintln!("kass: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<u8> {
+    let _terminal = Terminal::enter()?;
+
+    // get terminal size
+    let Some((width, height)) = term_size::dimensions() else {
+        return Err(Error::other("unable to get the terminal size"));
     };
-    editor.run()?;
 
-    // disable raw mode
-    terminal::disable_raw_mode()?;
-    // leave alternate screen
-    execute!(stdout(), terminal::LeaveAlternateScreen)?;
+    // get file path, --modeless starts the modeless preset whatever the config says
+    let args: Vec<String> = args().skip(1).collect();
+    let modeless = args.iter().any(|arg| arg == "--modeless");
+    let files: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+    let no_file = String::new();
 
-    Ok(())
+    // text editor
+    let mut editor = match files.first() {
+        Some(filepath) => kass::Kass::with_file(height, width, filepath)?,
+        None => kass::Kass::new(&[], height, width, &no_file)?,
+    };
+    // the other files wait in the buffer list
+    for file in files.iter().skip(1) {
+        editor.execute(&format!("badd {file}"));
+    }
+    if modeless {
+        editor.execute("set modeless");
+    }
+    editor.run()?;
+    Ok(editor.exit_code())
 }
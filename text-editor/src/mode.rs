@@ -4,4 +4,7 @@ pub enum Mode {
     Normal,
     Visual,
     Command,
+    Search,
+    Confirm,
+    Palette,
 }
@@ -0,0 +1,500 @@
+use std::{collections::HashMap, fmt};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Bool,
+    Number,
+    String,
+    // comma separated
+    List,
+}
+
+// where a value lives, local options start out as a copy of the global value
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scope {
+    Global,
+    Buffer,
+    Window,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Number(i64),
+    String(String),
+    List(Vec<String>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::Number(value) => write!(f, "{value}"),
+            Value::String(value) => write!(f, "{value}"),
+            Value::List(items) => write!(f, "{}", items.join(",")),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Definition {
+    pub name: &'static str,
+    pub short: &'static str,
+    pub kind: Kind,
+    pub scope: Scope,
+    // parsed like a :set value, "on" / "off" for booleans
+    pub default: &'static str,
+    // the values a string option accepts, anything goes when empty
+    pub allowed: &'static [&'static str],
+    // the smallest value of a number option
+    pub min: i64,
+    // checks every item of a list option
+    pub item: fn(&str) -> Result<(), String>,
+}
+
+const CURSOR_STYLES: [&str; 7] = [
+    "default",
+    "block",
+    "blinkblock",
+    "bar",
+    "blinkbar",
+    "underline",
+    "blinkunderline",
+];
+
+pub const ENCODINGS: [&str; 5] = ["utf-8", "utf-8-bom", "latin1", "utf-16le", "utf-16be"];
+
+const fn option(
+    name: &'static str,
+    short: &'static str,
+    kind: Kind,
+    scope: Scope,
+    default: &'static str,
+) -> Definition {
+    Definition {
+        name,
+        short,
+        kind,
+        scope,
+        default,
+        allowed: &[],
+        min: 0,
+        item: |_| Ok(()),
+    }
+}
+
+// a 'comments' item is `{flags}:{string}`
+fn comment_item(item: &str) -> Result<(), String> {
+    match item.split_once(':') {
+        None => Err(format!("E524: Missing colon: {item}")),
+        Some((_, "")) => Err(format!("E525: Zero length string: {item}")),
+        Some(_) => Ok(()),
+    }
+}
+
+/* 'comments' for the languages whose comments vim's default doesn't know,
+ * by file extension. A file gets them before the filetype sections of the
+ * config have their say */
+const FILETYPE_COMMENTS: [(&[&str], &str); 5] = [
+    (&["rs"], "s1:/*,mb:*,ex:*/,:///,://!,://"),
+    (
+        &[
+            "py", "sh", "bash", "zsh", "rb", "pl", "toml", "yaml", "yml", "cmake", "r",
+        ],
+        "b:#,fb:-",
+    ),
+    (&["lua", "sql", "hs", "elm", "ada"], ":---,:--"),
+    (&["vim"], ":\""),
+    (&["tex", "erl", "m"], ":%"),
+];
+
+// the :setlocal arguments a file with `extension` starts with
+pub fn filetype_defaults(extension: &str) -> Vec<String> {
+    FILETYPE_COMMENTS
+        .iter()
+        .filter(|(extensions, _)| extensions.contains(&extension))
+        .map(|(_, comments)| format!("comments={comments}"))
+        .collect()
+}
+
+pub const OPTIONS: [Definition; 33] = [
+    // comment leaders `J` drops from the joined line, in vim's {flags}:{string} format
+    Definition {
+        item: comment_item,
+        ..option(
+            "comments",
+            "com",
+            Kind::List,
+            Scope::Buffer,
+            "s1:/*,mb:*,ex:*/,://,b:#,:%,n:>,fb:-",
+        )
+    },
+    // the file ended in a line break when it was read
+    option("endofline", "eol", Kind::Bool, Scope::Buffer, "on"),
+    option("expandtab", "et", Kind::Bool, Scope::Buffer, "off"),
+    Definition {
+        allowed: &ENCODINGS,
+        ..option("fileencoding", "fenc", Kind::String, Scope::Buffer, "utf-8")
+    },
+    // the line endings written, \n, \r\n or \r
+    Definition {
+        allowed: &["unix", "dos", "mac"],
+        ..option("fileformat", "ff", Kind::String, Scope::Buffer, "unix")
+    },
+    // a missing line break after the last line is added on write
+    option("fixendofline", "fixeol", Kind::Bool, Scope::Buffer, "on"),
+    // a buffer with changes can be left, it stays loaded in the background
+    option("hidden", "hid", Kind::Bool, Scope::Global, "on"),
+    option("history", "hi", Kind::Number, Scope::Global, "200"),
+    option("hlsearch", "hls", Kind::Bool, Scope::Global, "on"),
+    option("ignorecase", "ic", Kind::Bool, Scope::Global, "off"),
+    option("incsearch", "is", Kind::Bool, Scope::Global, "on"),
+    Definition {
+        allowed: &CURSOR_STYLES,
+        ..option("insertcursor", "", Kind::String, Scope::Global, "blinkbar")
+    },
+    option(
+        "iskeyword",
+        "isk",
+        Kind::List,
+        Scope::Buffer,
+        "@,48-57,_,192-255",
+    ),
+    // what <leader> stands for in a :map
+    option("mapleader", "", Kind::String, Scope::Global, "\\"),
+    // how long messages stay on the bottom line, in milliseconds
+    option("messagetime", "", Kind::Number, Scope::Global, "5000"),
+    Definition {
+        allowed: &CURSOR_STYLES,
+        ..option("normalcursor", "", Kind::String, Scope::Global, "default")
+    },
+    // insert mode all the time with Ctrl-S, Ctrl-Z, Ctrl-C and friends, for non vim users
+    option("modeless", "", Kind::Bool, Scope::Global, "off"),
+    option("number", "nu", Kind::Bool, Scope::Window, "on"),
+    Definition {
+        min: 1,
+        ..option("numberwidth", "nuw", Kind::Number, Scope::Window, "6")
+    },
+    Definition {
+        min: 1,
+        ..option("previewheight", "pvh", Kind::Number, Scope::Global, "8")
+    },
+    option("relativenumber", "rnu", Kind::Bool, Scope::Window, "on"),
+    option("scrolloff", "so", Kind::Number, Scope::Global, "0"),
+    // the tabline is shown 0: never, 1: with more than one tab page, 2: always
+    option("showtabline", "stal", Kind::Number, Scope::Global, "1"),
+    option("shiftwidth", "sw", Kind::Number, Scope::Buffer, "4"),
+    option("smartcase", "scs", Kind::Bool, Scope::Global, "off"),
+    Definition {
+        min: 1,
+        ..option("tabstop", "ts", Kind::Number, Scope::Buffer, "4")
+    },
+    // a key that may start a longer mapping waits 'timeoutlen' milliseconds for the next
+    option("timeout", "to", Kind::Bool, Scope::Global, "on"),
+    option("timeoutlen", "tm", Kind::Number, Scope::Global, "1000"),
+    // trailing spaces and tabs are removed on write
+    option(
+        "trimtrailingwhitespace",
+        "",
+        Kind::Bool,
+        Scope::Buffer,
+        "off",
+    ),
+    // how many changes can be undone, the oldest are forgotten
+    option("undolevels", "ul", Kind::Number, Scope::Global, "1000"),
+    // the keys that can follow a pending prefix pop up after 'whichkeydelay' milliseconds
+    option("whichkey", "wk", Kind::Bool, Scope::Global, "on"),
+    option("whichkeydelay", "wkd", Kind::Number, Scope::Global, "500"),
+    option("wrapscan", "ws", Kind::Bool, Scope::Global, "on"),
+];
+
+pub fn definition(name: &str) -> Option<&'static Definition> {
+    OPTIONS
+        .iter()
+        .find(|option| option.name == name || (!option.short.is_empty() && option.short == name))
+}
+
+// every option name, boolean ones also as no{name} when `negated` is set
+pub fn names(negated: bool) -> Vec<String> {
+    let mut names: Vec<String> = OPTIONS
+        .iter()
+        .map(|option| option.name.to_string())
+        .collect();
+    if negated {
+        names.extend(
+            OPTIONS
+                .iter()
+                .filter(|option| option.kind == Kind::Bool)
+                .map(|option| format!("no{}", option.name)),
+        );
+    }
+    names
+}
+
+fn parse_value(option: &Definition, text: &str) -> Result<Value, String> {
+    match option.kind {
+        Kind::Bool => match text {
+            "on" | "true" | "1" => Ok(Value::Bool(true)),
+            "off" | "false" | "0" => Ok(Value::Bool(false)),
+            _ => Err(format!("E474: Invalid argument: {}={text}", option.name)),
+        },
+        Kind::Number => {
+            let number = text
+                .parse::<i64>()
+                .map_err(|_| format!("E521: Number required after =: {}={text}", option.name))?;
+            if number < option.min {
+                return Err(format!(
+                    "E487: Argument must be positive: {}={text}",
+                    option.name
+                ));
+            }
+            Ok(Value::Number(number))
+        }
+        Kind::String => {
+            if !option.allowed.is_empty() && !option.allowed.contains(&text) {
+                return Err(format!("E474: Invalid argument: {}={text}", option.name));
+            }
+            Ok(Value::String(text.to_string()))
+        }
+        Kind::List => {
+            let items: Vec<String> = text
+                .split(',')
+                .filter(|item| !item.is_empty())
+                .map(String::from)
+                .collect();
+            for item in &items {
+                (option.item)(item)?;
+            }
+            Ok(Value::List(items))
+        }
+    }
+}
+
+// the values of local options that were set for one buffer or window
+#[derive(Debug, Clone, Default)]
+pub struct Local(HashMap<&'static str, Value>);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SetScope {
+    // :set changes the global and the local value
+    Both,
+    // :setlocal
+    Local,
+    // :setglobal, what new buffers and windows start with
+    Global,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    global: HashMap<&'static str, Value>,
+    pub buffer: Local,
+    pub window: Local,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        let global = OPTIONS
+            .iter()
+            .map(|option| {
+                let value = parse_value(option, option.default)
+                    .unwrap_or_else(|error| panic!("bad default for {}: {error}", option.name));
+                (option.name, value)
+            })
+            .collect();
+
+        Options {
+            global,
+            buffer: Local::default(),
+            window: Local::default(),
+        }
+    }
+
+    fn local(&self, scope: Scope) -> Option<&Local> {
+        match scope {
+            Scope::Global => None,
+            Scope::Buffer => Some(&self.buffer),
+            Scope::Window => Some(&self.window),
+        }
+    }
+
+    // the value in effect: the local one when it was set, the global one otherwise
+    pub fn get(&self, name: &str) -> &Value {
+        let option = definition(name).unwrap_or_else(|| panic!("unknown option {name}"));
+        self.local(option.scope)
+            .and_then(|local| local.0.get(option.name))
+            .unwrap_or(&self.global[option.name])
+    }
+
+    pub fn bool(&self, name: &str) -> bool {
+        matches!(self.get(name), Value::Bool(true))
+    }
+
+    pub fn number(&self, name: &str) -> usize {
+        match self.get(name) {
+            Value::Number(value) => (*value).max(0) as usize,
+            _ => 0,
+        }
+    }
+
+    pub fn string(&self, name: &str) -> String {
+        self.get(name).to_string()
+    }
+
+    fn store(&mut self, option: &'static Definition, value: Value, scope: SetScope) {
+        if scope != SetScope::Local || option.scope == Scope::Global {
+            self.global.insert(option.name, value.clone());
+        }
+        if scope != SetScope::Global {
+            match option.scope {
+                Scope::Global => {}
+                Scope::Buffer => {
+                    self.buffer.0.insert(option.name, value);
+                }
+                Scope::Window => {
+                    self.window.0.insert(option.name, value);
+                }
+            }
+        }
+    }
+
+    // how `:set {name}?` shows an option
+    fn show(&self, option: &Definition) -> String {
+        match self.get(option.name) {
+            Value::Bool(true) => format!("  {}", option.name),
+            Value::Bool(false) => format!("no{}", option.name),
+            value => format!("  {}={value}", option.name),
+        }
+    }
+
+    // every option, one per line, for :set all
+    pub fn all(&self) -> String {
+        OPTIONS
+            .iter()
+            .map(|option| self.show(option))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /* applies one :set argument: `opt`, `noopt`, `invopt`, `opt!`, `opt&`,
+     * `opt?`, `opt=val` and `opt+=val` / `opt-=val` / `opt^=val`. Returns what
+     * should be shown, for queries */
+    pub fn set(&mut self, arg: &str, scope: SetScope) -> Result<Option<String>, String> {
+        let name_end = arg
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(arg.len());
+        let (name, rest) = arg.split_at(name_end);
+
+        let (option, negate, invert) = match definition(name) {
+            Some(option) => (option, false, false),
+            None => {
+                let (prefix, base) = if let Some(base) = name.strip_prefix("no") {
+                    ("no", base)
+                } else if let Some(base) = name.strip_prefix("inv") {
+                    ("inv", base)
+                } else {
+                    ("", name)
+                };
+                match definition(base) {
+                    Some(option) if !prefix.is_empty() && option.kind == Kind::Bool => {
+                        (option, prefix == "no", prefix == "inv")
+                    }
+                    Some(_) if !prefix.is_empty() => {
+                        return Err(format!("E474: Invalid argument: {arg}"))
+                    }
+                    _ => return Err(format!("E518: Unknown option: {name}")),
+                }
+            }
+        };
+
+        if negate || invert {
+            if !rest.is_empty() {
+                return Err(format!("E474: Invalid argument: {arg}"));
+            }
+            let value = !negate && !self.bool(option.name);
+            self.store(option, Value::Bool(value), scope);
+            return Ok(None);
+        }
+
+        match rest {
+            "" if option.kind == Kind::Bool => self.store(option, Value::Bool(true), scope),
+            "" | "?" => return Ok(Some(self.show(option))),
+            "!" if option.kind == Kind::Bool => {
+                let value = !self.bool(option.name);
+                self.store(option, Value::Bool(value), scope);
+            }
+            "&" => {
+                let value = parse_value(option, option.default)?;
+                self.store(option, value, scope);
+            }
+            _ => {
+                let (operator, text) = ["+=", "-=", "^=", "=", ":"]
+                    .iter()
+                    .find_map(|op| rest.strip_prefix(op).map(|text| (*op, text)))
+                    .ok_or_else(|| format!("E518: Unknown option: {arg}"))?;
+                if option.kind == Kind::Bool {
+                    return Err(format!("E474: Invalid argument: {arg}"));
+                }
+
+                let new = parse_value(option, text)?;
+                let value = match (self.get(option.name).clone(), new, operator) {
+                    (_, new, "=" | ":") => new,
+                    (Value::Number(old), Value::Number(n), "+=") => Value::Number(old + n),
+                    (Value::Number(old), Value::Number(n), "-=") => Value::Number(old - n),
+                    (Value::Number(old), Value::Number(n), _) => Value::Number(old * n),
+                    (Value::String(old), Value::String(s), "+=") => Value::String(old + &s),
+                    (Value::String(old), Value::String(s), "-=") => {
+                        Value::String(old.replacen(&s, "", 1))
+                    }
+                    (Value::String(old), Value::String(s), _) => Value::String(s + &old),
+                    (Value::List(mut old), Value::List(items), "+=") => {
+                        old.retain(|item| !items.contains(item));
+                        old.extend(items);
+                        Value::List(old)
+                    }
+                    (Value::List(mut old), Value::List(items), "-=") => {
+                        old.retain(|item| !items.contains(item));
+                        Value::List(old)
+                    }
+                    (Value::List(old), Value::List(mut items), _) => {
+                        let rest: Vec<String> = old
+                            .into_iter()
+                            .filter(|item| !items.contains(item))
+                            .collect();
+                        items.extend(rest);
+                        Value::List(items)
+                    }
+                    _ => return Err(format!("E474: Invalid argument: {arg}")),
+                };
+
+                // the result has to be valid as well, like `nuw-=10`
+                let value = parse_value(option, &value.to_string())?;
+                self.store(option, value, scope);
+            }
+        }
+        Ok(None)
+    }
+}
+
+/* splits the arguments of :set on whitespace, a backslash keeps the next
+ * character, like in `:set isk+=\ ` */
+pub fn split_args(args: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut chars = args.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => current.extend(chars.next()),
+            _ if c.is_whitespace() => {
+                if !current.is_empty() {
+                    result.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        result.push(current);
+    }
+    result
+}
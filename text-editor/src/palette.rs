@@ -0,0 +1,101 @@
+use super::completion::fuzzy_score;
+
+// what picking an entry of the palette does
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    // an ex command line, run as it is
+    Command(String),
+    // an ex command that needs an argument, the command line opens with it typed
+    Prompt(String),
+    // keys, run as if they were typed
+    Keys(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub description: String,
+    // the keys that run it, empty when nothing does
+    pub binding: String,
+    pub action: Action,
+}
+
+/* the command palette, every entry whose name or description fuzzy matches
+ * the typed text, the best match first */
+#[derive(Debug, Clone)]
+pub struct Palette {
+    pub input: String,
+    entries: Vec<Entry>,
+    // indices into entries
+    matches: Vec<usize>,
+    pub selected: usize,
+}
+
+impl Palette {
+    pub fn new(entries: Vec<Entry>) -> Self {
+        let mut palette = Palette {
+            input: String::new(),
+            entries,
+            matches: Vec::new(),
+            selected: 0,
+        };
+        palette.filter();
+        palette
+    }
+
+    // the names count more than the descriptions
+    fn score(&self, entry: &Entry) -> Option<i64> {
+        fuzzy_score(&self.input, &entry.name)
+            .map(|score| score + 1000)
+            .or_else(|| fuzzy_score(&self.input, &entry.description))
+    }
+
+    fn filter(&mut self) {
+        let mut scored: Vec<(i64, usize)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter_map(|(i, entry)| self.score(entry).map(|score| (score, i)))
+            .collect();
+        // the sort is stable, equal scores keep the order of the entries
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+        self.selected = 0;
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.input.push(c);
+        self.filter();
+    }
+
+    pub fn pop(&mut self) {
+        self.input.pop();
+        self.filter();
+    }
+
+    pub fn clear(&mut self) {
+        self.input.clear();
+        self.filter();
+    }
+
+    // moves the selection, going past either end wraps around
+    pub fn step(&mut self, forward: bool) {
+        let len = self.matches.len();
+        if len == 0 {
+            return;
+        }
+        self.selected = if forward {
+            (self.selected + 1) % len
+        } else {
+            (self.selected + len - 1) % len
+        };
+    }
+
+    pub fn matches(&self) -> Vec<&Entry> {
+        self.matches.iter().map(|&i| &self.entries[i]).collect()
+    }
+
+    pub fn selection(&self) -> Option<&Entry> {
+        self.matches.get(self.selected).map(|&i| &self.entries[i])
+    }
+}
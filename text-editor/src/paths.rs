@@ -0,0 +1,38 @@
+use std::{
+    env,
+    path::{Component, Path, PathBuf},
+};
+
+// a typed file name, `~` and `~/` stand for the home directory
+pub fn expand(name: &str) -> PathBuf {
+    let home = || env::var_os("HOME").map(PathBuf::from);
+    match name.strip_prefix('~') {
+        Some("") => home().unwrap_or_else(|| PathBuf::from(name)),
+        Some(rest) if rest.starts_with('/') => home().map_or_else(
+            || PathBuf::from(name),
+            |home| home.join(rest.trim_start_matches('/')),
+        ),
+        _ => PathBuf::from(name),
+    }
+}
+
+/* `path` from the root, relative ones start in the cwd. `.` and `..` are
+ * taken away without looking at the file system, like the shell's `cd` */
+pub fn absolute(path: &Path) -> PathBuf {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().unwrap_or_default().join(path)
+    };
+    let mut absolute = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            component => absolute.push(component),
+        }
+    }
+    absolute
+}
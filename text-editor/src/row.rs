@@ -2,6 +2,8 @@
 pub struct Row {
     pub chars: String,
     pub render: String,
+    // set by :global on the lines it still has to visit
+    pub marked: bool,
 }
 
 impl Row {
@@ -9,6 +11,7 @@ impl Row {
         let mut result = Self {
             chars,
             render: String::new(),
+            marked: false,
         };
 
         result.render_row();
@@ -32,6 +35,11 @@ impl Row {
         self.render_row();
     }
 
+    pub fn set_chars(&mut self, chars: String) {
+        self.chars = chars;
+        self.render_row();
+    }
+
     fn render_row(&mut self) {
         let mut render = String::new();
         for c in self.chars.chars() {
@@ -52,17 +60,10 @@ impl Row {
     }
 
     pub fn split(&mut self, at: usize) -> String {
-        let result = self.chars.split_off(at);
-
-        result
-    }
-
-    pub fn indent_level(&self) -> usize {
-        self.chars.len() - self.chars.trim_start_matches(' ').len()
+        self.chars.split_off(at)
     }
 
     pub fn append_string(&mut self, s: String) {
-        let strContent = s.as_str();
-        self.chars.push_str(strContent);
+        self.chars.push_str(s.as_str());
     }
 }
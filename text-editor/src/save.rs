@@ -0,0 +1,174 @@
+use std::{
+    fs::{self, File, Metadata, OpenOptions, Permissions},
+    io::{Error, ErrorKind, Result, Write},
+    path::{Path, PathBuf},
+    process,
+};
+
+/* the file a write goes to. Symbolic links are followed, they keep pointing
+ * at the file instead of being replaced by it */
+fn target(path: &Path) -> PathBuf {
+    match fs::canonicalize(path) {
+        Ok(target) => target,
+        // a link to a file that isn't there yet
+        Err(_) => match fs::read_link(path) {
+            Ok(link) => path.parent().map_or(link.clone(), |dir| dir.join(&link)),
+            Err(_) => path.to_path_buf(),
+        },
+    }
+}
+
+/* writes `bytes` to `path` so that a crash leaves either the old file or the
+ * new one: they go to a temporary file in the same directory, which is synced
+ * and renamed over the old file with its permissions and owner. A read-only
+ * file is only written with `force`, and stays read-only */
+pub fn write_atomic(path: &Path, bytes: &[u8], force: bool) -> Result<()> {
+    let target = target(path);
+    let metadata = fs::metadata(&target).ok();
+    if let Some(metadata) = &metadata {
+        if metadata.permissions().readonly() && !force {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                "the file is read-only (add ! to override)",
+            ));
+        }
+        // renaming would break a hard link, the other names would keep the old text
+        if links(metadata) > 1 {
+            return write_in_place(&target, bytes, metadata);
+        }
+    }
+
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let (temp, file) = match create_temp(dir, &name, metadata.as_ref()) {
+        Ok(created) => created,
+        // a directory we can't create files in, the file itself may still be writable
+        Err(error) if error.kind() == ErrorKind::PermissionDenied => {
+            return match &metadata {
+                Some(metadata) => write_in_place(&target, bytes, metadata),
+                None => Err(error),
+            };
+        }
+        Err(error) => return Err(error),
+    };
+    // the temporary file is this write's own, nothing else is taken away on failure
+    let result =
+        write_temp(file, bytes, metadata.as_ref()).and_then(|()| fs::rename(&temp, &target));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result?;
+    // the rename itself is only safe once the directory is on disk
+    sync_dir(dir);
+    Ok(())
+}
+
+/* a new file next to the target, created with the mode of the file it
+ * replaces so no one else can read it meanwhile. One left over from a crash
+ * of a process that had the same pid is left alone, another name is tried */
+fn create_temp(dir: &Path, name: &str, metadata: Option<&Metadata>) -> Result<(PathBuf, File)> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    if let Some(metadata) = metadata {
+        set_mode(&mut options, metadata);
+    }
+    let mut tries = 0;
+    loop {
+        let suffix = match tries {
+            0 => String::new(),
+            n => format!(".{n}"),
+        };
+        let temp = dir.join(format!(".{name}.{}{suffix}.kass~", process::id()));
+        match options.open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(error) if error.kind() == ErrorKind::AlreadyExists && tries < 100 => tries += 1,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+fn write_temp(mut file: File, bytes: &[u8], metadata: Option<&Metadata>) -> Result<()> {
+    if let Some(metadata) = metadata {
+        // the umask may have taken bits off the mode
+        file.set_permissions(metadata.permissions())?;
+        keep_owner(&file, metadata);
+    }
+    file.write_all(bytes)?;
+    file.sync_all()
+}
+
+// a read-only file is made writable for the write and read-only again after it
+fn write_in_place(path: &Path, bytes: &[u8], metadata: &Metadata) -> Result<()> {
+    let readonly = metadata.permissions().readonly();
+    if readonly {
+        fs::set_permissions(path, writable(metadata))?;
+    }
+    let result = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(path)
+        .and_then(|mut file| {
+            file.write_all(bytes)?;
+            file.sync_all()
+        });
+    if readonly {
+        let _ = fs::set_permissions(path, metadata.permissions());
+    }
+    result
+}
+
+#[cfg(unix)]
+fn set_mode(options: &mut OpenOptions, metadata: &Metadata) {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    options.mode(metadata.permissions().mode());
+}
+
+#[cfg(not(unix))]
+fn set_mode(_: &mut OpenOptions, _: &Metadata) {}
+
+// writable by the owner, for everyone else it stays as it was
+#[cfg(unix)]
+fn writable(metadata: &Metadata) -> Permissions {
+    use std::os::unix::fs::PermissionsExt;
+    Permissions::from_mode(metadata.permissions().mode() | 0o200)
+}
+
+#[cfg(not(unix))]
+fn writable(metadata: &Metadata) -> Permissions {
+    let mut permissions = metadata.permissions();
+    permissions.set_readonly(false);
+    permissions
+}
+
+#[cfg(unix)]
+fn links(metadata: &Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::nlink(metadata)
+}
+
+#[cfg(not(unix))]
+fn links(_: &Metadata) -> u64 {
+    1
+}
+
+// only root can give a file away, for anyone else it stays theirs
+#[cfg(unix)]
+fn keep_owner(file: &File, metadata: &Metadata) {
+    use std::os::unix::fs::MetadataExt;
+    let _ = std::os::unix::fs::fchown(file, Some(metadata.uid()), Some(metadata.gid()));
+}
+
+#[cfg(not(unix))]
+fn keep_owner(_: &File, _: &Metadata) {}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) {
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_: &Path) {}
@@ -1,4 +1,4 @@
-use super::row::*;
+use super::{options::Options, palette::Entry, row::*, theme::Theme, window::Rect};
 use crossterm::{
     self, cursor,
     style::{Attribute, Print, SetAttribute},
@@ -6,46 +6,178 @@ use crossterm::{
 };
 use std::{
     cmp::Ordering,
-    env::current_exe,
-    io::{stdout, Result, Write},
+    io::{stdout, Result},
 };
 use text_editor::Position;
 
+// entries the command palette shows at once
+const PALETTE_HEIGHT: usize = 12;
+
+#[derive(Debug, Clone)]
+pub enum HighlightKind {
+    Selection,
+    Search,
+    CurrentMatch,
+}
+
+// a highlighted [start, end) range of a row
+#[derive(Debug, Clone)]
+pub struct Highlight {
+    pub row: usize,
+    pub start: usize,
+    pub end: usize,
+    pub kind: HighlightKind,
+}
+
 #[derive(Debug, Clone)]
 pub struct Screen {
     width: usize,
     height: usize,
+    // the text area of the window being drawn, without its status line
+    top: usize,
+    left: usize,
+    view_height: usize,
+    view_width: usize,
     ln_shift: u16,
 
+    // 'number' and 'relativenumber'
     ln_display: bool,
+    ln_relative: bool,
+    tabstop: usize,
+    // most lines listed by the :s preview pane
+    preview_height: usize,
+    theme: Theme,
 }
 
-const LNO_SHIFT: u16 = 6;
-
 impl Screen {
-    pub fn new() -> Result<Self> {
-        let ln_display = true;
-        let (terminal_width, terminal_height) = crossterm::terminal::size()?;
+    pub fn new(terminal_height: usize, terminal_width: usize) -> Result<Self> {
         Ok(Screen {
-            width: (terminal_width) as usize,
-            height: (terminal_height) as usize,
+            width: terminal_width,
+            height: terminal_height,
+            top: 0,
+            left: 0,
+            view_height: terminal_height - 2,
+            view_width: terminal_width,
             ln_display: true,
-            ln_shift: if ln_display { LNO_SHIFT } else { 0 },
+            ln_relative: true,
+            ln_shift: 6,
+            tabstop: 4,
+            preview_height: 8,
+            theme: Theme::new(),
+        })
+    }
+
+    // the options of the window being drawn and its buffer
+    pub fn set_options(&mut self, options: &Options) {
+        self.ln_display = options.bool("number");
+        self.ln_relative = options.bool("relativenumber");
+        self.ln_shift = if self.ln_display || self.ln_relative {
+            options.number("numberwidth").min(self.view_width / 2) as u16
+        } else {
+            0
+        };
+        self.tabstop = options.number("tabstop").max(1);
+        self.preview_height = options.number("previewheight");
+    }
+
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme = theme.clone();
+    }
+
+    // the window drawn from now on, the last line of `rect` is its status line
+    pub fn set_area(&mut self, rect: Rect) {
+        self.top = rect.top;
+        self.left = rect.left;
+        self.view_height = rect.height.saturating_sub(1).max(1);
+        self.view_width = rect.width.max(1);
+    }
+
+    // the column between two windows side by side
+    pub fn draw_separator(&self, col: usize, top: usize, height: usize) -> Result<()> {
+        for y in top..top + height {
+            stdout()
+                .queue(cursor::MoveTo(col as u16, y as u16))?
+                .queue(SetAttribute(Attribute::Reverse))?
+                .queue(Print('|'))?
+                .queue(SetAttribute(Attribute::Reset))?;
+        }
+        Ok(())
+    }
+
+    /* the labels of the tab pages on the top line, the current one stands
+     * out. What doesn't fit is cut off */
+    pub fn draw_tabline(&self, labels: &[String], current: usize) -> Result<()> {
+        stdout().queue(cursor::MoveTo(0, 0))?;
+        let mut col = 0;
+        for (i, label) in labels.iter().enumerate() {
+            let label: String = format!(" {label} ")
+                .chars()
+                .take(self.width - col)
+                .collect();
+            self.theme.apply(if i == current {
+                "TabLineSel"
+            } else {
+                "TabLine"
+            })?;
+            stdout().queue(Print(&label))?;
+            Theme::reset()?;
+            col += label.chars().count();
+        }
+        self.theme.apply("TabLine")?;
+        stdout().queue(Print(" ".repeat(self.width - col)))?;
+        Theme::reset()
+    }
+
+    // the width of `text` on screen, tabs reach to the next tab stop
+    pub fn text_width(&self, text: &str) -> usize {
+        text.chars().fold(0, |col, c| match c {
+            '\t' => col + self.tabstop - col % self.tabstop,
+            _ => col + 1,
         })
     }
 
+    /* `text` as it is printed from screen column `col` on, tabs become spaces
+     * and nothing goes past the right edge. Moves `col` along */
+    fn expand(&self, text: &str, col: &mut usize) -> String {
+        let limit = self.view_width - self.ln_shift as usize;
+        let mut result = String::new();
+        for c in text.chars() {
+            let width = match c {
+                '\t' => self.tabstop - *col % self.tabstop,
+                _ => 1,
+            };
+            if *col + width > limit {
+                *col = limit;
+                break;
+            }
+            match c {
+                '\t' => result.extend(std::iter::repeat_n(' ', width)),
+                _ => result.push(c),
+            }
+            *col += width;
+        }
+        result
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    // `rows` start at line `first`, a preview only has the lines on screen
     pub fn draw_screen(
         &mut self,
         rows: &[Row],
+        first: usize,
         rowoff: usize,
         coloff: usize,
         cursor_at: usize,
+        highlights: &[Highlight],
     ) -> Result<()> {
-        for i in 0..(self.height - 2) {
+        for i in 0..self.view_height {
             let row = i + rowoff;
-            if row >= rows.len() {
+            if row < first || row - first >= rows.len() {
             } else {
-                let mut len = rows[row].len();
+                let mut len = rows[row - first].len();
 
                 if len < coloff {
                     continue;
@@ -53,8 +185,8 @@ impl Screen {
                 len -= coloff;
                 let start = coloff;
                 let end = start
-                    + if len >= (self.width - (LNO_SHIFT as usize)) {
-                        self.width - (LNO_SHIFT) as usize
+                    + if len >= (self.view_width - (self.ln_shift as usize)) {
+                        self.view_width - (self.ln_shift) as usize
                     } else {
                         len
                     };
@@ -63,64 +195,331 @@ impl Screen {
                 let line_order = cursor_at.cmp(&row);
 
                 let relative_ln = match line_order {
-                    Ordering::Equal => row + 1,
+                    Ordering::Equal if self.ln_display => row + 1,
+                    Ordering::Equal => 0,
                     Ordering::Greater => cursor_at - row,
                     Ordering::Less => row - cursor_at,
                 };
                 stdout()
                     .queue(SetAttribute(Attribute::Reset))?
-                    .queue(cursor::MoveTo(0, i as u16))?
-                    .queue(if line_order == Ordering::Equal {
-                        Print(format!("{:<4}", relative_ln))
+                    .queue(cursor::MoveTo(self.left as u16, (self.top + i) as u16))?;
+                // the number leaves two columns before the text
+                let width = (self.ln_shift as usize).saturating_sub(2).max(1);
+                self.theme.apply("LineNr")?;
+                if !self.ln_relative && self.ln_shift > 0 {
+                    stdout().queue(Print(format!("{:>width$}", row + 1)))?;
+                } else if self.ln_shift > 0 {
+                    stdout().queue(if line_order == Ordering::Equal {
+                        Print(format!("{:<width$}", relative_ln))
                     } else {
-                        Print(format!("{:4}", relative_ln))
-                    })?
-                    .queue(cursor::MoveTo(LNO_SHIFT, i as u16))?
-                    .queue(Print(rows[row].chars[start..end].to_string()))?;
+                        Print(format!("{:>width$}", relative_ln))
+                    })?;
+                }
+                Theme::reset()?;
+                stdout().queue(cursor::MoveTo(
+                    self.left as u16 + self.ln_shift,
+                    (self.top + i) as u16,
+                ))?;
+                self.draw_row(&rows[row - first].chars, row, start, end, highlights)?;
             }
         }
 
         Ok(())
     }
-    // draw status bar
-    pub fn draw_statusbar(&self, len: usize) -> Result<()> {
-        println!("hello world");
+
+    // prints chars[start..end] of a row, styling the highlighted parts
+    fn draw_row(
+        &self,
+        chars: &str,
+        row: usize,
+        start: usize,
+        end: usize,
+        highlights: &[Highlight],
+    ) -> Result<()> {
+        let mut row_highlights: Vec<&Highlight> = highlights
+            .iter()
+            .filter(|h| h.row == row && h.end > start && h.start < end)
+            .collect();
+        row_highlights.sort_by_key(|h| h.start);
+
+        // the screen column, for expanding tabs
+        let mut screen_col = 0;
+        let mut col = start;
+        for highlight in row_highlights {
+            let from = highlight.start.max(col);
+            let to = highlight.end.min(end);
+            if from >= to || !chars.is_char_boundary(from) || !chars.is_char_boundary(to) {
+                continue;
+            }
+
+            stdout().queue(Print(self.expand(&chars[col..from], &mut screen_col)))?;
+            self.theme.apply(match highlight.kind {
+                HighlightKind::Selection => "Visual",
+                HighlightKind::Search => "Search",
+                HighlightKind::CurrentMatch => "CurSearch",
+            })?;
+            stdout().queue(Print(self.expand(&chars[from..to], &mut screen_col)))?;
+            Theme::reset()?;
+            col = to;
+        }
+        stdout().queue(Print(self.expand(&chars[col..end], &mut screen_col)))?;
+
+        Ok(())
+    }
+    // lists changed lines at the bottom of the text area, for :s previews
+    pub fn draw_preview_pane(&self, lines: &[(usize, String)]) -> Result<()> {
+        let text_height = self.height - 2;
+        let height = lines
+            .len()
+            .min(self.preview_height)
+            .min(text_height.saturating_sub(1));
+        let top = text_height - height - 1;
+
         stdout()
-            .queue(cursor::MoveTo(LNO_SHIFT, self.height as u16 - 1))?
-            .queue(Print(format!("{len} bytes written to the disk")))?;
+            .queue(cursor::MoveTo(0, top as u16))?
+            .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?
+            .queue(SetAttribute(Attribute::Reverse))?
+            .queue(Print(format!(
+                "{:<width$}",
+                format!(" [Preview] {} lines", lines.len()),
+                width = self.width
+            )))?
+            .queue(SetAttribute(Attribute::Reset))?;
+
+        for (i, (row, text)) in lines.iter().take(height).enumerate() {
+            let line = format!("|{:>4}| {}", row + 1, text);
+            let line: String = line.chars().take(self.width).collect();
+
+            stdout()
+                .queue(cursor::MoveTo(0, (top + 1 + i) as u16))?
+                .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?
+                .queue(Print(line))?;
+        }
         Ok(())
     }
 
-    pub fn clear(&self) -> Result<()> {
-        stdout().queue(terminal::Clear(terminal::ClearType::All))?;
+    /* the completion candidates on the line above the status bar, the
+     * selected one reversed and `<` / `>` when there are more to either side */
+    pub fn draw_wildmenu(&self, candidates: &[String], selected: Option<usize>) -> Result<()> {
+        // paths only show their last part
+        let labels: Vec<String> = candidates
+            .iter()
+            .map(|candidate| {
+                let trimmed = candidate.trim_end_matches('/');
+                let name = trimmed.rsplit('/').next().unwrap_or(trimmed);
+                let slash = if candidate.ends_with('/') { "/" } else { "" };
+                format!("{name}{slash}")
+            })
+            .collect();
+
+        // the first label shown, moved along so the selected one fits
+        let width = self.width.saturating_sub(4);
+        let mut first = 0;
+        if let Some(selected) = selected {
+            while labels[first..=selected]
+                .iter()
+                .map(|label| label.chars().count() + 2)
+                .sum::<usize>()
+                > width
+                && first < selected
+            {
+                first += 1;
+            }
+        }
+
+        let top = self.height.saturating_sub(3) as u16;
+        stdout()
+            .queue(cursor::MoveTo(0, top))?
+            .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?
+            .queue(SetAttribute(Attribute::Reverse))?
+            .queue(Print(format!("{:width$}", "", width = self.width)))?
+            .queue(cursor::MoveTo(0, top))?
+            .queue(Print(if first > 0 { "< " } else { "  " }))?;
+
+        let mut used = 0;
+        for (i, label) in labels.iter().enumerate().skip(first) {
+            let len = label.chars().count() + 2;
+            if used + len > width {
+                stdout().queue(Print(">"))?;
+                break;
+            }
+            used += len;
+            if selected == Some(i) {
+                stdout().queue(SetAttribute(Attribute::NoReverse))?;
+                self.theme.apply("WildMenu")?;
+                stdout().queue(Print(label))?;
+                Theme::reset()?;
+                stdout()
+                    .queue(SetAttribute(Attribute::Reverse))?
+                    .queue(Print("  "))?;
+            } else {
+                stdout().queue(Print(format!("{label}  ")))?;
+            }
+        }
+        stdout().queue(SetAttribute(Attribute::Reset))?;
         Ok(())
     }
 
-    pub fn move_to(&mut self, pos: &Position, rowoff: u16, coloff: u16) -> Result<()> {
-        stdout().queue(cursor::MoveTo(pos.x - coloff + LNO_SHIFT, pos.y - rowoff))?;
+    /* the keys that can follow `prefix` in columns above the status bar, under
+     * a title line like the preview pane. What doesn't fit is left out */
+    pub fn draw_which_key(&self, prefix: &str, entries: &[(String, String)]) -> Result<()> {
+        let key_width = entries
+            .iter()
+            .map(|(keys, _)| keys.chars().count())
+            .max()
+            .unwrap_or(0);
+        let entry_width = entries
+            .iter()
+            .map(|(_, description)| key_width + 3 + description.chars().count())
+            .max()
+            .unwrap_or(0)
+            .min(self.width);
+        let columns = (self.width / (entry_width + 2)).max(1);
+        let height = entries
+            .len()
+            .div_ceil(columns)
+            .min((self.height - 2).saturating_sub(1));
+        let top = self.height - 3 - height;
+
+        stdout()
+            .queue(cursor::MoveTo(0, top as u16))?
+            .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?
+            .queue(SetAttribute(Attribute::Reverse))?
+            .queue(Print(format!(
+                "{:<width$}",
+                format!(" [{prefix}] {} keys", entries.len()),
+                width = self.width
+            )))?
+            .queue(SetAttribute(Attribute::Reset))?;
+
+        for line in 0..height {
+            stdout()
+                .queue(cursor::MoveTo(0, (top + 1 + line) as u16))?
+                .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?;
+            // entries go down the columns, like :set all in vim
+            for column in 0..columns {
+                let Some((keys, description)) = entries.get(column * height + line) else {
+                    break;
+                };
+                let description: String = description
+                    .chars()
+                    .take(entry_width.saturating_sub(key_width + 3))
+                    .collect();
+                stdout().queue(cursor::MoveTo(
+                    (column * (entry_width + 2)) as u16,
+                    (top + 1 + line) as u16,
+                ))?;
+                self.theme.apply("WhichKey")?;
+                stdout().queue(Print(format!("{keys:<key_width$}")))?;
+                Theme::reset()?;
+                stdout().queue(Print(format!(" → {description}")))?;
+            }
+        }
         Ok(())
     }
-    // terminal boundary
 
-    pub fn boundary(&self) -> Position {
-        // minus 2 because of the scroll bar at the right side
-        Position {
-            x: self.width as u16 - LNO_SHIFT,
-            y: self.height as u16 - 2,
+    /* the entries of the command palette above the status bar: the name, the
+     * description and on the right the keys that run it. The list scrolls to
+     * keep the selected entry, shown like the selected wildmenu item, in view */
+    pub fn draw_palette(&self, entries: &[&Entry], selected: usize) -> Result<()> {
+        let height = entries
+            .len()
+            .min(PALETTE_HEIGHT)
+            .min((self.height - 2).saturating_sub(1));
+        let top = self.height - 3 - height;
+        let first = (selected + 1).saturating_sub(height);
+        let name_width = entries
+            .iter()
+            .map(|entry| entry.name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .min(self.width / 3);
+
+        stdout()
+            .queue(cursor::MoveTo(0, top as u16))?
+            .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?
+            .queue(SetAttribute(Attribute::Reverse))?
+            .queue(Print(format!(
+                "{:<width$}",
+                format!(" [Palette] {} matches", entries.len()),
+                width = self.width
+            )))?
+            .queue(SetAttribute(Attribute::Reset))?;
+
+        for (i, entry) in entries.iter().enumerate().skip(first).take(height) {
+            // built-in keys are named by what they do and have no description
+            let left = if entry.description.is_empty() {
+                format!(" {}", entry.name)
+            } else {
+                let name: String = entry.name.chars().take(name_width).collect();
+                format!(" {name:<name_width$}  {}", entry.description)
+            };
+            let binding_width = entry.binding.chars().count() + 1;
+            let left: String = left
+                .chars()
+                .take(self.width.saturating_sub(binding_width + 1))
+                .collect();
+            let line = format!(
+                "{left:<width$}{} ",
+                entry.binding,
+                width = self.width.saturating_sub(binding_width)
+            );
+            let line: String = line.chars().take(self.width).collect();
+
+            stdout()
+                .queue(cursor::MoveTo(0, (top + 1 + i - first) as u16))?
+                .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?;
+            if i == selected {
+                self.theme.apply("WildMenu")?;
+            }
+            stdout().queue(Print(line))?;
+            Theme::reset()?;
         }
+        Ok(())
     }
 
-    // Line number display
-    pub fn line_number_display(&self) -> Result<()> {
-        for i in 0..self.height - 1 {
+    // a message of several lines, bottom aligned above the status bar
+    pub fn draw_message_pane(&self, message: &str) -> Result<()> {
+        let text_height = self.height - 2;
+        let lines: Vec<&str> = message.lines().collect();
+        let shown = &lines[lines.len().saturating_sub(text_height + 1)..];
+        let top = self.height - 1 - shown.len();
+
+        for (i, line) in shown.iter().enumerate() {
+            let line: String = line.chars().take(self.width).collect();
             stdout()
-                .queue(SetAttribute(Attribute::Reset))?
-                .queue(Print(format!("{:3} ", i + 1)))?
-                .queue(cursor::MoveTo(0, i as u16))?;
+                .queue(cursor::MoveTo(0, (top + i) as u16))?
+                .queue(terminal::Clear(terminal::ClearType::UntilNewLine))?
+                .queue(Print(line))?;
         }
+        Ok(())
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        stdout().queue(terminal::Clear(terminal::ClearType::All))?;
+        Ok(())
+    }
 
-        // execute!(stdout(), cursor::MoveTo(self.position_x as u16, 0))?;
-        stdout().flush()?;
+    // `line` is the cursor line, tabs before the cursor take more than one column
+    pub fn move_to(&self, pos: &Position, rowoff: u16, coloff: u16, line: &str) -> Result<()> {
+        let x = line
+            .get(coloff as usize..pos.x as usize)
+            .map_or(pos.x.saturating_sub(coloff) as usize, |text| {
+                self.text_width(text)
+            });
+        stdout().queue(cursor::MoveTo(
+            (self.left + x) as u16 + self.ln_shift,
+            (self.top as u16 + pos.y).saturating_sub(rowoff),
+        ))?;
         Ok(())
     }
+    // window boundary
+
+    pub fn boundary(&self) -> Position {
+        // without the line numbers and the status line
+        Position {
+            x: self.view_width as u16 - self.ln_shift,
+            y: self.view_height as u16,
+        }
+    }
 }
@@ -0,0 +1,397 @@
+use super::row::*;
+use regex::{Captures, Regex, RegexBuilder};
+use std::cell::RefCell;
+use text_editor::Position;
+
+// the patterns compiled last, a redraw compiles the same ones again
+const COMPILED_SIZE: usize = 4;
+
+// a pattern with the case and keyword options it was compiled under
+type CompileKey = (String, bool, bool, String);
+
+// where a pattern matched in one version of the text
+#[derive(Debug, Clone)]
+struct Counted {
+    key: CompileKey,
+    version: u64,
+    starts: Vec<(usize, usize)>,
+}
+
+// a single match of the search pattern, [start, end) on a row
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub row: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Search {
+    // the last used pattern, reused by n / N
+    pub pattern: String,
+    pub forward: bool,
+
+    // cleared by :nohlsearch until the next search
+    pub highlight: bool,
+    pub history: Vec<String>,
+    pub history_size: usize,
+
+    pub ignorecase: bool,
+    pub smartcase: bool,
+    // characters that make up a word for * and #, in vim's 'iskeyword' format
+    pub iskeyword: String,
+
+    // the last few patterns compiled, the newest last
+    compiled: RefCell<Vec<(CompileKey, Option<Pattern>)>>,
+    // the last search, counted again once it or the text changes
+    counted: Option<Counted>,
+}
+
+impl Search {
+    pub fn new() -> Self {
+        Search {
+            pattern: String::new(),
+            forward: true,
+            highlight: false,
+            history: Vec::new(),
+            history_size: 200,
+            ignorecase: false,
+            smartcase: false,
+            iskeyword: String::from("@,48-57,_,192-255"),
+            compiled: RefCell::new(Vec::new()),
+            counted: None,
+        }
+    }
+
+    pub fn is_keyword(&self, c: char) -> bool {
+        is_keyword(&self.iskeyword, c)
+    }
+
+    // the keyword under or after `at`, as a [start, end) byte range
+    pub fn word_at(&self, line: &str, at: usize) -> Option<(usize, usize)> {
+        let (start, _) = line
+            .char_indices()
+            .find(|(i, c)| *i + c.len_utf8() > at && self.is_keyword(*c))?;
+
+        let end = line[start..]
+            .char_indices()
+            .find(|(_, c)| !self.is_keyword(*c))
+            .map(|(i, _)| start + i)
+            .unwrap_or(line.len());
+        let start = line[..start]
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| self.is_keyword(*c))
+            .last()
+            .map(|(i, _)| i)
+            .unwrap_or(start);
+
+        Some((start, end))
+    }
+
+    /* the pattern used by * and #, `whole` wraps it in word boundaries where the
+     * word starts or ends with a keyword character */
+    pub fn word_pattern(&self, word: &str, whole: bool) -> String {
+        let is_word = |c: Option<char>| c.is_some_and(|c| self.is_keyword(c));
+        let mut pattern = regex::escape(word);
+
+        if whole && is_word(word.chars().next()) {
+            pattern.insert_str(0, "\\<");
+        }
+        if whole && is_word(word.chars().last()) {
+            pattern.push_str("\\>");
+        }
+        pattern
+    }
+
+    fn compile_key(&self, pattern: &str) -> CompileKey {
+        (
+            pattern.to_string(),
+            self.ignorecase,
+            self.smartcase,
+            self.iskeyword.clone(),
+        )
+    }
+
+    /* compiles `pattern` honouring ignorecase / smartcase, a `\c` or `\C`
+     * anywhere in the pattern forces ignoring or matching case */
+    pub fn compile(&self, pattern: &str) -> Option<Pattern> {
+        if pattern.is_empty() {
+            return None;
+        }
+        let key = self.compile_key(pattern);
+        let mut compiled = self.compiled.borrow_mut();
+        if let Some(i) = compiled.iter().position(|(compiled, _)| *compiled == key) {
+            let entry = compiled.remove(i);
+            compiled.push(entry);
+        } else {
+            compiled.push((key, self.build(pattern)));
+            if compiled.len() > COMPILED_SIZE {
+                compiled.remove(0);
+            }
+        }
+        compiled.last().and_then(|(_, regex)| regex.clone())
+    }
+
+    fn build(&self, pattern: &str) -> Option<Pattern> {
+        let mut forced_case = None;
+        let mut cleaned = String::with_capacity(pattern.len());
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('\\', Some('c')) => {
+                    forced_case = Some(true);
+                    chars.next();
+                }
+                ('\\', Some('C')) => {
+                    forced_case = Some(false);
+                    chars.next();
+                }
+                ('\\', Some(&next)) => {
+                    cleaned.push(c);
+                    cleaned.push(next);
+                    chars.next();
+                }
+                _ => cleaned.push(c),
+            }
+        }
+
+        let ignore_case = forced_case.unwrap_or(
+            self.ignorecase && !(self.smartcase && cleaned.chars().any(|c| c.is_uppercase())),
+        );
+
+        // with an alternative in it they would belong to one side only
+        let mut cleaned = cleaned.as_str();
+        let ends = !cleaned.contains('|');
+        let word_start = ends && cleaned.starts_with("\\<");
+        if word_start {
+            cleaned = &cleaned[2..];
+        }
+        let word_end = ends && cleaned.ends_with("\\>") && !cleaned.ends_with("\\\\>");
+        if word_end {
+            cleaned = &cleaned[..cleaned.len() - 2];
+        }
+
+        let regex = RegexBuilder::new(cleaned)
+            .case_insensitive(ignore_case)
+            .build()
+            .ok()?;
+        Some(Pattern {
+            regex,
+            word_start,
+            word_end,
+            iskeyword: self.iskeyword.clone(),
+        })
+    }
+
+    /* the starts of every match of the last pattern in `rows`, whose text is
+     * at `version` */
+    pub fn match_starts(&mut self, rows: &[Row], version: u64) -> &[(usize, usize)] {
+        let key = self.compile_key(&self.pattern);
+        let fresh = self
+            .counted
+            .as_ref()
+            .is_some_and(|counted| counted.key == key && counted.version == version);
+        if !fresh {
+            let starts = self
+                .compile(&self.pattern)
+                .map(|pattern| {
+                    find_all(&pattern, rows)
+                        .into_iter()
+                        .map(|m| (m.row, m.start))
+                        .collect()
+                })
+                .unwrap_or_default();
+            self.counted = Some(Counted {
+                key,
+                version,
+                starts,
+            });
+        }
+        self.counted
+            .as_ref()
+            .map_or(&[], |counted| counted.starts.as_slice())
+    }
+
+    pub fn remember(&mut self, pattern: &str) {
+        self.pattern = pattern.to_string();
+        self.highlight = true;
+
+        self.history.retain(|entry| entry != pattern);
+        self.history.push(pattern.to_string());
+        let excess = self.history.len().saturating_sub(self.history_size);
+        self.history.drain(..excess);
+    }
+}
+
+/* `@` stands for all letters, numbers are character codes and `a-b` is a
+ * range of either, anything else is taken literally */
+fn is_keyword(iskeyword: &str, c: char) -> bool {
+    let code = |part: &str| -> Option<u32> {
+        match part.parse::<u32>() {
+            Ok(n) => Some(n),
+            Err(_) if part.chars().count() == 1 => part.chars().next().map(|c| c as u32),
+            Err(_) => None,
+        }
+    };
+
+    iskeyword.split(',').any(|part| match part {
+        "" => false,
+        "@" => c.is_alphabetic(),
+        _ => match part.split_once('-') {
+            Some((from, to)) if !from.is_empty() && !to.is_empty() => {
+                match (code(from), code(to)) {
+                    (Some(from), Some(to)) => (from..=to).contains(&(c as u32)),
+                    _ => false,
+                }
+            }
+            _ => code(part) == Some(c as u32),
+        },
+    })
+}
+
+/* a compiled search pattern. The regex crate only knows its own word
+ * characters, so a `\<` that starts the pattern and a `\>` that ends it are
+ * taken off and checked against 'iskeyword' around each match instead */
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    regex: Regex,
+    word_start: bool,
+    word_end: bool,
+    iskeyword: String,
+}
+
+impl Pattern {
+    // [start, end) of `text` starts and ends a word where the pattern asks for it
+    fn at_bounds(&self, text: &str, start: usize, end: usize) -> bool {
+        let keyword = |c: Option<char>| c.is_some_and(|c| is_keyword(&self.iskeyword, c));
+        let before = |at: usize| keyword(text[..at].chars().next_back());
+        let after = |at: usize| keyword(text[at..].chars().next());
+        (!self.word_start || (!before(start) && after(start)))
+            && (!self.word_end || (before(end) && !after(end)))
+    }
+
+    /* the matches of `find` from `from` on, like the regex crate's iterators
+     * do it. One at the wrong bounds is tried again a character further on */
+    fn matches<'t, T: 't>(
+        &'t self,
+        text: &'t str,
+        from: usize,
+        find: impl Fn(&Regex, &'t str, usize) -> Option<T> + 't,
+        span: impl Fn(&T) -> (usize, usize) + 't,
+    ) -> impl Iterator<Item = T> + 't {
+        let next = |at: usize| text[at..].chars().next().map(|c| at + c.len_utf8());
+        let (mut at, mut last) = (Some(from), None);
+        std::iter::from_fn(move || {
+            while let Some(from) = at {
+                let found = find(&self.regex, text, from)?;
+                let (start, end) = span(&found);
+                // no empty match right where the last one ended
+                if (start == end && last == Some(end)) || !self.at_bounds(text, start, end) {
+                    at = next(start);
+                    continue;
+                }
+                at = if start == end { next(end) } else { Some(end) };
+                last = Some(end);
+                return Some(found);
+            }
+            None
+        })
+    }
+
+    pub fn find_iter<'t>(&'t self, text: &'t str) -> impl Iterator<Item = (usize, usize)> + 't {
+        self.matches(
+            text,
+            0,
+            |regex, text, at| regex.find_at(text, at).map(|m| (m.start(), m.end())),
+            |m| *m,
+        )
+    }
+
+    pub fn captures_iter<'t>(&'t self, text: &'t str) -> impl Iterator<Item = Captures<'t>> + 't {
+        self.captures_from(text, 0)
+    }
+
+    // the first match that starts at `at` or after it
+    pub fn captures_at<'t>(&'t self, text: &'t str, at: usize) -> Option<Captures<'t>> {
+        self.captures_from(text, at).next()
+    }
+
+    fn captures_from<'t>(
+        &'t self,
+        text: &'t str,
+        from: usize,
+    ) -> impl Iterator<Item = Captures<'t>> + 't {
+        self.matches(
+            text,
+            from,
+            |regex, text, at| regex.captures_at(text, at),
+            |captures| {
+                let m = captures.get(0).unwrap();
+                (m.start(), m.end())
+            },
+        )
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find_iter(text).next().is_some()
+    }
+}
+
+/* every match of `pattern` in `rows`. Empty ones like `^` count as well,
+ * n and N stop on them too */
+pub fn find_all(pattern: &Pattern, rows: &[Row]) -> Vec<Match> {
+    rows.iter()
+        .enumerate()
+        .flat_map(|(row, r)| {
+            pattern
+                .find_iter(&r.chars)
+                .map(move |(start, end)| Match { row, start, end })
+        })
+        .collect()
+}
+
+/* the next match after (or before) `from`, wrapping around the end of the
+ * buffer. The flag is true when the search wrapped */
+pub fn find_from(
+    pattern: &Pattern,
+    rows: &[Row],
+    from: &Position,
+    forward: bool,
+) -> Option<(Match, bool)> {
+    let starts = |row: usize| -> Vec<Match> {
+        pattern
+            .find_iter(&rows[row].chars)
+            .map(|(start, end)| Match { row, start, end })
+            .collect()
+    };
+    let (y, x) = (from.row(), from.x as usize);
+    if rows.is_empty() {
+        return None;
+    }
+    let y = y.min(rows.len() - 1);
+
+    if forward {
+        let after = starts(y).into_iter().find(|m| m.start > x);
+        if after.is_some() {
+            return after.map(|m| (m, false));
+        }
+        for row in (y + 1..rows.len()).chain(0..=y) {
+            if let Some(m) = starts(row).into_iter().next() {
+                return Some((m, row <= y));
+            }
+        }
+    } else {
+        let before = starts(y).into_iter().rev().find(|m| m.start < x);
+        if before.is_some() {
+            return before.map(|m| (m, false));
+        }
+        for row in (0..y).rev().chain((y..rows.len()).rev()) {
+            if let Some(m) = starts(row).into_iter().next_back() {
+                return Some((m, row >= y));
+            }
+        }
+    }
+
+    None
+}
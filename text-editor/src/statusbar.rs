@@ -2,61 +2,99 @@ use std::io::{stdout, Result};
 
 use crossterm::{
     cursor, queue,
-    style::{Color, Print, SetForegroundColor, Stylize},
+    style::{Color, Print, SetForegroundColor},
 };
 
+use super::{theme::Theme, window::Rect};
+
 #[derive(Debug, Clone)]
 pub struct Statusbar {
-    mode: String,
-    terminal_height: usize,
-    terminal_width: usize,
+    // where the status line of the window being drawn is
+    row: usize,
+    left: usize,
+    width: usize,
+    theme: Theme,
 }
 
 impl Statusbar {
-    pub fn new(mode: String, terminal_height: usize, terminal_width: usize) -> Result<Statusbar> {
+    pub fn new(terminal_height: usize, terminal_width: usize) -> Result<Statusbar> {
         Ok(Statusbar {
-            mode,
-            terminal_width,
-            terminal_height,
+            row: terminal_height - 2,
+            left: 0,
+            width: terminal_width,
+            theme: Theme::new(),
         })
     }
 
-    pub fn paint(&self, mode: String, path: String) -> Result<()> {
-        let styled = mode.magenta();
-        let styled_path = path.blue();
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme = theme.clone();
+    }
+
+    // the last line of `rect` is the status line of its window
+    pub fn set_area(&mut self, rect: Rect) {
+        self.row = rect.top + rect.height.saturating_sub(1);
+        self.left = rect.left;
+        self.width = rect.width;
+    }
+
+    /* prints `text` at `x` of the status line in the colors of `group`, cut
+     * off at the right edge of the window */
+    fn print(&self, x: usize, text: &str, group: &str) -> Result<()> {
+        if x >= self.width {
+            return Ok(());
+        }
+        let text: String = text.chars().take(self.width - x).collect();
+        queue!(
+            stdout(),
+            cursor::MoveTo((self.left + x) as u16, self.row as u16)
+        )?;
+        self.theme.apply(group)?;
+        queue!(stdout(), Print(text))?;
+        Theme::reset()
+    }
+
+    pub fn paint(
+        &self,
+        mode: String,
+        path: String,
+        modified: bool,
+        search_count: Option<(usize, usize)>,
+    ) -> Result<()> {
         let content = String::from("analyser");
 
-        SetForegroundColor(Color::Cyan);
         // SetBackgroundColor(Color::White);
 
-        for i in 0..self.terminal_width {
+        for i in 0..self.width {
             queue!(
                 stdout(),
-                cursor::MoveTo(0, (self.terminal_height - 2) as u16),
+                cursor::MoveTo((self.left + i) as u16, self.row as u16),
                 SetForegroundColor(Color::White),
                 Print(' ')
             )?;
         }
 
-        queue!(
-            stdout(),
-            cursor::MoveTo(2, (self.terminal_height - 2) as u16),
-            Print(styled),
-        )?;
-        queue!(
-            stdout(),
-            cursor::MoveTo(10, (self.terminal_height - 2) as u16),
-            Print(styled_path),
-        )?;
-        queue!(
-            stdout(),
-            cursor::MoveTo(
-                (self.terminal_width - content.len()) as u16,
-                (self.terminal_height - 2) as u16
-            ),
-            Print(content.green()),
+        self.print(2, &mode, "StatusLineMode")?;
+        self.print(10, &path, "StatusLinePath")?;
+        // the buffer has changes that aren't written
+        if modified {
+            self.print(11 + path.chars().count(), "[+]", "StatusLinePath")?;
+        }
+        self.print(
+            self.width.saturating_sub(content.len()),
+            &content,
+            "StatusLineRight",
         )?;
 
+        // [current/total] matches of the last search
+        if let Some((current, total)) = search_count {
+            let counter = format!("[{current}/{total}]");
+            self.print(
+                self.width.saturating_sub(content.len() + counter.len() + 2),
+                &counter,
+                "SearchCount",
+            )?;
+        }
+
         Ok(())
     }
 }
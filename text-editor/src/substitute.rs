@@ -0,0 +1,348 @@
+use super::search::Pattern;
+use regex::Captures;
+
+#[derive(Debug, Clone, Default)]
+pub struct Flags {
+    // g: every match on the line instead of the first one
+    pub global: bool,
+    // c: ask before every replacement
+    pub confirm: bool,
+    // n: only count the matches
+    pub count_only: bool,
+    // i / I: ignore or match case regardless of the settings
+    pub ignore_case: Option<bool>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Substitute {
+    // an empty pattern means the last used search pattern
+    pub pattern: String,
+    pub replacement: String,
+    pub flags: Flags,
+}
+
+impl Substitute {
+    /* parses the part of `:s/pattern/replacement/flags` after the `s`, any
+     * non alphanumeric character can be used instead of `/` */
+    pub fn parse(args: &str) -> std::result::Result<Substitute, String> {
+        let mut chars = args.chars();
+        let delimiter = match chars.next() {
+            Some(c) if !c.is_alphanumeric() && c != '\\' && c != '"' && c != ' ' => c,
+            _ => {
+                return Err(String::from(
+                    "E146: Regular expressions can't be delimited by letters",
+                ))
+            }
+        };
+
+        let mut parts = vec![String::new()];
+        let mut escaped = false;
+        for c in chars {
+            if parts.len() == 3 {
+                parts[2].push(c);
+                continue;
+            }
+            let part = parts.last_mut().unwrap();
+            match c {
+                _ if escaped => {
+                    // the delimiter is unescaped, everything else is kept for the regex / expansion
+                    if c != delimiter {
+                        part.push('\\');
+                    }
+                    part.push(c);
+                    escaped = false;
+                }
+                '\\' => escaped = true,
+                _ if c == delimiter => parts.push(String::new()),
+                _ => part.push(c),
+            }
+        }
+        if escaped {
+            parts.last_mut().unwrap().push('\\');
+        }
+
+        let pattern = parts[0].clone();
+        let replacement = parts.get(1).cloned().unwrap_or_default();
+        let mut flags = Flags::default();
+        for flag in parts.get(2).map(|f| f.trim()).unwrap_or_default().chars() {
+            match flag {
+                'g' => flags.global = !flags.global,
+                'c' => flags.confirm = true,
+                'n' => flags.count_only = true,
+                'i' => flags.ignore_case = Some(true),
+                'I' => flags.ignore_case = Some(false),
+                '&' => {}
+                _ => return Err(format!("E488: Trailing characters: {flag}")),
+            }
+        }
+
+        Ok(Substitute {
+            pattern,
+            replacement,
+            flags,
+        })
+    }
+}
+
+// the state of an interactive `:s///c`
+#[derive(Debug, Clone)]
+pub struct Confirm {
+    pub pattern: Pattern,
+    pub replacement: String,
+    pub global: bool,
+
+    // where the search for the next match continues
+    pub row: usize,
+    pub col: usize,
+    pub last_row: usize,
+
+    pub count: usize,
+    pub lines: usize,
+    // the last row that had a replacement, for counting lines
+    pub changed_row: Option<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CaseChange {
+    None,
+    Upper,
+    Lower,
+}
+
+/* expands the replacement for one match: `&` and `\0` are the whole match,
+ * `\1`..`\9` capture groups, `\u` / `\l` change the case of the next
+ * character, `\U` / `\L` everything up to `\e` or `\E`, `\r` and `\n` break the line */
+pub fn expand(captures: &Captures, replacement: &str) -> String {
+    let mut result = String::new();
+    let mut one_shot = CaseChange::None;
+    let mut lasting = CaseChange::None;
+
+    let push = |result: &mut String, text: &str, one_shot: &mut CaseChange, lasting| {
+        for c in text.chars() {
+            let change = if *one_shot != CaseChange::None {
+                std::mem::replace(one_shot, CaseChange::None)
+            } else {
+                lasting
+            };
+            match change {
+                CaseChange::Upper => result.extend(c.to_uppercase()),
+                CaseChange::Lower => result.extend(c.to_lowercase()),
+                CaseChange::None => result.push(c),
+            }
+        }
+    };
+
+    let group = |n: usize| captures.get(n).map(|m| m.as_str()).unwrap_or_default();
+
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => push(&mut result, group(0), &mut one_shot, lasting),
+            '\\' => match chars.next() {
+                Some(d @ '0'..='9') => push(
+                    &mut result,
+                    group(d.to_digit(10).unwrap() as usize),
+                    &mut one_shot,
+                    lasting,
+                ),
+                Some('u') => one_shot = CaseChange::Upper,
+                Some('l') => one_shot = CaseChange::Lower,
+                Some('U') => lasting = CaseChange::Upper,
+                Some('L') => lasting = CaseChange::Lower,
+                Some('e') | Some('E') => lasting = CaseChange::None,
+                Some('r') | Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some(other) => push(&mut result, &other.to_string(), &mut one_shot, lasting),
+                None => result.push('\\'),
+            },
+            _ => push(&mut result, &c.to_string(), &mut one_shot, lasting),
+        }
+    }
+    result
+}
+
+/* replaces the first (or every, with `global`) match on `line`. Returns the
+ * new line and the number of replacements */
+pub fn substitute_line(
+    pattern: &Pattern,
+    line: &str,
+    replacement: &str,
+    global: bool,
+) -> Option<(String, usize)> {
+    preview_line(pattern, line, replacement, global, "\n")
+        .map(|(result, spans)| (result, spans.len()))
+}
+
+/* like substitute_line, but line breaks in the replacement are shown as
+ * `newline` and the [start, end) spans of the replaced text are returned */
+pub fn preview_line(
+    pattern: &Pattern,
+    line: &str,
+    replacement: &str,
+    global: bool,
+    newline: &str,
+) -> Option<(String, Vec<(usize, usize)>)> {
+    let mut result = String::new();
+    let mut last = 0;
+    let mut spans = Vec::new();
+
+    for captures in pattern.captures_iter(line) {
+        let m = captures.get(0).unwrap();
+        result.push_str(&line[last..m.start()]);
+
+        let start = result.len();
+        result.push_str(&expand(&captures, replacement).replace('\n', newline));
+        spans.push((start, result.len()));
+        last = m.end();
+
+        if !global {
+            break;
+        }
+    }
+
+    if spans.is_empty() {
+        return None;
+    }
+    result.push_str(&line[last..]);
+    Some((result, spans))
+}
+
+// "3 substitutions on 2 lines"
+pub fn report(count: usize, lines: usize, count_only: bool) -> String {
+    let plural = |n: usize, word: &str, words: &str| {
+        if n == 1 {
+            format!("{n} {word}")
+        } else {
+            format!("{n} {words}")
+        }
+    };
+
+    let what = if count_only {
+        plural(count, "match", "matches")
+    } else {
+        plural(count, "substitution", "substitutions")
+    };
+    format!("{what} on {}", plural(lines, "line", "lines"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::Search;
+
+    fn substitute(pattern: &str, line: &str, replacement: &str, global: bool) -> Option<String> {
+        let pattern = Search::new().compile(pattern).unwrap();
+        substitute_line(&pattern, line, replacement, global).map(|(line, _)| line)
+    }
+
+    #[test]
+    fn whole_match() {
+        assert_eq!(
+            substitute("b+", "abbc", "[&]", false).as_deref(),
+            Some("a[bb]c")
+        );
+        assert_eq!(
+            substitute("b", "abc", "\\0\\0", false).as_deref(),
+            Some("abbc")
+        );
+        // an escaped & is a plain one
+        assert_eq!(substitute("b", "abc", "\\&", false).as_deref(), Some("a&c"));
+        assert_eq!(substitute("x", "abc", "&", false), None);
+    }
+
+    #[test]
+    fn groups() {
+        assert_eq!(
+            substitute("(\\w+) (\\w+)", "hello world", "\\2 \\1", false).as_deref(),
+            Some("world hello")
+        );
+        // a group that didn't take part in the match is empty
+        assert_eq!(
+            substitute("a(x)?", "ab", "[\\1]", false).as_deref(),
+            Some("[]b")
+        );
+    }
+
+    #[test]
+    fn case() {
+        assert_eq!(
+            substitute("\\w+", "foo bar", "\\u&", true).as_deref(),
+            Some("Foo Bar")
+        );
+        assert_eq!(
+            substitute("foo", "foo", "\\U&\\E!", false).as_deref(),
+            Some("FOO!")
+        );
+        assert_eq!(
+            substitute("FOO", "FOO", "\\L&", false).as_deref(),
+            Some("foo")
+        );
+        // the one character change wins over the lasting one
+        assert_eq!(
+            substitute("FOO", "FOO", "\\L\\u&", false).as_deref(),
+            Some("Foo")
+        );
+        assert_eq!(
+            substitute("(a)(b)", "ab", "\\U\\1\\e\\2", false).as_deref(),
+            Some("Ab")
+        );
+    }
+
+    #[test]
+    fn line_breaks() {
+        assert_eq!(
+            substitute(",", "a,b", "\\r", false).as_deref(),
+            Some("a\nb")
+        );
+        assert_eq!(
+            substitute(",", "a,b", "\\t", false).as_deref(),
+            Some("a\tb")
+        );
+        let pattern = Search::new().compile(",").unwrap();
+        let preview = preview_line(&pattern, "a,b,c", "\\n", true, "⏎");
+        assert_eq!(preview, Some((String::from("a⏎b⏎c"), vec![(1, 4), (5, 8)])));
+    }
+
+    #[test]
+    fn global() {
+        assert_eq!(substitute("a", "aaa", "b", false).as_deref(), Some("baa"));
+        assert_eq!(substitute("a", "aaa", "b", true).as_deref(), Some("bbb"));
+    }
+
+    #[test]
+    fn parts() {
+        let substitute = Substitute::parse("/a\\/b/c/gi").unwrap();
+        assert_eq!(
+            (substitute.pattern.as_str(), substitute.replacement.as_str()),
+            ("a/b", "c")
+        );
+        assert!(substitute.flags.global);
+        assert_eq!(substitute.flags.ignore_case, Some(true));
+        // other delimiters, with the rest of the parts left out
+        let substitute = Substitute::parse("#x\\.y").unwrap();
+        assert_eq!(
+            (substitute.pattern.as_str(), substitute.replacement.as_str()),
+            ("x\\.y", "")
+        );
+        assert!(!substitute.flags.global);
+        assert_eq!(
+            Substitute::parse("a/b/").unwrap_err(),
+            "E146: Regular expressions can't be delimited by letters"
+        );
+    }
+
+    #[test]
+    fn flags() {
+        let flags = |args: &str| Substitute::parse(args).map(|substitute| substitute.flags);
+        // every g turns it around, a second one takes the first back
+        assert!(!flags("/a/b/gg").unwrap().global);
+        let all = flags("/a/b/&cnI").unwrap();
+        assert!(all.confirm && all.count_only);
+        assert_eq!(all.ignore_case, Some(false));
+        assert!(flags("/a/b/ g ").unwrap().global);
+        assert_eq!(
+            flags("/a/b/gx").unwrap_err(),
+            "E488: Trailing characters: x"
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use crossterm::{
+    style::{Attribute, Color, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor},
+    QueueableCommand,
+};
+use std::{
+    collections::HashMap,
+    io::{stdout, Result},
+};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub attributes: Vec<Attribute>,
+}
+
+impl Style {
+    const fn colors(fg: Color, bg: Color) -> Style {
+        Style {
+            fg: Some(fg),
+            bg: Some(bg),
+            attributes: Vec::new(),
+        }
+    }
+
+    const fn fg(fg: Color) -> Style {
+        Style {
+            fg: Some(fg),
+            bg: None,
+            attributes: Vec::new(),
+        }
+    }
+}
+
+// the highlight groups and what they color
+pub const GROUPS: [&str; 12] = [
+    // the selection in visual mode
+    "Visual",
+    "Search",
+    // the match under the cursor
+    "CurSearch",
+    "WildMenu",
+    "StatusLineMode",
+    "StatusLinePath",
+    "StatusLineRight",
+    // the [n/m] search counter
+    "SearchCount",
+    "LineNr",
+    // the keys in the which-key popup
+    "WhichKey",
+    // the labels of the tab pages, the current one in TabLineSel
+    "TabLine",
+    "TabLineSel",
+];
+
+#[derive(Debug, Clone)]
+pub struct Theme {
+    styles: HashMap<&'static str, Style>,
+}
+
+impl Theme {
+    pub fn new() -> Self {
+        let styles = GROUPS
+            .iter()
+            .map(|group| {
+                let style = match *group {
+                    "Visual" | "TabLine" => Style {
+                        attributes: vec![Attribute::Reverse],
+                        ..Style::default()
+                    },
+                    "TabLineSel" => Style {
+                        attributes: vec![Attribute::Bold],
+                        ..Style::default()
+                    },
+                    "Search" | "WildMenu" => Style::colors(Color::Black, Color::Yellow),
+                    "CurSearch" => Style::colors(Color::Black, Color::Magenta),
+                    "StatusLineMode" => Style::fg(Color::Magenta),
+                    "StatusLinePath" => Style::fg(Color::Blue),
+                    "StatusLineRight" => Style::fg(Color::Green),
+                    "SearchCount" | "WhichKey" => Style::fg(Color::Yellow),
+                    _ => Style::default(),
+                };
+                (*group, style)
+            })
+            .collect();
+        Theme { styles }
+    }
+
+    pub fn style(&self, group: &str) -> Style {
+        self.styles.get(group).cloned().unwrap_or_default()
+    }
+
+    // queues the colors and attributes of `group`, undone by `reset`
+    pub fn apply(&self, group: &str) -> Result<()> {
+        let style = self.style(group);
+        if let Some(fg) = style.fg {
+            stdout().queue(SetForegroundColor(fg))?;
+        }
+        if let Some(bg) = style.bg {
+            stdout().queue(SetBackgroundColor(bg))?;
+        }
+        for attribute in style.attributes {
+            stdout().queue(SetAttribute(attribute))?;
+        }
+        Ok(())
+    }
+
+    pub fn reset() -> Result<()> {
+        stdout()
+            .queue(SetAttribute(Attribute::Reset))?
+            .queue(ResetColor)?;
+        Ok(())
+    }
+
+    // a group as :highlight would set it
+    pub fn show(&self, group: &str) -> String {
+        let style = self.style(group);
+        let mut line = format!("{group:<16}");
+        if let Some(fg) = style.fg {
+            line.push_str(&format!(" fg={}", color_name(fg)));
+        }
+        if let Some(bg) = style.bg {
+            line.push_str(&format!(" bg={}", color_name(bg)));
+        }
+        if !style.attributes.is_empty() {
+            let names: Vec<&str> = style
+                .attributes
+                .iter()
+                .map(|a| attribute_name(*a))
+                .collect();
+            line.push_str(&format!(" style={}", names.join(",")));
+        }
+        line
+    }
+
+    /* :highlight {group} [fg={color}] [bg={color}] [style={attr,..}], vim's
+     * guifg / ctermfg and friends are accepted too. `NONE` clears a part.
+     * Returns the listing when nothing is set */
+    pub fn highlight(&mut self, args: &str) -> std::result::Result<Option<String>, String> {
+        let mut words = args.split_whitespace().peekable();
+        let Some(name) = words.next() else {
+            let lines: Vec<String> = GROUPS.iter().map(|group| self.show(group)).collect();
+            return Ok(Some(lines.join("\n")));
+        };
+        let group = GROUPS
+            .iter()
+            .find(|group| group.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("E411: Highlight group not found: {name}"))?;
+        if words.peek().is_none() {
+            return Ok(Some(self.show(group)));
+        }
+
+        let mut style = self.style(group);
+        for word in words {
+            let (key, value) = word
+                .split_once('=')
+                .ok_or_else(|| format!("E416: Missing equal sign: {word}"))?;
+            match key.to_ascii_lowercase().as_str() {
+                "fg" | "guifg" | "ctermfg" => style.fg = parse_color(value)?,
+                "bg" | "guibg" | "ctermbg" => style.bg = parse_color(value)?,
+                "style" | "gui" | "cterm" => style.attributes = parse_attributes(value)?,
+                _ => return Err(format!("E423: Illegal argument: {word}")),
+            }
+        }
+        self.styles.insert(group, style);
+        Ok(None)
+    }
+}
+
+const COLORS: [(&str, Color); 16] = [
+    ("black", Color::Black),
+    ("darkgrey", Color::DarkGrey),
+    ("red", Color::Red),
+    ("darkred", Color::DarkRed),
+    ("green", Color::Green),
+    ("darkgreen", Color::DarkGreen),
+    ("yellow", Color::Yellow),
+    ("darkyellow", Color::DarkYellow),
+    ("blue", Color::Blue),
+    ("darkblue", Color::DarkBlue),
+    ("magenta", Color::Magenta),
+    ("darkmagenta", Color::DarkMagenta),
+    ("cyan", Color::Cyan),
+    ("darkcyan", Color::DarkCyan),
+    ("white", Color::White),
+    ("grey", Color::Grey),
+];
+
+const ATTRIBUTES: [(&str, Attribute); 6] = [
+    ("bold", Attribute::Bold),
+    ("italic", Attribute::Italic),
+    ("underline", Attribute::Underlined),
+    ("reverse", Attribute::Reverse),
+    ("dim", Attribute::Dim),
+    ("strikethrough", Attribute::CrossedOut),
+];
+
+fn color_name(color: Color) -> String {
+    match color {
+        Color::Rgb { r, g, b } => format!("#{r:02x}{g:02x}{b:02x}"),
+        Color::AnsiValue(n) => n.to_string(),
+        _ => COLORS
+            .iter()
+            .find(|(_, c)| *c == color)
+            .map_or_else(|| String::from("NONE"), |(name, _)| name.to_string()),
+    }
+}
+
+fn attribute_name(attribute: Attribute) -> &'static str {
+    ATTRIBUTES
+        .iter()
+        .find(|(_, a)| *a == attribute)
+        .map_or("NONE", |(name, _)| name)
+}
+
+// a color name, `#rrggbb` or a 256 color number
+pub fn parse_color(text: &str) -> std::result::Result<Option<Color>, String> {
+    let lower = text.to_ascii_lowercase().replace("gray", "grey");
+    if lower == "none" {
+        return Ok(None);
+    }
+    if let Some((_, color)) = COLORS.iter().find(|(name, _)| *name == lower) {
+        return Ok(Some(*color));
+    }
+    if let Some(hex) = lower.strip_prefix('#').filter(|hex| hex.len() == 6) {
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return match (channel(0), channel(2), channel(4)) {
+            (Some(r), Some(g), Some(b)) => Ok(Some(Color::Rgb { r, g, b })),
+            _ => Err(format!("E254: Cannot allocate color {text}")),
+        };
+    }
+    match lower.parse::<u8>() {
+        Ok(n) => Ok(Some(Color::AnsiValue(n))),
+        Err(_) => Err(format!("E421: Color name or number not recognized: {text}")),
+    }
+}
+
+fn parse_attributes(text: &str) -> std::result::Result<Vec<Attribute>, String> {
+    text.split(',')
+        .filter(|name| !name.eq_ignore_ascii_case("none"))
+        .map(|name| {
+            let lower = name.to_ascii_lowercase().replace("inverse", "reverse");
+            ATTRIBUTES
+                .iter()
+                .find(|(n, _)| *n == lower)
+                .map(|(_, attribute)| *attribute)
+                .ok_or_else(|| format!("E418: Illegal value: {name}"))
+        })
+        .collect()
+}
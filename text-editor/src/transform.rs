@@ -0,0 +1,388 @@
+use text_editor::CaseOp;
+
+// changes the case of every character in `text`
+pub fn change_case(text: &str, op: &CaseOp) -> String {
+    match op {
+        CaseOp::Upper => text.to_uppercase(),
+        CaseOp::Lower => text.to_lowercase(),
+        CaseOp::Toggle => text
+            .chars()
+            .map(|c| {
+                if c.is_lowercase() {
+                    c.to_uppercase().collect::<String>()
+                } else {
+                    c.to_lowercase().collect::<String>()
+                }
+            })
+            .collect(),
+    }
+}
+
+/* the flags and the string of the longest 'comments' item `line` starts
+ * with. A `b` one needs a blank after it, `f` ones only start a list item
+ * and the end of a three-part comment isn't followed by more of it */
+fn comment_leader<'a>(line: &str, comments: &'a str) -> Option<(&'a str, &'a str)> {
+    let trimmed = line.trim_start();
+    comments
+        .split(',')
+        .filter_map(|item| item.split_once(':'))
+        .filter(|(flags, leader)| {
+            let blank = trimmed
+                .get(leader.len()..)
+                .is_some_and(|rest| rest.chars().next().is_none_or(char::is_whitespace));
+            !leader.is_empty()
+                && !flags.contains(['e', 'f'])
+                && trimmed.starts_with(*leader)
+                && (!flags.contains('b') || blank)
+        })
+        .max_by_key(|(_, leader)| leader.len())
+}
+
+/* joins `next` onto `line` and returns the joined line together with the
+ * index the cursor should land on (the join point). `comments` is the
+ * buffer's 'comments' option */
+pub fn join_lines(line: &str, next: &str, smart: bool, comments: &str) -> (String, usize) {
+    if !smart {
+        return (format!("{line}{next}"), line.len());
+    }
+
+    let mut next = next.trim_start();
+
+    /* drop the leader of the second line when both are comments of the same
+     * kind, or it is the middle of the three-part comment the first one is in */
+    if let (Some((flags, leader)), Some((next_flags, next_leader))) = (
+        comment_leader(line, comments),
+        comment_leader(next, comments),
+    ) {
+        let middle = next_flags.contains('m') && flags.contains(['s', 'm']);
+        if (leader == next_leader && !flags.contains('s')) || middle {
+            next = next[next_leader.len()..].trim_start();
+        }
+    }
+
+    let line = line.trim_end();
+    let needs_space = !line.is_empty() && !next.is_empty() && !next.starts_with(')');
+
+    let mut joined = String::from(line);
+    if needs_space {
+        joined.push(' ');
+    }
+    let join_at = if needs_space {
+        line.len()
+    } else {
+        line.len().saturating_sub(1)
+    };
+    joined.push_str(next);
+
+    (joined, join_at)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NumberKind {
+    Decimal,
+    Hex,
+    Octal,
+    Binary,
+    Date,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct NumberSpan {
+    kind: NumberKind,
+    start: usize,
+    end: usize,
+}
+
+fn is_digit_of(c: u8, radix: u32) -> bool {
+    (c as char).is_digit(radix)
+}
+
+fn run_end(bytes: &[u8], from: usize, radix: u32) -> usize {
+    let mut end = from;
+    while end < bytes.len() && is_digit_of(bytes[end], radix) {
+        end += 1;
+    }
+    end
+}
+
+// matches YYYY-MM-DD starting at `at`
+fn date_at(bytes: &[u8], at: usize) -> bool {
+    let shape = b"dddd-dd-dd";
+    at + shape.len() <= bytes.len()
+        && shape.iter().enumerate().all(|(i, s)| match s {
+            b'd' => bytes[at + i].is_ascii_digit(),
+            _ => bytes[at + i] == *s,
+        })
+        && (at + shape.len() == bytes.len() || !bytes[at + shape.len()].is_ascii_digit())
+}
+
+// every number on the line, left to right
+fn number_spans(line: &str) -> Vec<NumberSpan> {
+    let bytes = line.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if !bytes[i].is_ascii_digit() {
+            i += 1;
+            continue;
+        }
+
+        let prefixed = |radix_char: u8, radix: u32| {
+            i + 2 < bytes.len()
+                && bytes[i] == b'0'
+                && bytes[i + 1].to_ascii_lowercase() == radix_char
+                && is_digit_of(bytes[i + 2], radix)
+        };
+
+        let span = if date_at(bytes, i) {
+            NumberSpan {
+                kind: NumberKind::Date,
+                start: i,
+                end: i + 10,
+            }
+        } else if prefixed(b'x', 16) {
+            NumberSpan {
+                kind: NumberKind::Hex,
+                start: i,
+                end: run_end(bytes, i + 2, 16),
+            }
+        } else if prefixed(b'b', 2) {
+            NumberSpan {
+                kind: NumberKind::Binary,
+                start: i,
+                end: run_end(bytes, i + 2, 2),
+            }
+        } else if prefixed(b'o', 8) {
+            NumberSpan {
+                kind: NumberKind::Octal,
+                start: i,
+                end: run_end(bytes, i + 2, 8),
+            }
+        } else {
+            let end = run_end(bytes, i, 10);
+            let octal =
+                bytes[i] == b'0' && end - i > 1 && bytes[i..end].iter().all(|b| is_digit_of(*b, 8));
+            let negative = !octal && i > 0 && bytes[i - 1] == b'-';
+            NumberSpan {
+                kind: if octal {
+                    NumberKind::Octal
+                } else {
+                    NumberKind::Decimal
+                },
+                start: if negative { i - 1 } else { i },
+                end,
+            }
+        };
+
+        i = span.end;
+        spans.push(span);
+    }
+    spans
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// adds `delta` to the year, month or day of a YYYY-MM-DD date, depending on `field`
+fn add_to_date(date: &str, field: usize, delta: i64) -> Option<String> {
+    let mut year: i64 = date.get(0..4)?.parse().ok()?;
+    let mut month: i64 = date.get(5..7)?.parse().ok()?;
+    let mut day: i64 = date.get(8..10)?.parse().ok()?;
+    if !(1..=12).contains(&month) {
+        return None;
+    }
+
+    match field {
+        0 => year += delta,
+        1 => {
+            let months = year * 12 + (month - 1) + delta;
+            year = months.div_euclid(12);
+            month = months.rem_euclid(12) + 1;
+        }
+        _ => {
+            day += delta;
+            while day < 1 {
+                month -= 1;
+                if month < 1 {
+                    month = 12;
+                    year -= 1;
+                }
+                day += days_in_month(year, month);
+            }
+            while day > days_in_month(year, month) {
+                day -= days_in_month(year, month);
+                month += 1;
+                if month > 12 {
+                    month = 1;
+                    year += 1;
+                }
+            }
+        }
+    }
+    if !(0..=9999).contains(&year) {
+        return None;
+    }
+    day = day.min(days_in_month(year, month));
+
+    Some(format!("{year:04}-{month:02}-{day:02}"))
+}
+
+fn add_to_number(text: &str, kind: NumberKind, delta: i64) -> Option<String> {
+    let (prefix, digits, radix) = match kind {
+        NumberKind::Hex => (&text[..2], &text[2..], 16),
+        NumberKind::Binary => (&text[..2], &text[2..], 2),
+        NumberKind::Octal if text.len() > 1 && text.as_bytes()[1].is_ascii_alphabetic() => {
+            (&text[..2], &text[2..], 8)
+        }
+        NumberKind::Octal => (&text[..1], &text[1..], 8),
+        _ => {
+            let value: i64 = text.parse().ok()?;
+            return Some(value.checked_add(delta)?.to_string());
+        }
+    };
+
+    // non decimal numbers are unsigned and wrap around like vim does
+    let value = u64::from_str_radix(digits, radix).ok()?;
+    let value = value.wrapping_add(delta as u64);
+    let width = digits.len();
+    let formatted = match radix {
+        16 if digits.chars().any(|c| c.is_ascii_uppercase()) => format!("{value:0width$X}"),
+        16 => format!("{value:0width$x}"),
+        8 => format!("{value:0width$o}"),
+        _ => format!("{value:0width$b}"),
+    };
+
+    Some(format!("{prefix}{formatted}"))
+}
+
+/* increments the number (or date) under or after `at` by `delta`, limited to
+ * `line[at..limit]`. Returns the new line and where the cursor should go */
+pub fn increment_number(
+    line: &str,
+    at: usize,
+    limit: usize,
+    delta: i64,
+) -> Option<(String, usize)> {
+    let span = number_spans(line)
+        .into_iter()
+        .find(|span| span.end > at && span.start < limit)?;
+    let text = &line[span.start..span.end];
+
+    let replacement = if span.kind == NumberKind::Date {
+        // the field under the cursor, the day when the cursor is before the date
+        let field = match at.saturating_sub(span.start) {
+            0..=3 if at >= span.start => 0,
+            4..=6 if at >= span.start => 1,
+            _ => 2,
+        };
+        add_to_date(text, field, delta)?
+    } else {
+        add_to_number(text, span.kind, delta)?
+    };
+
+    let mut result = String::from(&line[..span.start]);
+    result.push_str(&replacement);
+    result.push_str(&line[span.end..]);
+
+    // dates keep the cursor on the field that was changed
+    let cursor = if span.kind == NumberKind::Date && at >= span.start {
+        at
+    } else {
+        span.start + replacement.len() - 1
+    };
+
+    Some((result, cursor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMENTS: &str = "s1:/*,mb:*,ex:*/,://,b:#,fb:-";
+
+    fn join(line: &str, next: &str) -> String {
+        join_lines(line, next, true, COMMENTS).0
+    }
+
+    #[test]
+    fn comment_leaders() {
+        assert_eq!(join("// a", "  // b"), "// a b");
+        assert_eq!(join("/* a", " * b"), "/* a b");
+        assert_eq!(join(" * a", " * b"), " * a b");
+        assert_eq!(join("/* a", " */"), "/* a */");
+        assert_eq!(join("# a", "# b"), "# a b");
+        // `b` leaders need a blank after them, `f` ones start a list item only
+        assert_eq!(join("# a", "#b"), "# a #b");
+        assert_eq!(join("- a", "- b"), "- a - b");
+        assert_eq!(join("x", "// b"), "x // b");
+        assert_eq!(
+            join_lines("a", " b", false, COMMENTS),
+            (String::from("a b"), 1)
+        );
+    }
+
+    // the line after adding `delta` to the number under or after `at`
+    fn add(line: &str, at: usize, delta: i64) -> Option<String> {
+        increment_number(line, at, line.len(), delta).map(|(line, _)| line)
+    }
+
+    #[test]
+    fn decimal() {
+        assert_eq!(
+            increment_number("x 9 y", 0, 5, 1),
+            Some((String::from("x 10 y"), 3))
+        );
+        assert_eq!(add("abc 123", 5, 1).as_deref(), Some("abc 124"));
+        assert_eq!(add("5", 0, -6).as_deref(), Some("-1"));
+        assert_eq!(add("a -1", 0, 1).as_deref(), Some("a 0"));
+        assert_eq!(add("no number", 0, 1), None);
+        // only the part of the line up to `limit`, like a visual selection
+        assert_eq!(increment_number("a 1", 0, 2, 1), None);
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(add("0xff", 0, 1).as_deref(), Some("0x100"));
+        assert_eq!(add("0x00ff", 0, 1).as_deref(), Some("0x0100"));
+        assert_eq!(add("0XFE", 0, 1).as_deref(), Some("0XFF"));
+        assert_eq!(add("0x10", 0, -1).as_deref(), Some("0x0f"));
+    }
+
+    #[test]
+    fn octal() {
+        assert_eq!(add("07", 0, 1).as_deref(), Some("010"));
+        assert_eq!(add("0777", 0, 1).as_deref(), Some("01000"));
+        assert_eq!(add("0o17", 0, 1).as_deref(), Some("0o20"));
+    }
+
+    #[test]
+    fn binary() {
+        assert_eq!(add("0b111", 0, 1).as_deref(), Some("0b1000"));
+        assert_eq!(add("0b0010", 0, -1).as_deref(), Some("0b0001"));
+    }
+
+    #[test]
+    fn dates() {
+        // the day when the cursor is on it or before the date
+        assert_eq!(add("2024-02-28", 8, 1).as_deref(), Some("2024-02-29"));
+        assert_eq!(add("on 2024-02-28", 0, 2).as_deref(), Some("on 2024-03-01"));
+        assert_eq!(add("2023-12-31", 9, 1).as_deref(), Some("2024-01-01"));
+        assert_eq!(add("2024-03-01", 9, -1).as_deref(), Some("2024-02-29"));
+        // a month or a year later keeps the day within the month
+        assert_eq!(add("2024-01-31", 5, 1).as_deref(), Some("2024-02-29"));
+        assert_eq!(add("2024-01-15", 6, -1).as_deref(), Some("2023-12-15"));
+        assert_eq!(add("2024-02-29", 0, 1).as_deref(), Some("2025-02-28"));
+        // the cursor stays on the field that changed
+        assert_eq!(
+            increment_number("2024-01-15", 5, 10, 1).map(|(_, x)| x),
+            Some(5)
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use super::row::Row;
+use std::{
+    collections::VecDeque,
+    ops::Range,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use text_editor::Position;
+
+// the lines `removed` at `at` were replaced by `inserted`
+#[derive(Debug, Clone)]
+struct Edit {
+    at: usize,
+    removed: Vec<String>,
+    inserted: Vec<String>,
+}
+
+/* one undoable change, the edits in the order they were made. `before` and
+ * `after` are the versions of the text on either side of it */
+#[derive(Debug, Clone)]
+struct Change {
+    edits: Vec<Edit>,
+    cursor: Position,
+    before: u64,
+    after: u64,
+}
+
+// versions are never reused, not even by another buffer
+static VERSIONS: AtomicU64 = AtomicU64::new(0);
+
+fn next_version() -> u64 {
+    VERSIONS.fetch_add(1, Ordering::Relaxed)
+}
+
+fn splice(rows: &mut Vec<Row>, at: usize, count: usize, lines: &[String]) {
+    rows.splice(at..at + count, lines.iter().cloned().map(Row::new));
+}
+
+/* undo by the lines each change replaced. Every edit of the text goes through
+ * `splice`, whatever was spliced between two calls of `settle` is one change,
+ * however many edits it took */
+#[derive(Debug, Clone)]
+pub struct UndoHistory {
+    undo: VecDeque<Change>,
+    redo: Vec<Change>,
+    // the edits since the last change
+    pending: Vec<Edit>,
+    // where the next change starts from
+    cursor: Position,
+    /* every edit makes a new version of the text, undo and redo go back to
+     * the one they had. `start` is where the pending edits started from and
+     * `saved` what was last read or written */
+    version: u64,
+    start: u64,
+    saved: u64,
+    // 'undolevels', the oldest changes are dropped beyond it
+    levels: usize,
+}
+
+impl UndoHistory {
+    pub fn new() -> Self {
+        let version = next_version();
+        UndoHistory {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            pending: Vec::new(),
+            cursor: Position::default(),
+            version,
+            start: version,
+            saved: version,
+            levels: 1000,
+        }
+    }
+
+    pub fn set_levels(&mut self, levels: usize) {
+        self.levels = levels;
+        while self.undo.len() > self.levels {
+            self.undo.pop_front();
+        }
+    }
+
+    /* replaces the lines of `range` with `lines`. An edit of lines the last
+     * edit already replaced is folded into it, so typing along a line keeps
+     * a single copy of what it was */
+    pub fn splice(&mut self, rows: &mut Vec<Row>, range: Range<usize>, lines: Vec<String>) {
+        let removed: Vec<String> = rows[range.clone()]
+            .iter()
+            .map(|row| row.chars.clone())
+            .collect();
+        if removed == lines {
+            return;
+        }
+        if self.pending.is_empty() {
+            self.start = self.version;
+        }
+        self.version = next_version();
+
+        if range.len() == lines.len() {
+            // the rows stay, whatever :global marked on them too
+            for (row, line) in rows[range.clone()].iter_mut().zip(&lines) {
+                if row.chars != *line {
+                    row.set_chars(line.clone());
+                }
+            }
+        } else {
+            splice(rows, range.start, range.len(), &lines);
+        }
+
+        if let Some(last) = self.pending.last_mut() {
+            let end = last.at + last.inserted.len();
+            if range.len() == lines.len() && last.at <= range.start && range.end <= end {
+                let at = range.start - last.at;
+                last.inserted.splice(at..at + lines.len(), lines);
+                return;
+            }
+        }
+        self.pending.push(Edit {
+            at: range.start,
+            removed,
+            inserted: lines,
+        });
+    }
+
+    /* called after every key. A change is recorded once `done` is set, an
+     * insert keeps going until it is left */
+    pub fn settle(&mut self, cursor: &Position, done: bool) {
+        if self.pending.is_empty() {
+            self.cursor = cursor.clone();
+            return;
+        }
+        if !done {
+            return;
+        }
+        self.undo.push_back(Change {
+            edits: std::mem::take(&mut self.pending),
+            cursor: self.cursor.clone(),
+            before: self.start,
+            after: self.version,
+        });
+        if self.undo.len() > self.levels {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+        self.cursor = cursor.clone();
+    }
+
+    /* takes back the last change, returns where the cursor was before it.
+     * None when there is nothing to undo */
+    pub fn undo(&mut self, rows: &mut Vec<Row>, cursor: &Position) -> Option<Position> {
+        self.settle(cursor, true);
+        let mut change = self.undo.pop_back()?;
+        for edit in change.edits.iter().rev() {
+            splice(rows, edit.at, edit.inserted.len(), &edit.removed);
+        }
+        self.version = change.before;
+        let position = std::mem::replace(&mut change.cursor, cursor.clone());
+        self.redo.push(change);
+        self.cursor = position.clone();
+        Some(position)
+    }
+
+    pub fn redo(&mut self, rows: &mut Vec<Row>, cursor: &Position) -> Option<Position> {
+        self.settle(cursor, true);
+        let mut change = self.redo.pop()?;
+        for edit in &change.edits {
+            splice(rows, edit.at, edit.removed.len(), &edit.inserted);
+        }
+        self.version = change.after;
+        let position = std::mem::replace(&mut change.cursor, cursor.clone());
+        self.undo.push_back(change);
+        self.cursor = position.clone();
+        Some(position)
+    }
+
+    // what caches of the text compare against, the same version is the same text
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    // the text differs from what was last read or written
+    pub fn modified(&self) -> bool {
+        self.version != self.saved
+    }
+
+    pub fn mark_saved(&mut self) {
+        self.saved = self.version;
+    }
+}
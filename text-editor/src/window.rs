@@ -0,0 +1,343 @@
+use super::options::Local;
+use text_editor::Position;
+
+// a part of the terminal, in cells
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub top: usize,
+    pub left: usize,
+    pub height: usize,
+    pub width: usize,
+}
+
+/* a window that isn't the current one. Like buffers, the current window lives
+ * in the fields of Kass and is swapped with one of these */
+#[derive(Debug, Clone)]
+pub struct Window {
+    pub id: usize,
+    // the number of the buffer it shows
+    pub buffer: usize,
+    pub cursor: Position,
+    pub rowoff: u16,
+    pub coloff: u16,
+    pub options: Local,
+}
+
+/* a tab page that isn't the current one, its layout with the window that
+ * was current in it and the others */
+#[derive(Debug, Clone)]
+pub struct TabPage {
+    pub layout: Layout,
+    pub window: Window,
+    pub windows: Vec<Window>,
+}
+
+impl TabPage {
+    /* closes the windows showing the buffer `number`, false when that leaves
+     * the tab page without a window */
+    pub fn close_buffer(&mut self, number: usize) -> bool {
+        for window in self.windows.iter().filter(|window| window.buffer == number) {
+            self.layout.remove(window.id);
+        }
+        self.windows.retain(|window| window.buffer != number);
+        if self.window.buffer == number {
+            let Some(window) = self.windows.pop() else {
+                return false;
+            };
+            self.layout.remove(self.window.id);
+            self.window = window;
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Left,
+    Down,
+    Up,
+    Right,
+}
+
+// the smallest window, a line of text over its status line, and a column
+const MIN_HEIGHT: usize = 2;
+const MIN_WIDTH: usize = 1;
+
+/* how the windows share the terminal. A split lays its children out side by
+ * side when `vertical` is set and stacked otherwise, each with its size in
+ * that direction. Windows side by side have a separator column between them,
+ * stacked ones are split by their status lines */
+#[derive(Debug, Clone)]
+pub enum Layout {
+    Window(usize),
+    Split {
+        vertical: bool,
+        children: Vec<(Layout, usize)>,
+    },
+}
+
+fn min_size(vertical: bool) -> usize {
+    if vertical {
+        MIN_WIDTH
+    } else {
+        MIN_HEIGHT
+    }
+}
+
+impl Layout {
+    // the window ids, from the top left to the bottom right
+    pub fn windows(&self) -> Vec<usize> {
+        match self {
+            Layout::Window(id) => vec![*id],
+            Layout::Split { children, .. } => children
+                .iter()
+                .flat_map(|(child, _)| child.windows())
+                .collect(),
+        }
+    }
+
+    /* the area of every window in `area`. The last child of a split gets
+     * what the others leave, the sizes can be off after the terminal changed */
+    pub fn rects(&self, area: Rect) -> Vec<(usize, Rect)> {
+        match self {
+            Layout::Window(id) => vec![(*id, area)],
+            Layout::Split { vertical, children } => {
+                let mut rects = Vec::new();
+                let total = if *vertical { area.width } else { area.height };
+                let mut offset = 0;
+                for (i, (child, size)) in children.iter().enumerate() {
+                    let rest = total.saturating_sub(offset);
+                    let size = if i + 1 == children.len() {
+                        rest
+                    } else {
+                        // the ones after it keep their smallest size
+                        let after = children.len() - i - 1;
+                        let reserved = after * (min_size(*vertical) + usize::from(*vertical));
+                        (*size).min(rest.saturating_sub(reserved)).max(1)
+                    };
+                    let rect = if *vertical {
+                        Rect {
+                            left: area.left + offset,
+                            width: size,
+                            ..area
+                        }
+                    } else {
+                        Rect {
+                            top: area.top + offset,
+                            height: size,
+                            ..area
+                        }
+                    };
+                    rects.extend(child.rects(rect));
+                    offset += size + usize::from(*vertical);
+                }
+                rects
+            }
+        }
+    }
+
+    /* puts the window `new` before `id`, the two share the space `id` had.
+     * Err when there isn't room for both */
+    pub fn split(
+        &mut self,
+        id: usize,
+        new: usize,
+        vertical: bool,
+        area: Rect,
+    ) -> Result<(), String> {
+        match self {
+            Layout::Window(window) if *window == id => {
+                let (first, second) = halves(area, vertical)?;
+                *self = Layout::Split {
+                    vertical,
+                    children: vec![(Layout::Window(new), first), (Layout::Window(id), second)],
+                };
+                Ok(())
+            }
+            Layout::Window(_) => Err(String::from("E957: Invalid window number")),
+            Layout::Split {
+                vertical: direction,
+                children,
+            } => {
+                let rects = child_rects(*direction, children, area);
+                let Some(i) = children
+                    .iter()
+                    .position(|(child, _)| child.windows().contains(&id))
+                else {
+                    return Err(String::from("E957: Invalid window number"));
+                };
+                // a split in the same direction gets another child instead of a nested split
+                if matches!(children[i].0, Layout::Window(_)) && *direction == vertical {
+                    let (first, second) = halves(rects[i], vertical)?;
+                    children[i].1 = second;
+                    children.insert(i, (Layout::Window(new), first));
+                    return Ok(());
+                }
+                children[i].0.split(id, new, vertical, rects[i])
+            }
+        }
+    }
+
+    /* takes the window `id` out, its space goes to the child before it or the
+     * one after. A split left with a single child becomes that child */
+    pub fn remove(&mut self, id: usize) {
+        let Layout::Split { vertical, children } = self else {
+            return;
+        };
+        if let Some(i) = children
+            .iter()
+            .position(|(child, _)| matches!(child, Layout::Window(window) if *window == id))
+        {
+            let (_, size) = children.remove(i);
+            let freed = size + usize::from(*vertical);
+            if let Some((_, neighbor)) = children.get_mut(i.saturating_sub(1)) {
+                *neighbor += freed;
+            }
+        } else {
+            for (child, _) in children.iter_mut() {
+                child.remove(id);
+            }
+        }
+        if children.len() == 1 {
+            *self = children.remove(0).0;
+        }
+    }
+
+    /* grows (or shrinks) the window `id` by `delta` in a direction, taking the
+     * room from the child after it, or before it for the last one */
+    pub fn resize(&mut self, id: usize, vertical: bool, delta: isize, area: Rect) -> bool {
+        let Layout::Split {
+            vertical: direction,
+            children,
+        } = self
+        else {
+            return false;
+        };
+        let rects = child_rects(*direction, children, area);
+        let Some(i) = children
+            .iter()
+            .position(|(child, _)| child.windows().contains(&id))
+        else {
+            return false;
+        };
+        // a split further in has the final say
+        if children[i].0.resize(id, vertical, delta, rects[i]) {
+            return true;
+        }
+        if *direction != vertical || children.len() < 2 {
+            return false;
+        }
+
+        let sizes: Vec<usize> = rects
+            .iter()
+            .map(|rect| if vertical { rect.width } else { rect.height })
+            .collect();
+        let other = if i + 1 < children.len() { i + 1 } else { i - 1 };
+        let min = min_size(vertical) as isize;
+        let delta = delta
+            .min(sizes[other] as isize - min)
+            .max(min - sizes[i] as isize);
+        for (j, size) in sizes.iter().enumerate() {
+            children[j].1 = *size;
+        }
+        children[i].1 = (sizes[i] as isize + delta) as usize;
+        children[other].1 = (sizes[other] as isize - delta) as usize;
+        true
+    }
+
+    // Ctrl-w =, every split shares its room evenly
+    pub fn equalize(&mut self, area: Rect) {
+        let Layout::Split { vertical, children } = self else {
+            return;
+        };
+        let separators = if *vertical { children.len() - 1 } else { 0 };
+        let total = if *vertical { area.width } else { area.height } - separators;
+        let count = children.len();
+        for (i, (_, size)) in children.iter_mut().enumerate() {
+            *size = total / count + usize::from(i < total % count);
+        }
+        let rects = child_rects(*vertical, children, area);
+        for ((child, _), rect) in children.iter_mut().zip(rects) {
+            child.equalize(rect);
+        }
+    }
+
+    /* the window next to `id` in `direction`, the one at the cursor's row or
+     * column when there are several */
+    pub fn neighbor(
+        &self,
+        id: usize,
+        direction: Direction,
+        at: (usize, usize),
+        area: Rect,
+    ) -> Option<usize> {
+        let rects = self.rects(area);
+        let (_, current) = rects.iter().find(|(window, _)| *window == id)?;
+        let (row, col) = at;
+        let mut candidates: Vec<&(usize, Rect)> = rects
+            .iter()
+            .filter(|(_, rect)| match direction {
+                Direction::Left => {
+                    rect.left + rect.width + 1 == current.left
+                        && overlaps(rect.top, rect.height, current.top, current.height)
+                }
+                Direction::Right => {
+                    current.left + current.width + 1 == rect.left
+                        && overlaps(rect.top, rect.height, current.top, current.height)
+                }
+                Direction::Up => {
+                    rect.top + rect.height == current.top
+                        && overlaps(rect.left, rect.width, current.left, current.width)
+                }
+                Direction::Down => {
+                    current.top + current.height == rect.top
+                        && overlaps(rect.left, rect.width, current.left, current.width)
+                }
+            })
+            .collect();
+        candidates.sort_by_key(|(_, rect)| match direction {
+            Direction::Left | Direction::Right => {
+                usize::from(!(rect.top..rect.top + rect.height).contains(&row))
+            }
+            Direction::Up | Direction::Down => {
+                usize::from(!(rect.left..rect.left + rect.width).contains(&col))
+            }
+        });
+        candidates.first().map(|(window, _)| *window)
+    }
+}
+
+fn overlaps(start: usize, len: usize, other_start: usize, other_len: usize) -> bool {
+    start < other_start + other_len && other_start < start + len
+}
+
+// the room of a window split in two, the new window gets the first half
+fn halves(area: Rect, vertical: bool) -> Result<(usize, usize), String> {
+    let (size, separator) = if vertical {
+        (area.width, 1)
+    } else {
+        (area.height, 0)
+    };
+    if size < 2 * min_size(vertical) + separator {
+        return Err(String::from("E36: Not enough room"));
+    }
+    let first = (size - separator) / 2;
+    Ok((first, size - separator - first))
+}
+
+// the areas of the children of a split, as Layout::rects lays them out
+fn child_rects(vertical: bool, children: &[(Layout, usize)], area: Rect) -> Vec<Rect> {
+    let placeholder = Layout::Split {
+        vertical,
+        children: children
+            .iter()
+            .enumerate()
+            .map(|(i, (_, size))| (Layout::Window(i), *size))
+            .collect(),
+    };
+    placeholder
+        .rects(area)
+        .into_iter()
+        .map(|(_, rect)| rect)
+        .collect()
+}